@@ -29,4 +29,21 @@ abigen!(
     IERC20,
     "src/abi/IERC20.abi",
     event_derives(serde::Deserialize, serde::Serialize)
-);
\ No newline at end of file
+);
+
+abigen!(
+    IPool,
+    "src/abi/IPool.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+abigen!(
+    IClPool,
+    "src/abi/IClPool.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
+
+abigen!(
+    IStateView,
+    "src/abi/IStateView.abi",
+    event_derives(serde::Deserialize, serde::Serialize)
+);
@@ -0,0 +1,409 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ethers::types::{BlockId, H160, I256, U256};
+use ethers_providers::{Http, Provider};
+use futures_util::future::try_join_all;
+
+use crate::abi::{IClPool, IStateView, MSLauncher};
+use crate::errors::{MathError, QuoteError};
+use crate::pool_catalog::OP_STACK_WETH;
+use crate::price_math::{
+    compute_swap_step, get_sqrt_ratio_at_tick, get_tick_at_sqrt_ratio, FEE_DENOMINATOR, MAX_SQRT_RATIO, MAX_TICK,
+    MIN_SQRT_RATIO, MIN_TICK,
+};
+use crate::types::PoolType;
+
+/// Tick bitmap words loaded on each side of the current tick. A word holds
+/// 256 tick spacings, so this covers a few percent of price on the
+/// narrowest pools.
+pub const DEFAULT_WORDS_AROUND: i16 = 2;
+
+/// The price and liquidity of a concentrated liquidity pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ClPoolState {
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+    /// LP fee, in hundredths of a bip
+    pub fee: u32,
+    /// V4 protocol fee, 12 bits per direction. V3 style pools take it out
+    /// of the LP fee, so it doesn't change their swaps and is zero.
+    pub protocol_fee: u32,
+    pub tick_spacing: i32,
+}
+
+impl ClPoolState {
+    /// The fee swaps pay, in hundredths of a bip.
+    pub fn swap_fee(&self, zero_for_one: bool) -> u32 {
+        let protocol_fee = if zero_for_one { self.protocol_fee & 0xfff } else { self.protocol_fee >> 12 };
+        protocol_fee + self.fee - protocol_fee * self.fee / FEE_DENOMINATOR
+    }
+}
+
+/// What a swap pays into and out of a pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapQuote {
+    /// Paid into the pool if positive, out of it if negative, like pools
+    /// return them
+    pub amount0: I256,
+    pub amount1: I256,
+    /// The state of the pool after the swap
+    pub sqrt_price_x96: U256,
+    pub tick: i32,
+    pub liquidity: u128,
+}
+
+impl SwapQuote {
+    pub fn amount_in(&self, zero_for_one: bool) -> U256 {
+        if zero_for_one { self.amount0.unsigned_abs() } else { self.amount1.unsigned_abs() }
+    }
+
+    pub fn amount_out(&self, zero_for_one: bool) -> U256 {
+        if zero_for_one { self.amount1.unsigned_abs() } else { self.amount0.unsigned_abs() }
+    }
+}
+
+/// The lowest or highest sqrt price a swap can go to.
+pub fn sqrt_price_limit(zero_for_one: bool) -> U256 {
+    if zero_for_one { MIN_SQRT_RATIO + 1 } else { MAX_SQRT_RATIO - 1 }
+}
+
+/// A concentrated liquidity pool at one block with the ticks around its
+/// price, enough to quote swaps like the pool would without the EVM.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ClPoolSnapshot {
+    pub state: ClPoolState,
+    /// `liquidityNet` of the initialized ticks of the loaded words
+    pub ticks: BTreeMap<i32, i128>,
+    /// The first and last tick bitmap words loaded, swaps going past them
+    /// can't be quoted
+    pub words: (i16, i16),
+}
+
+impl ClPoolSnapshot {
+    pub fn new(state: ClPoolState, words: (i16, i16)) -> Self {
+        Self { state, ticks: BTreeMap::new(), words }
+    }
+
+    pub fn with_tick(mut self, tick: i32, liquidity_net: i128) -> Self {
+        self.ticks.insert(tick, liquidity_net);
+        self
+    }
+
+    /// The tick bitmap word holding `tick`.
+    pub fn word_of(&self, tick: i32) -> i16 {
+        (tick.div_euclid(self.state.tick_spacing) >> 8) as i16
+    }
+
+    /// Reads the pool from `source` with `words_around` tick bitmap words on
+    /// each side of the word of the current tick.
+    pub async fn load(source: &impl ClPoolSource, words_around: i16) -> Result<Self, QuoteError> {
+        let state = source.state().await?;
+        if state.tick_spacing <= 0 {
+            return Err(QuoteError::Rpc(format!("invalid tick spacing {}", state.tick_spacing)));
+        }
+
+        let mut snapshot = Self::new(state, (0, 0));
+        let word = snapshot.word_of(state.tick);
+        snapshot.words = (word.saturating_sub(words_around), word.saturating_add(words_around));
+
+        let words: Vec<i16> = (snapshot.words.0..=snapshot.words.1).collect();
+        let bitmaps = try_join_all(words.iter().map(|word| source.tick_bitmap(*word))).await?;
+        let ticks: Vec<i32> = words.iter().zip(bitmaps)
+            .flat_map(|(word, bitmap)| (0..256).filter(move |bit| bitmap.bit(*bit)).map(move |bit| (*word as i32) * 256 + bit as i32))
+            .map(|compressed| compressed * state.tick_spacing)
+            .collect();
+        let liquidity_nets = try_join_all(ticks.iter().map(|tick| source.liquidity_net(*tick))).await?;
+
+        snapshot.ticks = ticks.into_iter().zip(liquidity_nets).collect();
+        Ok(snapshot)
+    }
+
+    /// Quotes a swap like the pool's `swap`. A positive `amount_specified`
+    /// is an exact input, a negative one an exact output.
+    pub fn swap(&self, zero_for_one: bool, amount_specified: I256, sqrt_price_limit: U256) -> Result<SwapQuote, QuoteError> {
+        let state = &self.state;
+        let valid_limit = if zero_for_one {
+            sqrt_price_limit < state.sqrt_price_x96 && sqrt_price_limit > MIN_SQRT_RATIO
+        } else {
+            sqrt_price_limit > state.sqrt_price_x96 && sqrt_price_limit < MAX_SQRT_RATIO
+        };
+        if !valid_limit {
+            return Err(QuoteError::InvalidPriceLimit(sqrt_price_limit));
+        }
+
+        let exact_in = !amount_specified.is_negative();
+        let fee = state.swap_fee(zero_for_one);
+        let mut remaining = amount_specified;
+        let mut calculated = I256::zero();
+        let (mut sqrt_price_x96, mut tick, mut liquidity) = (state.sqrt_price_x96, state.tick, state.liquidity);
+
+        while !remaining.is_zero() && sqrt_price_x96 != sqrt_price_limit {
+            let sqrt_price_start = sqrt_price_x96;
+            let (next_tick, initialized) = self.next_initialized_tick(tick, zero_for_one)?;
+            let next_tick = next_tick.clamp(MIN_TICK, MAX_TICK);
+            let sqrt_price_next = get_sqrt_ratio_at_tick(next_tick)?;
+
+            let past_limit = if zero_for_one { sqrt_price_next < sqrt_price_limit } else { sqrt_price_next > sqrt_price_limit };
+            let target = if past_limit { sqrt_price_limit } else { sqrt_price_next };
+            let step = compute_swap_step(sqrt_price_x96, target, liquidity, remaining, fee)?;
+            sqrt_price_x96 = step.sqrt_price_next;
+
+            let amount_in = I256::from_raw(step.amount_in + step.fee_amount);
+            let amount_out = I256::from_raw(step.amount_out);
+            if exact_in {
+                remaining -= amount_in;
+                calculated -= amount_out;
+            } else {
+                remaining += amount_out;
+                calculated += amount_in;
+            }
+
+            if sqrt_price_x96 == sqrt_price_next {
+                if initialized {
+                    let liquidity_net = self.ticks[&next_tick];
+                    let liquidity_net = if zero_for_one { -liquidity_net } else { liquidity_net };
+                    liquidity = liquidity.checked_add_signed(liquidity_net).ok_or(MathError::Overflow)?;
+                }
+                tick = if zero_for_one { next_tick - 1 } else { next_tick };
+            } else if sqrt_price_x96 != sqrt_price_start {
+                tick = get_tick_at_sqrt_ratio(sqrt_price_x96)?;
+            }
+        }
+
+        let (amount0, amount1) = if zero_for_one == exact_in {
+            (amount_specified - remaining, calculated)
+        } else {
+            (calculated, amount_specified - remaining)
+        };
+        Ok(SwapQuote { amount0, amount1, sqrt_price_x96, tick, liquidity })
+    }
+
+    /// Swaps all of `amount_in` as far as the pool goes.
+    pub fn swap_exact_in(&self, zero_for_one: bool, amount_in: U256) -> Result<SwapQuote, QuoteError> {
+        self.swap(zero_for_one, I256::from_raw(amount_in), sqrt_price_limit(zero_for_one))
+    }
+
+    // Like TickBitmap.nextInitializedTickWithinOneWord, the next initialized
+    // tick at or below `tick`, or above it, in the same word. The word
+    // boundary if there is none.
+    fn next_initialized_tick(&self, tick: i32, lte: bool) -> Result<(i32, bool), QuoteError> {
+        let spacing = self.state.tick_spacing;
+        let compressed = if lte { tick.div_euclid(spacing) } else { tick.div_euclid(spacing) + 1 };
+        let word = (compressed >> 8) as i16;
+        if word < self.words.0 || word > self.words.1 {
+            return Err(QuoteError::TicksNotLoaded(word));
+        }
+
+        let word_start = compressed >> 8 << 8;
+        let found = if lte {
+            self.ticks.range(word_start * spacing..=compressed * spacing).next_back()
+        } else {
+            self.ticks.range(compressed * spacing..=(word_start + 255) * spacing).next()
+        };
+
+        Ok(match found {
+            Some((tick, _)) => (*tick, true),
+            None if lte => (word_start * spacing, false),
+            None => ((word_start + 255) * spacing, false),
+        })
+    }
+}
+
+/// What a snapshot is read from, normally a pool or the V4 StateView.
+#[async_trait]
+pub trait ClPoolSource: Send + Sync {
+    async fn state(&self) -> Result<ClPoolState, QuoteError>;
+    async fn tick_bitmap(&self, word: i16) -> Result<U256, QuoteError>;
+    async fn liquidity_net(&self, tick: i32) -> Result<i128, QuoteError>;
+}
+
+fn rpc(e: impl std::fmt::Display) -> QuoteError {
+    QuoteError::Rpc(e.to_string())
+}
+
+/// Reads a Uniswap V3, PancakeSwap V3 or Aerodrome Slipstream pool over
+/// RPC, at one block.
+pub struct RpcClPoolSource {
+    pool: IClPool<Provider<Http>>,
+    block: BlockId,
+}
+
+impl RpcClPoolSource {
+    pub fn new(pool: H160, client: Arc<Provider<Http>>, block: u64) -> Self {
+        Self { pool: IClPool::new(pool, client), block: block.into() }
+    }
+}
+
+#[async_trait]
+impl ClPoolSource for RpcClPoolSource {
+    async fn state(&self) -> Result<ClPoolState, QuoteError> {
+        let (sqrt_price_x96, tick) = self.pool.slot_0().block(self.block).call().await.map_err(rpc)?;
+        Ok(ClPoolState {
+            sqrt_price_x96,
+            tick,
+            liquidity: self.pool.liquidity().block(self.block).call().await.map_err(rpc)?,
+            fee: self.pool.fee().block(self.block).call().await.map_err(rpc)?,
+            protocol_fee: 0,
+            tick_spacing: self.pool.tick_spacing().block(self.block).call().await.map_err(rpc)?,
+        })
+    }
+
+    async fn tick_bitmap(&self, word: i16) -> Result<U256, QuoteError> {
+        self.pool.tick_bitmap(word).block(self.block).call().await.map_err(rpc)
+    }
+
+    async fn liquidity_net(&self, tick: i32) -> Result<i128, QuoteError> {
+        Ok(self.pool.ticks(tick).block(self.block).call().await.map_err(rpc)?.1)
+    }
+}
+
+/// Reads a pool of the Uniswap V4 PoolManager through its StateView, at one
+/// block.
+pub struct V4StateViewSource {
+    state_view: IStateView<Provider<Http>>,
+    pool_id: [u8; 32],
+    tick_spacing: i32,
+    block: BlockId,
+}
+
+impl V4StateViewSource {
+    pub fn new(state_view: H160, pool_id: [u8; 32], tick_spacing: i32, client: Arc<Provider<Http>>, block: u64) -> Self {
+        Self { state_view: IStateView::new(state_view, client), pool_id, tick_spacing, block: block.into() }
+    }
+}
+
+#[async_trait]
+impl ClPoolSource for V4StateViewSource {
+    async fn state(&self) -> Result<ClPoolState, QuoteError> {
+        let (sqrt_price_x96, tick, protocol_fee, fee) =
+            self.state_view.get_slot_0(self.pool_id).block(self.block).call().await.map_err(rpc)?;
+        Ok(ClPoolState {
+            sqrt_price_x96,
+            tick,
+            liquidity: self.state_view.get_liquidity(self.pool_id).block(self.block).call().await.map_err(rpc)?,
+            fee,
+            protocol_fee,
+            tick_spacing: self.tick_spacing,
+        })
+    }
+
+    async fn tick_bitmap(&self, word: i16) -> Result<U256, QuoteError> {
+        self.state_view.get_tick_bitmap(self.pool_id, word).block(self.block).call().await.map_err(rpc)
+    }
+
+    async fn liquidity_net(&self, tick: i32) -> Result<i128, QuoteError> {
+        Ok(self.state_view.get_tick_liquidity(self.pool_id, tick).block(self.block).call().await.map_err(rpc)?.1)
+    }
+}
+
+/// A trade quoted by [LauncherQuoter], what `simulatePriceAndAmount` and
+/// `simulateTrade` of the simulator return.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TradeQuote {
+    /// The price of the pool after the trade
+    pub sqrt_price_x96: U256,
+    /// Quote tokens swapped in the ms pool
+    pub swap_amount: U256,
+    /// In base tokens, before gas
+    pub profit: I256,
+}
+
+/// Quotes the trades of a launcher from snapshots of its pools and of its
+/// ms pool, in microseconds instead of an EVM run per trade.
+///
+/// A trade of `delta` base tokens is two swaps. Selling the base token,
+/// `delta` is sold into the pool and the quote tokens it returns, the swap
+/// amount, buy base tokens back in the ms pool. Buying it, the swaps are
+/// the other way around. The profit is the base tokens left over. This is
+/// the launcher contract as far as the bot can see it, the simulator stays
+/// the reference: check quotes against it with
+/// [crate::types::verify_trade_list].
+#[derive(Clone, Debug, Default)]
+pub struct LauncherQuoter {
+    /// By launcher pool index, `None` for pools that couldn't be loaded
+    pub pools: Vec<Option<ClPoolSnapshot>>,
+    pub base_is_token0: Vec<bool>,
+    pub ms_pool: ClPoolSnapshot,
+    pub ms_base_is_token0: bool,
+}
+
+impl LauncherQuoter {
+    /// Snapshots the pools of `launcher` at `block`. The ms pool is read as
+    /// a V3 style pool.
+    pub async fn load(client: Arc<Provider<Http>>, launcher: H160, block: u64, words_around: i16) -> Result<Self, QuoteError> {
+        let launcher = MSLauncher::new(launcher, client.clone());
+        let pool_count = launcher.get_pool_count().block(block).call().await.map_err(rpc)?.as_usize();
+        let base = launcher.get_base_token().block(block).call().await.map_err(rpc)?;
+        let quote = launcher.get_quote_token().block(block).call().await.map_err(rpc)?;
+
+        let mut quoter = Self::default();
+        for index in 0..pool_count {
+            let (pool, pool_type) = launcher.get_pool_info(index.into()).block(block).call().await.map_err(rpc)?;
+            let snapshot = match PoolType::try_from(pool_type) {
+                Ok(PoolType::UniswapV4) => {
+                    let source = V4StateViewSource::new(
+                        launcher.v_4_state_view().block(block).call().await.map_err(rpc)?,
+                        launcher.v_4_pool_id().block(block).call().await.map_err(rpc)?,
+                        launcher.v_4_tick_spacing().block(block).call().await.map_err(rpc)?,
+                        client.clone(),
+                        block);
+                    // V4 pools trade native ETH instead of WETH
+                    let currency = |token: H160| if token == OP_STACK_WETH { H160::zero() } else { token };
+                    quoter.base_is_token0.push(currency(base) < currency(quote));
+                    ClPoolSnapshot::load(&source, words_around).await
+                }
+                Ok(_) => {
+                    quoter.base_is_token0.push(base < quote);
+                    ClPoolSnapshot::load(&RpcClPoolSource::new(pool, client.clone(), block), words_around).await
+                }
+                Err(e) => {
+                    quoter.base_is_token0.push(base < quote);
+                    Err(QuoteError::UnsupportedPool { index, reason: e.to_string() })
+                }
+            };
+
+            match snapshot {
+                Ok(snapshot) => quoter.pools.push(Some(snapshot)),
+                Err(e) => {
+                    println!("Pool {} can't be quoted natively: {}", index, e);
+                    quoter.pools.push(None);
+                }
+            }
+        }
+
+        let ms_pool = launcher.ms_pool().block(block).call().await.map_err(rpc)?;
+        let ms_base = launcher.ms_base_token().block(block).call().await.map_err(rpc)?;
+        let ms_quote = launcher.ms_quote_token().block(block).call().await.map_err(rpc)?;
+        quoter.ms_pool = ClPoolSnapshot::load(&RpcClPoolSource::new(ms_pool, client, block), words_around).await?;
+        quoter.ms_base_is_token0 = ms_base < ms_quote;
+
+        Ok(quoter)
+    }
+
+    /// Quotes trading `delta` base tokens on the pool at `pool_index`.
+    pub fn quote(&self, pool_index: usize, sell_base_token: bool, delta: U256) -> Result<TradeQuote, QuoteError> {
+        let pool = self.pools.get(pool_index).and_then(Option::as_ref).ok_or_else(|| QuoteError::UnsupportedPool {
+            index: pool_index,
+            reason: "not loaded".to_string(),
+        })?;
+        let base_is_token0 = self.base_is_token0[pool_index];
+
+        // Selling base is zero for one when base is token0
+        let (swap_amount, base_out, sqrt_price_x96) = if sell_base_token {
+            let sold = pool.swap_exact_in(base_is_token0, delta)?;
+            let swap_amount = sold.amount_out(base_is_token0);
+            let bought = self.ms_pool.swap_exact_in(!self.ms_base_is_token0, swap_amount)?;
+            (swap_amount, bought.amount_out(!self.ms_base_is_token0), sold.sqrt_price_x96)
+        } else {
+            let sold = self.ms_pool.swap_exact_in(self.ms_base_is_token0, delta)?;
+            let swap_amount = sold.amount_out(self.ms_base_is_token0);
+            let bought = pool.swap_exact_in(!base_is_token0, swap_amount)?;
+            (swap_amount, bought.amount_out(!base_is_token0), bought.sqrt_price_x96)
+        };
+
+        Ok(TradeQuote { sqrt_price_x96, swap_amount, profit: I256::from_raw(base_out) - I256::from_raw(delta) })
+    }
+}
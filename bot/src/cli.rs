@@ -0,0 +1,104 @@
+use clap::{Parser, Subcommand};
+use ms_bot::config::Platform;
+
+/// MS launcher bot
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Command,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the strategy for a platform until stopped
+    Run {
+        #[command(flatten)]
+        target: Target,
+
+        /// Override `simulation_mode` from the config (base only, arbitrum always simulates)
+        #[arg(long)]
+        simulation_mode: Option<bool>,
+
+        /// Override `execution_mode` from the config (base and op)
+        #[arg(long)]
+        execution_mode: Option<bool>,
+
+        /// Record raw flashblocks frames to this file, overrides `base.record_path`
+        #[arg(long, conflicts_with = "replay")]
+        record: Option<String>,
+
+        /// Replay flashblocks from a recording instead of connecting to the feed (base only)
+        #[arg(long)]
+        replay: Option<String>,
+
+        /// Replay speed relative to the recording, 0 replays without delays
+        #[arg(long, default_value_t = 1.0, requires = "replay")]
+        replay_speed: f64,
+    },
+
+    /// Simulate a single block and print the resulting trades
+    SimulateOnce {
+        #[command(flatten)]
+        target: Target,
+
+        /// Block to simulate against
+        #[arg(long)]
+        block: u64,
+    },
+
+    /// Validate a config file and print a summary
+    CheckConfig {
+        #[command(flatten)]
+        target: Target,
+    },
+
+    /// Inspect or create the keystore holding the operator key
+    Keystore {
+        #[command(subcommand)]
+        command: KeystoreCommand,
+    },
+}
+
+#[derive(Debug, clap::Args)]
+pub struct Target {
+    /// Platform to run on
+    #[arg(long, short)]
+    pub platform: Platform,
+
+    /// Config file, defaults to `config_<platform>.toml`
+    #[arg(long, short)]
+    pub config: Option<String>,
+}
+
+impl Target {
+    pub fn config_path(&self) -> String {
+        self.config.clone().unwrap_or_else(|| self.platform.default_config_path())
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum KeystoreCommand {
+    /// Decrypt a keystore and print its address
+    Inspect {
+        /// Path of the keystore file
+        path: String,
+    },
+
+    /// Create a new keystore with a random key
+    New {
+        /// Directory to write the keystore to
+        dir: String,
+
+        /// File name of the keystore, defaults to its uuid
+        #[arg(long)]
+        name: Option<String>,
+    },
+}
+
+/// A flashblocks recording to feed the strategy from
+#[derive(Debug)]
+pub struct Replay {
+    pub path: String,
+    pub speed: f64,
+}
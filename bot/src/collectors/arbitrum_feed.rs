@@ -0,0 +1,300 @@
+use alloy_primitives::TxKind;
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ethers::types::{Bytes, Transaction, H160, H256, U256};
+use ethers::utils::rlp::{Decodable, Rlp};
+use revm_trace::SimulationTx;
+use serde::{Deserialize, Serialize};
+
+use crate::collectors::feed_client::{FeedCodec, FeedItem};
+use crate::errors::RelayError;
+
+/// Chain id of Arbitrum One
+pub const ARB1_CHAIN_ID: u64 = 42161;
+/// L2 block produced by the first feed message on Arbitrum One, the blocks
+/// before it were imported from the classic chain
+pub const ARB1_GENESIS_BLOCK: u64 = 22_207_817;
+
+/// L1 message kind of a message carrying an L2 message, the kind of every
+/// message the sequencer itself puts on the feed
+pub const L1_MESSAGE_L2_MESSAGE: u8 = 3;
+
+// L2 message kinds
+const L2_MESSAGE_UNSIGNED_USER_TX: u8 = 0;
+const L2_MESSAGE_CONTRACT_TX: u8 = 1;
+const L2_MESSAGE_BATCH: u8 = 3;
+const L2_MESSAGE_SIGNED_TX: u8 = 4;
+const L2_MESSAGE_HEARTBEAT: u8 = 6;
+
+// Limits nitro puts on L2 messages
+const MAX_L2_MESSAGE_SIZE: usize = 256 * 1024;
+const MAX_BATCH_DEPTH: u32 = 16;
+
+/// Header of a message sequenced on Arbitrum.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct L1MessageHeader {
+    /// L1 message kind, [L1_MESSAGE_L2_MESSAGE] for sequencer transactions
+    pub kind: u8,
+    /// The sequencer for its own messages, the aliased L1 sender otherwise
+    pub sender: H160,
+    /// L1 block the message was sequenced at
+    pub block_number: u64,
+    pub timestamp: u64,
+    /// Set for messages from the delayed inbox
+    pub request_id: Option<H256>,
+    #[serde(rename = "baseFeeL1")]
+    pub base_fee_l1: Option<u128>,
+}
+
+/// A transaction carried by an L2 message.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ArbitrumTransaction {
+    /// A signed transaction, with `from` recovered from the signature
+    Signed(Box<Transaction>),
+    /// A transaction sent from L1 without a signature
+    Unsigned(UnsignedTransaction),
+    /// A signed transaction of a type that isn't decoded, as its raw envelope
+    Unsupported(Bytes),
+}
+
+impl ArbitrumTransaction {
+    /// The call revm replays for this transaction, `None` for the
+    /// transaction types that aren't decoded.
+    pub fn simulation_tx(&self) -> Option<SimulationTx> {
+        let (from, to, value, data) = match self {
+            ArbitrumTransaction::Signed(tx) => (tx.from, tx.to, tx.value, &tx.input),
+            ArbitrumTransaction::Unsigned(tx) => (tx.from, tx.to, tx.value, &tx.data),
+            ArbitrumTransaction::Unsupported(_) => return None,
+        };
+
+        Some(SimulationTx {
+            caller: from.0.into(),
+            transact_to: to.map_or(TxKind::Create, |to| TxKind::Call(to.0.into())),
+            value: alloy_primitives::U256::from_limbs(value.0),
+            data: data.to_vec().into(),
+        })
+    }
+}
+
+/// A transaction an L1 contract sent on behalf of its sender.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct UnsignedTransaction {
+    pub from: H160,
+    /// `None` for a contract creation
+    pub to: Option<H160>,
+    /// Only user transactions have a nonce, contract transactions don't
+    pub nonce: Option<U256>,
+    pub gas_limit: U256,
+    pub max_fee_per_gas: U256,
+    pub value: U256,
+    pub data: Bytes,
+}
+
+/// A message of the Arbitrum sequencer feed with its transactions decoded.
+#[derive(Clone, Debug)]
+pub struct ArbitrumFeedMessage {
+    pub sequence_number: u64,
+    /// L2 block the message produces
+    pub block_number: u64,
+    /// Hash of that block, when the feed sends it
+    pub block_hash: Option<H256>,
+    pub header: L1MessageHeader,
+    pub delayed_messages_read: u64,
+    /// The L2 message as received
+    pub l2_msg: Bytes,
+    /// Transactions of the L2 message, empty for other message kinds
+    pub transactions: Vec<ArbitrumTransaction>,
+}
+
+impl FeedItem for ArbitrumFeedMessage {
+    fn position(&self) -> (u64, u64) {
+        (self.sequence_number, 0)
+    }
+
+    fn block(&self) -> (u64, Option<u64>) {
+        (self.block_number, Some(self.header.timestamp))
+    }
+}
+
+// The JSON the feed broadcasts, see nitro's `BroadcastMessage`
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastMessage {
+    #[serde(default)]
+    messages: Vec<BroadcastFeedMessage>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BroadcastFeedMessage {
+    sequence_number: u64,
+    message: MessageWithMetadata,
+    #[serde(default)]
+    block_hash: Option<H256>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct MessageWithMetadata {
+    message: L1IncomingMessage,
+    delayed_messages_read: u64,
+}
+
+#[derive(Deserialize)]
+struct L1IncomingMessage {
+    header: L1MessageHeader,
+    // Base64, null when empty
+    #[serde(rename = "l2Msg")]
+    l2_msg: Option<String>,
+}
+
+/// Decodes the JSON broadcast messages of an Arbitrum sequencer feed into
+/// [ArbitrumFeedMessage]s.
+#[derive(Clone, Copy, Debug)]
+pub struct ArbitrumCodec {
+    /// L2 block produced by the message with sequence number 0
+    pub genesis_block: u64,
+}
+
+impl ArbitrumCodec {
+    pub fn new(genesis_block: u64) -> Self {
+        Self { genesis_block }
+    }
+
+    /// The codec of the Arbitrum One feed, `wss://arb1.arbitrum.io/feed`.
+    pub fn arb1() -> Self {
+        Self::new(ARB1_GENESIS_BLOCK)
+    }
+}
+
+impl FeedCodec for ArbitrumCodec {
+    type Item = ArbitrumFeedMessage;
+
+    fn decode(&self, frame: &[u8]) -> Result<Vec<ArbitrumFeedMessage>, RelayError> {
+        let broadcast: BroadcastMessage = serde_json::from_slice(frame).map_err(RelayError::InvalidPayload)?;
+
+        let mut messages = Vec::with_capacity(broadcast.messages.len());
+        for feed_message in broadcast.messages {
+            let message = feed_message.message.message;
+            let l2_msg = match &message.l2_msg {
+                Some(encoded) => STANDARD
+                    .decode(encoded)
+                    .map_err(|e| RelayError::InvalidL2Message(format!("bad base64 in message {}: {}", feed_message.sequence_number, e)))?,
+                None => Vec::new(),
+            };
+
+            let transactions = if message.header.kind == L1_MESSAGE_L2_MESSAGE {
+                parse_l2_message(&l2_msg, message.header.sender)?
+            } else {
+                Vec::new()
+            };
+
+            messages.push(ArbitrumFeedMessage {
+                sequence_number: feed_message.sequence_number,
+                block_number: self.genesis_block + feed_message.sequence_number,
+                block_hash: feed_message.block_hash,
+                header: message.header,
+                delayed_messages_read: feed_message.message.delayed_messages_read,
+                l2_msg: l2_msg.into(),
+                transactions,
+            });
+        }
+
+        Ok(messages)
+    }
+}
+
+/// Decodes the transactions of an L2 message sent by `sender`. Batches are
+/// flattened, heartbeats carry no transactions.
+pub fn parse_l2_message(data: &[u8], sender: H160) -> Result<Vec<ArbitrumTransaction>, RelayError> {
+    parse_l2_message_at(data, sender, 0)
+}
+
+fn parse_l2_message_at(data: &[u8], sender: H160, depth: u32) -> Result<Vec<ArbitrumTransaction>, RelayError> {
+    let (&kind, mut rest) = data
+        .split_first()
+        .ok_or_else(|| RelayError::InvalidL2Message("empty message".to_string()))?;
+
+    match kind {
+        L2_MESSAGE_UNSIGNED_USER_TX | L2_MESSAGE_CONTRACT_TX => {
+            let with_nonce = kind == L2_MESSAGE_UNSIGNED_USER_TX;
+            Ok(vec![ArbitrumTransaction::Unsigned(parse_unsigned_tx(rest, sender, with_nonce)?)])
+        }
+        L2_MESSAGE_BATCH => {
+            if depth >= MAX_BATCH_DEPTH {
+                return Err(RelayError::InvalidL2Message(format!("batch nested deeper than {}", MAX_BATCH_DEPTH)));
+            }
+
+            // Segments are prefixed with their length, the batch ends at the
+            // first one that can't be read
+            let mut transactions = Vec::new();
+            while rest.len() >= 8 {
+                let (size, tail) = rest.split_at(8);
+                let size = u64::from_be_bytes(size.try_into().unwrap()) as usize;
+                if size > MAX_L2_MESSAGE_SIZE || size > tail.len() {
+                    break;
+                }
+
+                let (segment, tail) = tail.split_at(size);
+                transactions.extend(parse_l2_message_at(segment, sender, depth + 1)?);
+                rest = tail;
+            }
+
+            Ok(transactions)
+        }
+        L2_MESSAGE_SIGNED_TX => Ok(vec![parse_signed_tx(rest)?]),
+        L2_MESSAGE_HEARTBEAT => Ok(Vec::new()),
+        _ => Err(RelayError::InvalidL2Message(format!("unsupported L2 message kind {}", kind))),
+    }
+}
+
+fn parse_signed_tx(envelope: &[u8]) -> Result<ArbitrumTransaction, RelayError> {
+    let tx_type = *envelope
+        .first()
+        .ok_or_else(|| RelayError::InvalidL2Message("empty transaction".to_string()))?;
+
+    // Legacy transactions are RLP lists, typed ones start with their type
+    if !matches!(tx_type, 0x01 | 0x02 | 0xc0..=0xff) {
+        return Ok(ArbitrumTransaction::Unsupported(envelope.to_vec().into()));
+    }
+
+    let mut tx = Transaction::decode(&Rlp::new(envelope))
+        .map_err(|e| RelayError::InvalidL2Message(format!("bad transaction: {}", e)))?;
+    tx.from = tx
+        .recover_from()
+        .map_err(|e| RelayError::InvalidL2Message(format!("bad signature on {:?}: {}", tx.hash, e)))?;
+
+    Ok(ArbitrumTransaction::Signed(Box::new(tx)))
+}
+
+fn parse_unsigned_tx(mut data: &[u8], sender: H160, with_nonce: bool) -> Result<UnsignedTransaction, RelayError> {
+    let gas_limit = read_word(&mut data)?;
+    let max_fee_per_gas = read_word(&mut data)?;
+    let nonce = if with_nonce { Some(read_word(&mut data)?) } else { None };
+    // The destination is a 32 byte word, zero for a contract creation
+    let mut destination = [0u8; 32];
+    read_word(&mut data)?.to_big_endian(&mut destination);
+    let to = H160::from_slice(&destination[12..]);
+    let value = read_word(&mut data)?;
+
+    Ok(UnsignedTransaction {
+        from: sender,
+        to: (!to.is_zero()).then_some(to),
+        nonce,
+        gas_limit,
+        max_fee_per_gas,
+        value,
+        data: data.to_vec().into(),
+    })
+}
+
+fn read_word(data: &mut &[u8]) -> Result<U256, RelayError> {
+    if data.len() < 32 {
+        return Err(RelayError::InvalidL2Message("unsigned transaction is truncated".to_string()));
+    }
+
+    let (word, rest) = data.split_at(32);
+    *data = rest;
+    Ok(U256::from_big_endian(word))
+}
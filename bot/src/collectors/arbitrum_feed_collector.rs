@@ -0,0 +1,84 @@
+use std::sync::Arc;
+
+use crate::{errors::RelayError, collectors::{arbitrum_feed::{ArbitrumCodec, ArbitrumFeedMessage}, chain_check::ChainCheck, feed_client::{ClientOptions, Heartbeat}, feed_clients::{FeedEndpoint, RelayClients}, reconnect_policy::ReconnectPolicy}, types::{Collector, CollectorStream}};
+use anyhow::Result;
+use async_trait::async_trait;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// A collector that maintains a pool of Arbitrum sequencer feed connections
+/// over one or more endpoints, and generates a stream of
+/// [ArbitrumFeedMessage] events.
+pub struct ArbitrumFeedCollector {
+    endpoints: Vec<FeedEndpoint>,
+    chain_id: u64,
+    codec: ArbitrumCodec,
+    policy: ReconnectPolicy,
+    options: ClientOptions,
+}
+
+impl ArbitrumFeedCollector {
+    /// A collector of the Arbitrum One feed, see [ArbitrumCodec::arb1].
+    pub fn new(endpoints: Vec<FeedEndpoint>, chain_id: u64) -> Self {
+        Self {
+            endpoints,
+            chain_id,
+            codec: ArbitrumCodec::arb1(),
+            policy: ReconnectPolicy::default(),
+            options: ClientOptions::default(),
+        }
+    }
+
+    /// Decodes the feed of another Arbitrum chain.
+    pub fn with_codec(mut self, codec: ArbitrumCodec) -> Self {
+        self.codec = codec;
+        self
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.options.heartbeat = heartbeat;
+        self
+    }
+
+    /// Rejects connections whose feed doesn't match `chain_check`.
+    pub fn with_chain_check(mut self, chain_check: Arc<ChainCheck>) -> Self {
+        self.options.chain_check = Some(chain_check);
+        self
+    }
+}
+
+#[async_trait]
+impl Collector<ArbitrumFeedMessage> for ArbitrumFeedCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, ArbitrumFeedMessage>> {
+        let receiver = start_arbitrum_feed_collector(
+            self.endpoints.clone(), self.chain_id, self.codec, self.policy, self.options.clone()).await?;
+        Ok(Box::pin(UnboundedReceiverStream::new(receiver)))
+    }
+}
+
+pub async fn start_arbitrum_feed_collector(
+    endpoints: Vec<FeedEndpoint>,
+    chain_id: u64,
+    codec: ArbitrumCodec,
+    policy: ReconnectPolicy,
+    options: ClientOptions,
+) ->
+    Result<UnboundedReceiver<ArbitrumFeedMessage>, RelayError>
+{
+    // Create a channel to receive messages from the feed client
+    let (sender, receiver) = unbounded_channel();
+
+    // Create a new relay client and start background maintenance
+    let relay_client = RelayClients::with_codec(
+        &endpoints, chain_id, codec, policy,
+        sender, options)
+        .await?;
+    tokio::spawn(RelayClients::start_reader(relay_client));
+
+    Ok(receiver)
+}
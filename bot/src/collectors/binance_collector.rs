@@ -1,8 +1,11 @@
-
+use crate::types::{Collector, CollectorStream};
+use anyhow::Result;
+use async_trait::async_trait;
 use crypto_ws_client::{BinanceSpotWSClient, WSClient};
 use ethers::types::U256;
 use serde::Deserialize;
-use tokio::sync::RwLock;
+use tokio::sync::{mpsc::unbounded_channel, RwLock};
+use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
 use std::{collections::HashMap, sync::Arc};
 
 #[derive(Debug, Deserialize)]
@@ -16,35 +19,81 @@ pub struct BinanceData {
     pub p: String,
 }
 
-pub async fn start_binance_collector(symbols: Vec<String>) -> Arc<RwLock<HashMap<String, U256>>> {
-    let reader = Arc::new(RwLock::new(HashMap::new()));
-    let reader_clone = reader.clone();
+/// A trade price for a Binance spot symbol, scaled by 1e8.
+#[derive(Debug, Clone)]
+pub struct BinancePrice {
+    pub symbol: String,
+    pub price: U256,
+}
+
+/// A collector that listens to the Binance spot trade stream for a set of
+/// symbols, and generates a stream of [BinancePrice] events.
+pub struct BinanceCollector {
+    symbols: Vec<String>,
+}
 
-    let (sender_ws, receiver_ws) = std::sync::mpsc::channel();
+impl BinanceCollector {
+    pub fn new(symbols: Vec<String>) -> Self {
+        Self { symbols }
+    }
+}
 
-    tokio::spawn(async move {
-        let ws_client = BinanceSpotWSClient::new(sender_ws.clone(), None).await;
-        println!("[MS] Connected to Binance");
+#[async_trait]
+impl Collector<BinancePrice> for BinanceCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, BinancePrice>> {
+        let symbols = self.symbols.clone();
+        let (sender_ws, receiver_ws) = std::sync::mpsc::channel();
+        let (sender, receiver) = unbounded_channel();
 
-        ws_client.subscribe_trade(&symbols).await;
-        println!("[MS] Subscribed to {:?}", symbols);
+        tokio::spawn(async move {
+            let ws_client = BinanceSpotWSClient::new(sender_ws.clone(), None).await;
+            println!("[MS] Connected to Binance");
 
-        ws_client.run().await;
-        ws_client.close().await;
-    });
+            ws_client.subscribe_trade(&symbols).await;
+            println!("[MS] Subscribed to {:?}", symbols);
 
-    tokio::spawn(async move {
-        loop {
+            ws_client.run().await;
+            ws_client.close().await;
+        });
+
+        // The websocket client only speaks std channels, so bridge it on a blocking thread
+        tokio::task::spawn_blocking(move || {
             while let Ok(msg) = receiver_ws.recv() {
-                let binance_message = serde_json::from_str::<BinanceMessage>(&msg).unwrap();
+                let Ok(binance_message) = serde_json::from_str::<BinanceMessage>(&msg) else {
+                    continue;
+                };
+
                 let binance_price = (binance_message.data.p.parse::<f64>().unwrap_or(0.0) * 100000000.0) as u64;
                 if binance_price != 0 {
-                    let mut binance_price_map = reader_clone.write().await;
-                    binance_price_map.insert(binance_message.data.s, U256::from(binance_price));
+                    let price = BinancePrice {
+                        symbol: binance_message.data.s,
+                        price: U256::from(binance_price),
+                    };
+
+                    if sender.send(price).is_err() {
+                        break;
+                    }
                 }
             }
+        });
+
+        Ok(Box::pin(UnboundedReceiverStream::new(receiver)))
+    }
+}
+
+pub async fn start_binance_collector(symbols: Vec<String>) -> Arc<RwLock<HashMap<String, U256>>> {
+    let reader = Arc::new(RwLock::new(HashMap::new()));
+    let reader_clone = reader.clone();
+
+    tokio::spawn(async move {
+        let collector = BinanceCollector::new(symbols);
+        let mut stream = collector.get_event_stream().await.unwrap();
+
+        while let Some(binance_price) = stream.next().await {
+            let mut binance_price_map = reader_clone.write().await;
+            binance_price_map.insert(binance_price.symbol, binance_price.price);
         }
     });
 
     reader
-}
\ No newline at end of file
+}
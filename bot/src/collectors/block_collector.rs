@@ -1,5 +1,9 @@
-use ethers::types::U256;
+use crate::types::{Collector, CollectorStream};
+use anyhow::Result;
+use async_trait::async_trait;
+use ethers::types::{Block, H256, U256};
 use ethers_providers::{Middleware, Provider, Ws};
+use log::*;
 use tokio::sync::RwLock;
 use std::sync::Arc;
 use tokio_stream::StreamExt;
@@ -16,30 +20,81 @@ pub struct BlockInfo {
     pub timestamp: U256,
 }
 
+impl From<&Block<H256>> for BlockInfo {
+    fn from(block: &Block<H256>) -> Self {
+        BlockInfo {
+            number: block.number.unwrap_or_default().as_u64().into(),
+            gas_limit: block.gas_limit,
+            gas_used: block.gas_used,
+            base_fee_per_gas: block.base_fee_per_gas.unwrap_or_default(),
+            next_base_fee: calculate_next_block_base_fee(
+                block.base_fee_per_gas.unwrap_or_default(),
+                block.gas_used,
+                block.gas_limit),
+            timestamp: block.timestamp,
+        }
+    }
+}
+
+/// A collector that listens for new blocks over a websocket subscription,
+/// and generates a stream of [BlockInfo] events. The subscription is
+/// re-established whenever the socket drops.
+pub struct BlockCollector {
+    block_ws_url: String,
+}
+
+impl BlockCollector {
+    pub fn new(block_ws_url: String) -> Self {
+        Self { block_ws_url }
+    }
+}
+
+#[async_trait]
+impl Collector<BlockInfo> for BlockCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, BlockInfo>> {
+        let block_ws_url = self.block_ws_url.clone();
+
+        let stream = async_stream::stream! {
+            loop {
+                let provider = match Provider::<Ws>::connect(block_ws_url.clone()).await {
+                    Ok(provider) => provider,
+                    Err(e) => {
+                        error!("Failed to connect to {}: {}", block_ws_url, e);
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                let mut blocks = match provider.subscribe_blocks().await {
+                    Ok(blocks) => blocks,
+                    Err(e) => {
+                        error!("Failed to subscribe to blocks: {}", e);
+                        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+                        continue;
+                    }
+                };
+
+                while let Some(block) = blocks.next().await {
+                    yield BlockInfo::from(&block);
+                }
+            }
+        };
+
+        Ok(Box::pin(stream))
+    }
+}
+
 pub async fn start_block_collector(block_ws_url: String) -> Arc<RwLock<BlockInfo>>
 {
     let reader = Arc::new(RwLock::new(BlockInfo::default()));
     let reader_clone = reader.clone();
 
     tokio::spawn(async move {
-        loop {
-            let provider = Provider::<Ws>::connect(block_ws_url.clone()).await.unwrap();
-            let mut stream = provider.subscribe_blocks().await.unwrap();
-        
-            while let Some(block) = stream.next().await {
-                let new_block = BlockInfo {
-                    number: block.number.unwrap_or_default().as_u64().into(),
-                    gas_limit: block.gas_limit,
-                    gas_used: block.gas_used,
-                    base_fee_per_gas: block.base_fee_per_gas.unwrap_or_default(),
-                    next_base_fee: calculate_next_block_base_fee(
-                        block.base_fee_per_gas.unwrap_or_default(),
-                        block.gas_used,
-                        block.gas_limit),
-                    timestamp: block.timestamp,
-                };
-                *reader_clone.write().await = new_block;
-            }
+        let collector = BlockCollector::new(block_ws_url);
+        let mut stream = collector.get_event_stream().await.unwrap();
+
+        while let Some(new_block) = stream.next().await {
+            *reader_clone.write().await = new_block;
         }
     });
 
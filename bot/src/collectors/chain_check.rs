@@ -0,0 +1,86 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use async_trait::async_trait;
+use ethers_providers::{Http, Middleware, Provider};
+use log::*;
+
+use crate::errors::RelayError;
+
+/// The view of the chain a feed is checked against, normally the RPC node.
+#[async_trait]
+pub trait ChainHead: Send + Sync {
+    async fn chain_id(&self) -> Result<u64, RelayError>;
+    async fn block_number(&self) -> Result<u64, RelayError>;
+}
+
+#[async_trait]
+impl ChainHead for Provider<Http> {
+    async fn chain_id(&self) -> Result<u64, RelayError> {
+        let chain_id = self.get_chainid().await.map_err(|e| RelayError::Msg(e.to_string()))?;
+        Ok(chain_id.as_u64())
+    }
+
+    async fn block_number(&self) -> Result<u64, RelayError> {
+        let block_number = self.get_block_number().await.map_err(|e| RelayError::Msg(e.to_string()))?;
+        Ok(block_number.as_u64())
+    }
+}
+
+/// Checks that a feed belongs to the chain the bot runs on. Feed messages don't
+/// carry a chain id, so the first message of every connection is compared
+/// with the head of an RPC node that does.
+pub struct ChainCheck {
+    chain_id: u64,
+    head: Box<dyn ChainHead>,
+    /// How far the feed may be from the RPC head, in blocks
+    pub max_block_distance: u64,
+    /// How far a flashblock timestamp may be from the local clock
+    pub max_clock_skew: Duration,
+}
+
+impl ChainCheck {
+    pub fn new(chain_id: u64, head: impl ChainHead + 'static) -> Self {
+        Self {
+            chain_id,
+            head: Box::new(head),
+            max_block_distance: 30,
+            max_clock_skew: Duration::from_secs(60),
+        }
+    }
+
+    pub fn chain_id(&self) -> u64 {
+        self.chain_id
+    }
+
+    /// Checks that the RPC node itself is on the expected chain.
+    pub async fn verify_head(&self) -> Result<(), RelayError> {
+        let chain_id = self.head.chain_id().await?;
+        if chain_id != self.chain_id {
+            error!("RPC is on chain {} instead of {}", chain_id, self.chain_id);
+            return Err(RelayError::InvalidChainId);
+        }
+
+        Ok(())
+    }
+
+    /// Checks the first message of a connection, given as its block number
+    /// and timestamp (see [FeedItem::block](crate::collectors::feed_client::FeedItem::block)),
+    /// against the RPC head.
+    pub async fn verify(&self, (block_number, timestamp): (u64, Option<u64>)) -> Result<(), RelayError> {
+        let head = self.head.block_number().await?;
+        if block_number.abs_diff(head) > self.max_block_distance {
+            warn!("Feed is at block {} while chain {} is at {}", block_number, self.chain_id, head);
+            return Err(RelayError::InvalidChainId);
+        }
+
+        if let Some(timestamp) = timestamp {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            if timestamp.abs_diff(now) > self.max_clock_skew.as_secs() {
+                warn!("Feed block {} has timestamp {}, {}s away from now", block_number, timestamp, timestamp.abs_diff(now));
+                return Err(RelayError::InvalidChainId);
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -1,35 +1,136 @@
 use std::io::Cursor;
+use std::sync::Arc;
+use std::time::Duration;
 
+use crate::collectors::chain_check::ChainCheck;
+use crate::collectors::feed_merger::FeedMerger;
+use crate::collectors::feed_recorder::FeedRecorder;
+use crate::collectors::frame_quarantine::FrameQuarantine;
 use crate::errors::{ConnectionUpdate, RelayError};
 use crate::types::FlashblocksPayloadV1;
 use tokio::sync::mpsc::UnboundedSender;
-use ethers::providers::StreamExt;
+use futures_util::{SinkExt, StreamExt};
 use log::*;
 use tokio::net::TcpStream;
 use tokio::task::JoinHandle;
+use tokio::time::{interval, Instant, MissedTickBehavior};
 use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
 use tungstenite::Message;
 use url::Url;
 
-/// Sequencer Feed Client
-pub struct RelayClient {
+/// A message a feed delivers, see [FeedCodec].
+pub trait FeedItem: Send + 'static {
+    /// Position of the message in the feed, the same on every connection.
+    /// [FeedMerger] drops the copies of a position after the first one.
+    fn position(&self) -> (u64, u64);
+
+    /// L2 block the message belongs to and, when the message carries it,
+    /// the block timestamp. Checked by [ChainCheck].
+    fn block(&self) -> (u64, Option<u64>);
+}
+
+impl FeedItem for FlashblocksPayloadV1 {
+    fn position(&self) -> (u64, u64) {
+        (self.metadata.block_number, self.index)
+    }
+
+    fn block(&self) -> (u64, Option<u64>) {
+        (self.metadata.block_number, self.base.as_ref().map(|base| base.timestamp))
+    }
+}
+
+/// Turns the frames of a feed into messages, so [RelayClient] can carry
+/// feeds other than flashblocks.
+pub trait FeedCodec: Send + Sync + 'static {
+    type Item: FeedItem;
+
+    /// Decodes one frame, which may carry any number of messages.
+    fn decode(&self, frame: &[u8]) -> Result<Vec<Self::Item>, RelayError>;
+}
+
+/// Brotli-compressed OP flashblocks, one per frame, see [decode_frame].
+#[derive(Clone, Copy, Debug, Default)]
+pub struct FlashblocksCodec;
+
+impl FeedCodec for FlashblocksCodec {
+    type Item = FlashblocksPayloadV1;
+
+    fn decode(&self, frame: &[u8]) -> Result<Vec<FlashblocksPayloadV1>, RelayError> {
+        decode_frame(frame).map(|flashblock| vec![flashblock])
+    }
+}
+
+/// How a client checks that its connection is still alive.
+#[derive(Clone, Copy, Debug)]
+pub struct Heartbeat {
+    /// How often to ping the server
+    pub ping_interval: Duration,
+    /// A connection without a data frame for this long is stalled
+    pub stall_timeout: Duration,
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        // Flashblocks arrive every 200ms, a few seconds of silence is not normal
+        Self { ping_interval: Duration::from_secs(1), stall_timeout: Duration::from_secs(5) }
+    }
+}
+
+/// Optional behaviour of a [RelayClient], shared by all clients of a pool.
+#[derive(Clone, Default)]
+pub struct ClientOptions {
+    /// Records raw frames when set
+    pub recorder: Option<FeedRecorder>,
+    /// Drops flashblocks another connection already delivered
+    pub merger: Option<Arc<FeedMerger>>,
+    /// Counts and keeps frames that fail to decode
+    pub quarantine: Option<Arc<FrameQuarantine>>,
+    /// Rejects connections to a feed of another chain
+    pub chain_check: Option<Arc<ChainCheck>>,
+    pub heartbeat: Heartbeat,
+}
+
+/// Sequencer Feed Client, reading flashblocks unless given another codec
+pub struct RelayClient<C: FeedCodec = FlashblocksCodec> {
     // Socket connection to read from
     connection: WebSocketStream<MaybeTlsStream<TcpStream>>,
     // For sending errors / disconnects
     connection_update: UnboundedSender<ConnectionUpdate>,
+    // Decodes the frames
+    codec: Arc<C>,
     // Sends Transactions
-    sender: UnboundedSender<FlashblocksPayloadV1>,
+    sender: UnboundedSender<C::Item>,
     // Relay ID
     id: u32,
+    // Recorder, merger, quarantine, chain check and heartbeat settings
+    options: ClientOptions,
+    // Frames read while verifying the chain and not handled yet
+    pending: Vec<Vec<u8>>,
 }
 
 impl RelayClient {
-    // Does not start the reader, only makes the websocket connection
+    // Does not start the reader, only makes the websocket connection and
+    // checks the feed's chain when `options.chain_check` is set
     pub async fn new(
         url: Url,
         id: u32,
         sender: UnboundedSender<FlashblocksPayloadV1>,
         connection_update: UnboundedSender<ConnectionUpdate>,
+        options: ClientOptions,
+    ) -> Result<Self, RelayError> {
+        Self::with_codec(url, id, Arc::new(FlashblocksCodec), sender, connection_update, options).await
+    }
+}
+
+impl<C: FeedCodec> RelayClient<C> {
+    // Same as [RelayClient::new] for a feed decoded by `codec`
+    pub async fn with_codec(
+        url: Url,
+        id: u32,
+        codec: Arc<C>,
+        sender: UnboundedSender<C::Item>,
+        connection_update: UnboundedSender<ConnectionUpdate>,
+        options: ClientOptions,
     ) -> Result<Self, RelayError> {
         info!("Adding client | Client Id: {}", id);
 
@@ -50,13 +151,62 @@ impl RelayClient {
 
         let (socket, resp) = connect_async(req).await?;
 
-
-        Ok(Self {
+        let mut client = Self {
             connection: socket,
             connection_update,
+            codec,
             sender,
             id,
-        })
+            options,
+            pending: Vec::new(),
+        };
+
+        if let Some(chain_check) = client.options.chain_check.clone() {
+            client.verify_chain(&chain_check).await?;
+        }
+
+        Ok(client)
+    }
+
+    // Reads up to the first message and checks it against `chain_check`
+    async fn verify_chain(&mut self, chain_check: &ChainCheck) -> Result<(), RelayError> {
+        let deadline = Instant::now() + self.options.heartbeat.stall_timeout;
+
+        loop {
+            let msg = tokio::time::timeout_at(deadline, self.connection.next())
+                .await
+                .map_err(|_| RelayError::Msg("No message to verify the chain with".to_string()))?;
+
+            match msg {
+                Some(Ok(msg @ (Message::Binary(_) | Message::Text(_)))) => {
+                    let bytes = msg.into_data();
+                    let items = match self.codec.decode(&bytes) {
+                        Ok(items) => items,
+                        Err(e) => {
+                            if let Some(quarantine) = &self.options.quarantine {
+                                quarantine.add(self.id, &bytes, &e);
+                            }
+                            continue;
+                        }
+                    };
+
+                    // Frames without messages are kept and passed on as well
+                    let first = items.first().map(|item| item.block());
+                    self.pending.push(bytes);
+                    if let Some(block) = first {
+                        return chain_check.verify(block).await;
+                    }
+                }
+                Some(Ok(Message::Ping(data))) => {
+                    let _ = self.connection.send(Message::Pong(data)).await;
+                }
+                Some(Ok(Message::Close(_))) | None => {
+                    return Err(RelayError::Msg("Connection closed before the first message".to_string()));
+                }
+                Some(Err(e)) => return Err(e.into()),
+                _ => {}
+            }
+        }
     }
 
     // Start the reader
@@ -72,23 +222,57 @@ impl RelayClient {
     }
 
     pub async fn run(mut self) -> Result<(), RelayError> {
-        while let Some(msg) = self.connection.next().await {
+        let heartbeat = self.options.heartbeat;
+        let mut ping = interval(heartbeat.ping_interval);
+        ping.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut last_frame = Instant::now();
+
+        for bytes in std::mem::take(&mut self.pending) {
+            if !self.handle_frame(bytes) {
+                return Ok(());
+            }
+        }
+
+        loop {
+            let msg = tokio::select! {
+                msg = self.connection.next() => match msg {
+                    Some(msg) => msg,
+                    None => {
+                        let _ = self.connection_update.send(ConnectionUpdate::Closed(self.id));
+                        warn!("Connection ended | Client Id: {}", self.id);
+                        break;
+                    }
+                },
+                _ = ping.tick() => {
+                    if last_frame.elapsed() > heartbeat.stall_timeout {
+                        let _ = self.connection_update.send(ConnectionUpdate::Stalled(self.id));
+                        warn!("Connection stalled | Client Id: {} | No frames for {:?}", self.id, last_frame.elapsed());
+                        let _ = self.connection.close(None).await;
+                        break;
+                    }
+
+                    // A failed ping shows up as an error on the next read
+                    let _ = self.connection.send(Message::Ping(vec![])).await;
+                    continue;
+                }
+            };
+
             match msg {
-                Ok(Message::Binary(bytes)) => {
-                    // Decode binary message to string first
-                    let mut reader = Cursor::new(bytes);
-                    let mut decompressed = Vec::new();
-                    brotli_decompressor::BrotliDecompress(&mut reader, &mut decompressed)?;
-                
-                    let text = String::from_utf8(decompressed).unwrap();
-                    let flashblock: FlashblocksPayloadV1 = serde_json::from_str(&text).unwrap();
-                    
-                    if self.sender.send(flashblock).is_err() {
+                Ok(msg @ (Message::Binary(_) | Message::Text(_))) => {
+                    last_frame = Instant::now();
+
+                    if !self.handle_frame(msg.into_data()) {
                         break; // we gracefully exit
                     }
-
                 }
-                Ok(Message::Close(_)) => break,
+                Ok(Message::Ping(data)) => {
+                    let _ = self.connection.send(Message::Pong(data)).await;
+                }
+                Ok(Message::Close(frame)) => {
+                    let _ = self.connection_update.send(ConnectionUpdate::Closed(self.id));
+                    warn!("Connection closed by the server | Client Id: {} | {:?}", self.id, frame);
+                    break;
+                }
 
                     // println!("message: {:?}", message);
                     // let decoded_root: Root = match serde_json::from_slice(&message.into_data()) {
@@ -108,4 +292,52 @@ impl RelayClient {
 
         Ok(())
     }
+
+    // Records, decodes and forwards a frame, returns false once the receiver is gone
+    fn handle_frame(&self, bytes: Vec<u8>) -> bool {
+        if let Some(recorder) = &self.options.recorder {
+            recorder.record(self.id, &bytes);
+        }
+
+        // A bad frame is skipped, the connection itself is still fine
+        let items = match self.codec.decode(&bytes) {
+            Ok(items) => items,
+            Err(e) => {
+                warn!("Skipping bad frame | Client Id: {} | {}", self.id, e);
+                if let Some(quarantine) = &self.options.quarantine {
+                    quarantine.add(self.id, &bytes, &e);
+                }
+                return true;
+            }
+        };
+
+        for item in items {
+            if let Some(merger) = &self.options.merger {
+                if !merger.observe(self.id, &item) {
+                    continue;
+                }
+            }
+
+            if self.sender.send(item).is_err() {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Decodes a brotli-compressed flashblocks frame
+pub fn decode_frame(bytes: &[u8]) -> Result<FlashblocksPayloadV1, RelayError> {
+    // Decode binary message to string first
+    let mut reader = Cursor::new(bytes);
+    let mut decompressed = Vec::new();
+    brotli_decompressor::BrotliDecompress(&mut reader, &mut decompressed)
+        .map_err(RelayError::Decompress)?;
+
+    let text = String::from_utf8(decompressed)?;
+    let flashblock: FlashblocksPayloadV1 = serde_json::from_str(&text)
+        .map_err(RelayError::InvalidPayload)?;
+
+    Ok(flashblock)
 }
@@ -1,184 +1,371 @@
 use crate::errors::{ConnectionUpdate, RelayError};
 use crate::collectors::feed_client::*;
+use crate::collectors::feed_merger::FeedMerger;
+use crate::collectors::frame_quarantine::FrameQuarantine;
+use crate::collectors::reconnect_policy::ReconnectPolicy;
 use crate::types::FlashblocksPayloadV1;
 // use crossbeam_channel::{unbounded, Receiver, Sender};
 use log::*;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
 use std::collections::HashMap;
-use std::time::SystemTime;
+use std::sync::Arc;
 use tokio::task::JoinHandle;
+use tokio::time::{Duration, Instant};
 use url::Url;
 
-// For maintaining the sequencer feed clients
-pub struct RelayClients {
-    // All Clients
+// How often the pool is checked for finished clients and due reconnects
+const CHECK_INTERVAL: Duration = Duration::from_millis(100);
+// How often connection stats are logged
+const STATS_INTERVAL: Duration = Duration::from_secs(40);
+// An endpoint with this many failures in a row is failed over from
+const DEGRADED_AFTER_FAILURES: u32 = 3;
+// Client ids carry the index of their endpoint in the upper bits
+const ENDPOINT_ID_SHIFT: u32 = 16;
+
+/// A feed url. Endpoints with the lowest priority value are used
+/// first and share the connections; the others take over when they degrade.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FeedEndpoint {
+    pub url: String,
+    pub priority: u32,
+}
+
+impl FeedEndpoint {
+    pub fn new(url: impl Into<String>, priority: u32) -> Self {
+        Self { url: url.into(), priority }
+    }
+}
+
+/// What the pool currently does with an endpoint.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum EndpointRole {
+    /// Among the best healthy endpoints, grows up to the connection budget
+    Preferred,
+    /// Healthy but a better endpoint is up, no new connections
+    Standby,
+    /// Failing, only probed with `min_connections` to notice recovery
+    Degraded,
+}
+
+// Connections and health of one endpoint
+struct EndpointState {
+    endpoint: FeedEndpoint,
+    url: Url,
+    // Clients connected to this endpoint
     clients: HashMap<u32, JoinHandle<()>>,
+    // Failed connects and dropped connections since the endpoint was last stable
+    failures: u32,
+    // Earliest time to open the next connection
+    next_attempt: Instant,
+    // When the last connection was made, cleared when one drops
+    connected_at: Option<Instant>,
+    connects: u64,
+    disconnects: u64,
+}
+
+impl EndpointState {
+    fn is_degraded(&self) -> bool {
+        self.failures >= DEGRADED_AFTER_FAILURES
+    }
+}
+
+// For maintaining the sequencer feed clients, of flashblocks unless given another codec
+pub struct RelayClients<C: FeedCodec = FlashblocksCodec> {
+    // All endpoints with their clients, best priority first
+    endpoints: Vec<EndpointState>,
     // Connection error receiver
     error_receiver: UnboundedReceiver<ConnectionUpdate>,
     // Connection error sender
     error_sender: UnboundedSender<ConnectionUpdate>,
+    // Decodes the frames of every client
+    codec: Arc<C>,
     // To send transactions
-    sender: UnboundedSender<FlashblocksPayloadV1>,
-    // Max number of relay connections
-    max_connections: u32,
-    // Connection Url
-    url: Url,
+    sender: UnboundedSender<C::Item>,
+    // Backoff and per endpoint connection budget
+    policy: ReconnectPolicy,
     // Chain id
     chain_id: u64,
+    // Shared by all clients, always with a merger and a quarantine
+    options: ClientOptions,
+    // Merges the clients into a single duplicate free stream
+    merger: Arc<FeedMerger>,
+    // Counts and keeps frames that fail to decode
+    quarantine: Arc<FrameQuarantine>,
 }
 
 impl RelayClients {
-    // Does not start the reader, only makes the initial connections
+    // Does not start the reader, only makes the initial `min_connections`
+    // connections, falling back to worse endpoints when better ones fail
     pub async fn new(
-        url: &str,
+        endpoints: &[FeedEndpoint],
         chain_id: u64,
-        max_connections: u32,
-        init_connections: u8,
+        policy: ReconnectPolicy,
         sender: UnboundedSender<FlashblocksPayloadV1>,
+        options: ClientOptions,
     ) -> Result<Self, RelayError> {
-        let url = Url::parse(url)?;
+        Self::with_codec(endpoints, chain_id, FlashblocksCodec, policy, sender, options).await
+    }
+}
 
-        let (updates_sender, updates_receiver) = unbounded_channel();
-        let mut connections: HashMap<u32, JoinHandle<()>> = HashMap::new();
-
-        for id in 0..init_connections {
-            let conn = RelayClient::new(
-                url.clone(),
-                id.into(),
-                sender.clone(),
-                updates_sender.clone(),
-            )
-            .await?;
-
-            connections.insert(id.into(), conn.spawn());
+impl<C: FeedCodec> RelayClients<C> {
+    // Same as [RelayClients::new] for a feed decoded by `codec`
+    pub async fn with_codec(
+        endpoints: &[FeedEndpoint],
+        chain_id: u64,
+        codec: C,
+        policy: ReconnectPolicy,
+        sender: UnboundedSender<C::Item>,
+        mut options: ClientOptions,
+    ) -> Result<Self, RelayError> {
+        if endpoints.is_empty() {
+            return Err(RelayError::Msg("No feed endpoints".to_string()));
         }
 
-        Ok(RelayClients {
-            clients: connections,
+        let mut endpoints = endpoints.to_vec();
+        endpoints.sort_by_key(|e| e.priority);
+        let mut states = Vec::with_capacity(endpoints.len());
+        for endpoint in endpoints {
+            states.push(EndpointState {
+                url: Url::parse(&endpoint.url)?,
+                endpoint,
+                clients: HashMap::new(),
+                failures: 0,
+                next_attempt: Instant::now(),
+                connected_at: None,
+                connects: 0,
+                disconnects: 0,
+            });
+        }
+
+        if let Some(chain_check) = &options.chain_check {
+            if chain_check.chain_id() != chain_id {
+                return Err(RelayError::InvalidChainId);
+            }
+            chain_check.verify_head().await?;
+        }
+
+        let (updates_sender, updates_receiver) = unbounded_channel();
+        let merger = options.merger.get_or_insert_with(|| Arc::new(FeedMerger::new())).clone();
+        let quarantine = options.quarantine.get_or_insert_with(|| Arc::new(FrameQuarantine::new(0))).clone();
+
+        let mut relay_clients = RelayClients {
+            endpoints: states,
             error_receiver: updates_receiver,
             error_sender: updates_sender,
+            codec: Arc::new(codec),
             sender,
-            url,
             chain_id,
-            max_connections,
-        })
+            policy,
+            options,
+            merger,
+            quarantine,
+        };
+
+        let wanted = policy.min_connections.min(policy.max_connections).max(1);
+        let mut last_error = None;
+        for index in 0..relay_clients.endpoints.len() {
+            while relay_clients.total_clients() < wanted {
+                match relay_clients.add_client(index).await {
+                    Ok(()) => {}
+                    Err(e) => {
+                        warn!("Failed to connect | Url: {} | {}", relay_clients.endpoints[index].endpoint.url, e);
+                        last_error = Some(e);
+                        break;
+                    }
+                }
+            }
+        }
+
+        match last_error {
+            Some(e) if relay_clients.total_clients() == 0 => Err(e),
+            _ => Ok(relay_clients),
+        }
     }
 
     // Required to call after making a new instance
     pub async fn start_reader(mut self) {
-        let mut last_connected_time = SystemTime::now();
-        let mut last_disconnected_time = SystemTime::now();
-
-        let mut active_clients: Vec<bool> = vec![true; self.clients.len()];
+        let policy = self.policy;
+        let mut last_stats = Instant::now();
 
-        let mut total_active_clients: u32 = active_clients.len().try_into().unwrap();
-        let mut total_clients = total_active_clients;
-
-        let max_clients = self.max_connections;
-        let mut num_checks = 0;
+        for index in 0..self.endpoints.len() {
+            let active = self.endpoints[index].clients.len() as u32;
+            self.endpoints[index].next_attempt = Instant::now() + policy.growth_delay(active);
+        }
 
         loop {
-            // Wait for 1 second before checking the connections again
-            tokio::time::sleep(tokio::time::Duration::from_secs(1)).await;
-            num_checks += 1;
-
-            if num_checks > 40 {
-                info!(
-                    "Connections AC/TC: {}/{} | ratio: {:.2}",
-                    total_active_clients,
-                    total_clients,
-                    total_active_clients as f32 / total_clients as f32
-                );
-                num_checks = 0;
-            }
-
-            // Check for any updates to the connections
-            match self.error_receiver.try_recv() {
-                Ok(id) => {
-                    let updated_id: u32 = match id {
-                        ConnectionUpdate::StoppedSendingFrames(d) => d,
-                        ConnectionUpdate::Unknown(d) => d,
+            tokio::select! {
+                Some(update) = self.error_receiver.recv() => {
+                    let (id, reason) = match update {
+                        ConnectionUpdate::StoppedSendingFrames(d) => (d, "stopped sending frames"),
+                        ConnectionUpdate::Stalled(d) => (d, "stalled"),
+                        ConnectionUpdate::Closed(d) => (d, "closed"),
+                        ConnectionUpdate::Unknown(d) => (d, "unknown"),
                     };
 
-                    {
-                        let bool = match active_clients.get_mut(updated_id as usize) {
-                            Some(d) => d,
-                            None => panic!("This is not supposed to happen"),
-                        };
+                    if let Some(state) = self.endpoints.get_mut((id >> ENDPOINT_ID_SHIFT) as usize) {
+                        state.clients.remove(&id);
+                        state.failures += 1;
+                        state.disconnects += 1;
+                        state.connected_at = None;
+                        // Replace it after the backoff of the current failure streak
+                        state.next_attempt = Instant::now() + policy.backoff(state.failures);
+                        warn!("Client disconnected | Client Id: {} | Url: {} | Reason: {}", id, state.endpoint.url, reason);
+                    }
+                    continue;
+                }
+                _ = tokio::time::sleep(CHECK_INTERVAL) => {}
+            }
+
+            for state in self.endpoints.iter_mut() {
+                // Clients report how their connection ended, only those that
+                // stopped because the receiver is gone end without an update
+                state.clients.retain(|_, handle| !handle.is_finished());
 
-                        *bool = false;
-                        total_active_clients -= 1;
+                // An endpoint that held its connections for a while is healthy again
+                let stable = state.connected_at.is_some_and(|t| t.elapsed() >= policy.max_backoff);
+                if state.failures > 0 && stable && !state.clients.is_empty() {
+                    if state.is_degraded() {
+                        info!("Endpoint recovered | Url: {}", state.endpoint.url);
                     }
+                    state.failures = 0;
+                }
+            }
 
-                    last_disconnected_time = SystemTime::now();
-                    warn!("Client disconnected | Client Id: {}", updated_id);
+            if last_stats.elapsed() >= STATS_INTERVAL {
+                self.log_stats();
+                last_stats = Instant::now();
+            }
+
+            for index in 0..self.endpoints.len() {
+                let role = self.role(index);
+                let state = &self.endpoints[index];
+                let active = state.clients.len() as u32;
+
+                if active >= self.target(role) || Instant::now() < state.next_attempt {
+                    continue;
                 }
-                Err(_) => {
-                    // No message was received, check the connections
-                    let now = SystemTime::now();
-                    let elapsed_connected =
-                        now.duration_since(last_connected_time).unwrap().as_secs();
-                    let elapsed_disconnect = now
-                        .duration_since(last_disconnected_time)
-                        .unwrap()
-                        .as_secs();
-
-                    let mut up_coming_connection = None;
-                    // Iterate over all connections
-                    for (id, is_connected) in active_clients.iter_mut().enumerate() {
-                        if !*is_connected && elapsed_connected > 70 && elapsed_disconnect > 70 {
-                            // This connection is not connected and it's been more than 70 seconds since the last connect / disconnect event
-                            up_coming_connection = Some(id);
-                            // Adding the connection
-                            if self.add_or_replace_client(id as u32).await.is_err() {
-                                last_disconnected_time = SystemTime::now();
-                                error!("Failed to add client");
-                                break;
-                            }
-                            *is_connected = true;
-
-                            total_active_clients += 1;
-                            last_connected_time = now;
-                            break;
-                        }
+
+                match self.add_client(index).await {
+                    Ok(()) => {
+                        let state = &mut self.endpoints[index];
+                        state.next_attempt = Instant::now() + policy.growth_delay(active + 1);
                     }
+                    Err(e) => {
+                        let state = &mut self.endpoints[index];
+                        let delay = policy.backoff(state.failures);
+                        state.next_attempt = Instant::now() + delay;
+                        error!("Failed to add client | Url: {} | Retrying in {:?} | {}", state.endpoint.url, delay, e);
 
-                    // If all connections are active and max connections is less than current "total connections", we add
-                    if up_coming_connection.is_none()
-                        && elapsed_connected > 70
-                        && elapsed_disconnect > 70
-                        && max_clients > total_clients
-                    {
-                        // A new connection needs to be created
-                        if self.add_or_replace_client(total_clients).await.is_err() {
-                            last_disconnected_time = SystemTime::now();
-                            error!("Failed to add client");
-                            continue;
+                        if state.failures == DEGRADED_AFTER_FAILURES {
+                            warn!("Endpoint degraded, failing over | Url: {}", state.endpoint.url);
                         }
-
-                        active_clients.push(true);
-                        total_active_clients += 1;
-                        total_clients += 1;
-                        last_connected_time = SystemTime::now();
                     }
                 }
             }
+
+            let total = self.total_clients();
+            if total < policy.min_connections {
+                warn!("Below minimum connections | {}/{}", total, policy.min_connections);
+            }
+        }
+    }
+
+    /// Role of the endpoint at `index`, see [EndpointRole].
+    fn role(&self, index: usize) -> EndpointRole {
+        let state = &self.endpoints[index];
+        if state.is_degraded() {
+            return EndpointRole::Degraded;
+        }
+
+        let best = self.endpoints.iter()
+            .filter(|s| !s.is_degraded())
+            .map(|s| s.endpoint.priority)
+            .min();
+
+        if best == Some(state.endpoint.priority) {
+            EndpointRole::Preferred
+        } else {
+            EndpointRole::Standby
         }
     }
 
-    // Adds a new client connection
-    async fn add_or_replace_client(&mut self, id: u32) -> Result<(), RelayError> {
-        let client = RelayClient::new(
-            self.url.clone(),
+    fn target(&self, role: EndpointRole) -> u32 {
+        match role {
+            EndpointRole::Preferred => self.policy.max_connections,
+            EndpointRole::Standby => 0,
+            EndpointRole::Degraded => self.policy.min_connections.clamp(1, self.policy.max_connections),
+        }
+    }
+
+    fn total_clients(&self) -> u32 {
+        self.endpoints.iter().map(|s| s.clients.len() as u32).sum()
+    }
+
+    fn log_stats(&self) {
+        let stats = self.merger.stats();
+        info!("Feed pool | Chain Id: {} | connections: {}", self.chain_id, self.total_clients());
+
+        for (index, state) in self.endpoints.iter().enumerate() {
+            let role = self.role(index);
+            let (wins, duplicates) = stats.iter()
+                .filter(|(id, _)| (id >> ENDPOINT_ID_SHIFT) as usize == index)
+                .fold((0, 0), |(w, d), (_, s)| (w + s.wins, d + s.duplicates));
+
+            info!(
+                "Endpoint {} | priority: {} | {:?} | connections: {}/{} | failures: {} | connects: {} | disconnects: {} | wins: {} | duplicates: {}",
+                state.endpoint.url, state.endpoint.priority, role, state.clients.len(), self.target(role),
+                state.failures, state.connects, state.disconnects, wins, duplicates
+            );
+        }
+
+        let bad_frames = self.quarantine.counts();
+        if bad_frames.total() > 0 {
+            warn!(
+                "Bad frames | decompress: {} | utf8: {} | payload: {}",
+                bad_frames.decompress, bad_frames.utf8, bad_frames.payload
+            );
+        }
+        for (id, stats) in stats {
+            info!(
+                "Client {} | wins: {} | duplicates: {} | avg lag: {}us",
+                id, stats.wins, stats.duplicates, stats.average_lag_us()
+            );
+        }
+    }
+
+    // Adds a new client connection to the endpoint at `index`
+    async fn add_client(&mut self, index: usize) -> Result<(), RelayError> {
+        let state = &self.endpoints[index];
+        // Reuse the lowest free slot so per client stats stay readable
+        let slot = (0..).find(|slot| !state.clients.contains_key(&client_id(index, *slot))).unwrap();
+        let id = client_id(index, slot);
+
+        let result = RelayClient::with_codec(
+            state.url.clone(),
             id,
+            self.codec.clone(),
             self.sender.clone(),
             self.error_sender.clone(),
+            self.options.clone(),
         )
-        .await?;
+        .await;
 
-        let handle = client.spawn();
-        self.clients.insert(id, handle);
-
-        Ok(())
+        let state = &mut self.endpoints[index];
+        match result {
+            Ok(client) => {
+                state.clients.insert(id, client.spawn());
+                state.connects += 1;
+                state.connected_at = Some(Instant::now());
+                Ok(())
+            }
+            Err(e) => {
+                state.failures += 1;
+                Err(e)
+            }
+        }
     }
 }
+
+fn client_id(endpoint_index: usize, slot: u32) -> u32 {
+    ((endpoint_index as u32) << ENDPOINT_ID_SHIFT) | slot
+}
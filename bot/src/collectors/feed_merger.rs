@@ -0,0 +1,93 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::collectors::feed_client::FeedItem;
+
+/// Number of blocks, counting back from the newest one seen, whose
+/// messages are remembered. Anything older is dropped as stale.
+const RETAINED_BLOCKS: u64 = 4;
+
+/// How each connection fared against the others.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionStats {
+    /// Messages this connection delivered first
+    pub wins: u64,
+    /// Messages this connection delivered after another one already had
+    pub duplicates: u64,
+    /// Total time, in microseconds, this connection trailed the winner by
+    pub total_lag_us: u64,
+}
+
+impl ConnectionStats {
+    /// Average time this connection trailed the winner by, in microseconds.
+    pub fn average_lag_us(&self) -> u64 {
+        self.total_lag_us.checked_div(self.duplicates).unwrap_or_default()
+    }
+}
+
+#[derive(Default)]
+struct MergerState {
+    // (block_number, index) -> first arrival
+    seen: BTreeMap<(u64, u64), Instant>,
+    stats: HashMap<u32, ConnectionStats>,
+}
+
+/// Merges the redundant feed streams of several connections into one, keyed
+/// on [FeedItem::position], `(block_number, index)` for flashblocks. The first
+/// connection to deliver a message wins, later copies are dropped.
+#[derive(Default)]
+pub struct FeedMerger {
+    state: Mutex<MergerState>,
+}
+
+impl FeedMerger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records that `client_id` received `item`, returns whether it is the
+    /// first copy and should be passed on.
+    pub fn observe(&self, client_id: u32, item: &impl FeedItem) -> bool {
+        self.observe_at(client_id, item, Instant::now())
+    }
+
+    pub fn observe_at(&self, client_id: u32, item: &impl FeedItem, received_at: Instant) -> bool {
+        let key = item.position();
+        let mut state = self.state.lock().unwrap();
+
+        let newest_block = state.seen.keys().next_back().map(|(block_number, _)| *block_number);
+        if let Some(newest_block) = newest_block {
+            if key.0 + RETAINED_BLOCKS <= newest_block {
+                state.stats.entry(client_id).or_default().duplicates += 1;
+                return false;
+            }
+        }
+
+        if let Some(first) = state.seen.get(&key).copied() {
+            let lag_us = received_at.saturating_duration_since(first).as_micros() as u64;
+            let stats = state.stats.entry(client_id).or_default();
+            stats.duplicates += 1;
+            stats.total_lag_us += lag_us;
+            return false;
+        }
+
+        state.seen.insert(key, received_at);
+        state.stats.entry(client_id).or_default().wins += 1;
+
+        // Forget blocks that have fallen out of the window
+        if key.0 >= RETAINED_BLOCKS {
+            state.seen = state.seen.split_off(&(key.0 + 1 - RETAINED_BLOCKS, 0));
+        }
+
+        true
+    }
+
+    /// Per connection stats, ordered by client id.
+    pub fn stats(&self) -> Vec<(u32, ConnectionStats)> {
+        let state = self.state.lock().unwrap();
+        let mut stats: Vec<_> = state.stats.iter().map(|(id, stats)| (*id, *stats)).collect();
+        stats.sort_by_key(|(id, _)| *id);
+        stats
+    }
+}
@@ -0,0 +1,171 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, BufReader, BufWriter, ErrorKind, Read, Write};
+use std::sync::mpsc::{channel, RecvTimeoutError, Sender};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use log::*;
+use tokio::sync::oneshot;
+
+/// Largest frame a recording may hold, the largest message the websocket
+/// client accepts. A longer length prefix means the file is corrupt.
+pub const MAX_FRAME_SIZE: usize = 64 << 20;
+
+/// How often buffered frames are written to the file. A crash loses at most
+/// the frames received in the last interval.
+pub const FLUSH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// A raw feed frame as it came off the socket, with the time it was received.
+///
+/// On disk every frame is stored as
+/// `received_at_us: u64 | client_id: u32 | len: u32 | data: [u8; len]`,
+/// all little endian, appended one after another.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedFrame {
+    /// Receive time in microseconds since the unix epoch
+    pub received_at_us: u64,
+    /// Id of the relay client that received the frame
+    pub client_id: u32,
+    /// The frame exactly as received, still compressed
+    pub data: Vec<u8>,
+}
+
+impl RecordedFrame {
+    pub fn now(client_id: u32, data: Vec<u8>) -> Self {
+        let received_at_us = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64;
+        Self { received_at_us, client_id, data }
+    }
+
+    pub fn write_to(&self, writer: &mut impl Write) -> io::Result<()> {
+        if self.data.len() > MAX_FRAME_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidInput, format!("frame of {} bytes is too large", self.data.len())));
+        }
+
+        writer.write_all(&self.received_at_us.to_le_bytes())?;
+        writer.write_all(&self.client_id.to_le_bytes())?;
+        writer.write_all(&(self.data.len() as u32).to_le_bytes())?;
+        writer.write_all(&self.data)
+    }
+
+    /// Reads the next frame, or `None` at the end of the recording. A frame cut
+    /// short by a crash while recording is treated as the end, a length above
+    /// [MAX_FRAME_SIZE] as an error.
+    pub fn read_from(reader: &mut impl Read) -> io::Result<Option<Self>> {
+        let mut header = [0u8; 16];
+        match reader.read_exact(&mut header) {
+            Ok(()) => {}
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+            Err(e) => return Err(e),
+        }
+
+        let received_at_us = u64::from_le_bytes(header[0..8].try_into().unwrap());
+        let client_id = u32::from_le_bytes(header[8..12].try_into().unwrap());
+        let len = u32::from_le_bytes(header[12..16].try_into().unwrap()) as usize;
+        if len > MAX_FRAME_SIZE {
+            return Err(io::Error::new(ErrorKind::InvalidData, format!("frame of {} bytes is too large", len)));
+        }
+
+        let mut data = vec![0u8; len];
+        match reader.read_exact(&mut data) {
+            Ok(()) => Ok(Some(Self { received_at_us, client_id, data })),
+            Err(e) if e.kind() == ErrorKind::UnexpectedEof => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Appends raw feed frames to a recording file. Writes happen on a dedicated
+/// thread so recording never blocks the socket readers, and are flushed every
+/// [FLUSH_INTERVAL] or on [FeedRecorder::flush].
+#[derive(Clone, Debug)]
+pub struct FeedRecorder {
+    sender: Sender<Command>,
+}
+
+#[derive(Debug)]
+enum Command {
+    Record(RecordedFrame),
+    Flush(oneshot::Sender<io::Result<()>>),
+}
+
+impl FeedRecorder {
+    /// Opens `path` for appending, creating it if needed.
+    pub fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let (sender, receiver) = channel::<Command>();
+        info!("Recording feed frames | Path: {}", path);
+        let path = path.to_string();
+
+        std::thread::spawn(move || {
+            let mut writer = BufWriter::new(file);
+            let mut last_flush = Instant::now();
+            // First error since the last `flush()`, the writer keeps going past it
+            let mut failed: Option<io::Error> = None;
+            let fail = |failed: &mut Option<io::Error>, e: io::Error| {
+                if failed.is_none() {
+                    error!("Failed to record frames to {}: {}", path, e);
+                    *failed = Some(e);
+                }
+            };
+
+            loop {
+                match receiver.recv_timeout(FLUSH_INTERVAL.saturating_sub(last_flush.elapsed())) {
+                    Ok(Command::Record(frame)) => {
+                        if let Err(e) = frame.write_to(&mut writer) {
+                            fail(&mut failed, e);
+                        }
+                    }
+                    Ok(Command::Flush(done)) => {
+                        last_flush = Instant::now();
+                        let flushed = writer.flush();
+                        let _ = done.send(match failed.take() {
+                            Some(e) => Err(e),
+                            None => flushed,
+                        });
+                        continue;
+                    }
+                    Err(RecvTimeoutError::Timeout) => {}
+                    Err(RecvTimeoutError::Disconnected) => {
+                        if let Err(e) = writer.flush() {
+                            fail(&mut failed, e);
+                        }
+                        break;
+                    }
+                }
+
+                if last_flush.elapsed() >= FLUSH_INTERVAL {
+                    last_flush = Instant::now();
+                    if let Err(e) = writer.flush() {
+                        fail(&mut failed, e);
+                    }
+                }
+            }
+        });
+
+        Ok(Self { sender })
+    }
+
+    pub fn record(&self, client_id: u32, data: &[u8]) {
+        let _ = self.sender.send(Command::Record(RecordedFrame::now(client_id, data.to_vec())));
+    }
+
+    /// Waits until every frame recorded so far is written to the file. Fails
+    /// with the first error the writer hit since the last flush.
+    pub async fn flush(&self) -> io::Result<()> {
+        let stopped = || io::Error::new(ErrorKind::BrokenPipe, "recorder stopped");
+        let (done, written) = oneshot::channel();
+        self.sender.send(Command::Flush(done)).map_err(|_| stopped())?;
+        written.await.map_err(|_| stopped())?
+    }
+}
+
+/// Reads every complete frame of a recording, in the order it was written.
+pub fn read_recording(path: &str) -> io::Result<Vec<RecordedFrame>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut frames = vec![];
+
+    while let Some(frame) = RecordedFrame::read_from(&mut reader)? {
+        frames.push(frame);
+    }
+
+    Ok(frames)
+}
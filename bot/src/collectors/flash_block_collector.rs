@@ -1,19 +1,93 @@
-use crate::{collectors::feed_clients::RelayClients, types::FlashblocksPayloadV1};
+use std::sync::Arc;
+
+use crate::{errors::RelayError, collectors::{chain_check::ChainCheck, feed_client::{ClientOptions, Heartbeat}, feed_clients::{FeedEndpoint, RelayClients}, reconnect_policy::ReconnectPolicy, feed_recorder::FeedRecorder, frame_quarantine::FrameQuarantine}, types::{Collector, CollectorStream, FlashblocksPayloadV1}};
+use anyhow::Result;
+use async_trait::async_trait;
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// A collector that maintains a pool of flashblocks websocket connections
+/// over one or more endpoints, and generates a stream of
+/// [FlashblocksPayloadV1] events.
+pub struct FlashBlockCollector {
+    endpoints: Vec<FeedEndpoint>,
+    chain_id: u64,
+    record_path: Option<String>,
+    policy: ReconnectPolicy,
+    options: ClientOptions,
+}
+
+impl FlashBlockCollector {
+    pub fn new(endpoints: Vec<FeedEndpoint>, chain_id: u64) -> Self {
+        Self {
+            endpoints,
+            chain_id,
+            record_path: None,
+            policy: ReconnectPolicy::default(),
+            options: ClientOptions::default(),
+        }
+    }
+
+    /// Records every raw frame received to `record_path`, see [FeedRecorder].
+    pub fn with_record_path(mut self, record_path: Option<String>) -> Self {
+        self.record_path = record_path;
+        self
+    }
+
+    /// Collects the frames that fail to decode into `quarantine` instead of
+    /// only counting them.
+    pub fn with_quarantine(mut self, quarantine: Arc<FrameQuarantine>) -> Self {
+        self.options.quarantine = Some(quarantine);
+        self
+    }
+
+    pub fn with_reconnect_policy(mut self, policy: ReconnectPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn with_heartbeat(mut self, heartbeat: Heartbeat) -> Self {
+        self.options.heartbeat = heartbeat;
+        self
+    }
+
+    /// Rejects connections whose feed doesn't match `chain_check`.
+    pub fn with_chain_check(mut self, chain_check: Arc<ChainCheck>) -> Self {
+        self.options.chain_check = Some(chain_check);
+        self
+    }
+}
+
+#[async_trait]
+impl Collector<FlashblocksPayloadV1> for FlashBlockCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, FlashblocksPayloadV1>> {
+        let mut options = self.options.clone();
+        if let Some(path) = &self.record_path {
+            options.recorder = Some(FeedRecorder::open(path)?);
+        }
+
+        let receiver = start_flash_block_collector(self.endpoints.clone(), self.chain_id, self.policy, options).await?;
+        Ok(Box::pin(UnboundedReceiverStream::new(receiver)))
+    }
+}
 
-pub async fn start_flash_block_collector(flash_block_ws_url: String, chain_id: u64) -> 
-    UnboundedReceiver<FlashblocksPayloadV1>
+pub async fn start_flash_block_collector(
+    endpoints: Vec<FeedEndpoint>,
+    chain_id: u64,
+    policy: ReconnectPolicy,
+    options: ClientOptions,
+) ->
+    Result<UnboundedReceiver<FlashblocksPayloadV1>, RelayError>
 {
     // Create a channel to receive messages from the feed client
     let (sender_fb, receiver_fb) = unbounded_channel();
 
     // Create a new relay client and start background maintenance
-    let relay_client = RelayClients::new(&
-        flash_block_ws_url, chain_id, 20, 1, 
-        sender_fb.clone())
-        .await
-        .expect("Failed to create relay client");
+    let relay_client = RelayClients::new(
+        &endpoints, chain_id, policy,
+        sender_fb.clone(), options)
+        .await?;
     tokio::spawn(RelayClients::start_reader(relay_client));
 
-    receiver_fb
-}
\ No newline at end of file
+    Ok(receiver_fb)
+}
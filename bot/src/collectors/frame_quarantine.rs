@@ -0,0 +1,83 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::SystemTime;
+
+use crate::errors::RelayError;
+
+/// A frame that could not be decoded, kept for inspection.
+#[derive(Clone, Debug)]
+pub struct QuarantinedFrame {
+    pub client_id: u32,
+    pub received_at: SystemTime,
+    /// The frame exactly as received
+    pub data: Vec<u8>,
+    pub error: String,
+}
+
+/// Number of frames that failed each decoding step.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct DecodeErrorCounts {
+    pub decompress: u64,
+    pub utf8: u64,
+    pub payload: u64,
+}
+
+impl DecodeErrorCounts {
+    pub fn total(&self) -> u64 {
+        self.decompress + self.utf8 + self.payload
+    }
+}
+
+#[derive(Default)]
+struct QuarantineState {
+    counts: DecodeErrorCounts,
+    frames: VecDeque<QuarantinedFrame>,
+}
+
+/// Counts frames that failed to decode and keeps the most recent ones.
+/// A capacity of 0 only counts.
+#[derive(Default)]
+pub struct FrameQuarantine {
+    capacity: usize,
+    state: Mutex<QuarantineState>,
+}
+
+impl FrameQuarantine {
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity, state: Mutex::default() }
+    }
+
+    /// Records a frame `client_id` failed to decode with `error`.
+    pub fn add(&self, client_id: u32, data: &[u8], error: &RelayError) {
+        let mut state = self.state.lock().unwrap();
+
+        match error {
+            RelayError::Decompress(_) => state.counts.decompress += 1,
+            RelayError::InvalidUtf8(_) => state.counts.utf8 += 1,
+            _ => state.counts.payload += 1,
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+
+        if state.frames.len() == self.capacity {
+            state.frames.pop_front();
+        }
+        state.frames.push_back(QuarantinedFrame {
+            client_id,
+            received_at: SystemTime::now(),
+            data: data.to_vec(),
+            error: error.to_string(),
+        });
+    }
+
+    pub fn counts(&self) -> DecodeErrorCounts {
+        self.state.lock().unwrap().counts
+    }
+
+    /// The quarantined frames, oldest first.
+    pub fn frames(&self) -> Vec<QuarantinedFrame> {
+        self.state.lock().unwrap().frames.iter().cloned().collect()
+    }
+}
@@ -1,9 +1,31 @@
-
+use crate::types::{Collector, CollectorStream};
+use anyhow::Result;
+use async_trait::async_trait;
 use mev_share::sse::{Event, EventClient};
 use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
-use tokio_stream::StreamExt;
+use tokio_stream::{wrappers::UnboundedReceiverStream, StreamExt};
+
+/// A collector that listens to the MEV-share SSE endpoint, and generates a
+/// stream of MEV-share [Event]s.
+pub struct MevShareCollector {
+    mevshare_sse_url: String,
+}
+
+impl MevShareCollector {
+    pub fn new(mevshare_sse_url: String) -> Self {
+        Self { mevshare_sse_url }
+    }
+}
 
-pub async fn start_mevshare_collector(mevshare_sse_url: String) -> 
+#[async_trait]
+impl Collector<Event> for MevShareCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, Event>> {
+        let receiver = start_mevshare_collector(self.mevshare_sse_url.clone()).await;
+        Ok(Box::pin(UnboundedReceiverStream::new(receiver)))
+    }
+}
+
+pub async fn start_mevshare_collector(mevshare_sse_url: String) ->
     UnboundedReceiver<Event>
 {
     // Create a channel to receive messages from the feed client
@@ -13,7 +35,7 @@ pub async fn start_mevshare_collector(mevshare_sse_url: String) ->
         loop {
             let client = EventClient::default();
             let mut stream = client.events(&mevshare_sse_url).await.unwrap();
-            
+
             while let Some(event) = stream.next().await {
                 let Ok(event) = event else {
                     continue;
@@ -22,7 +44,7 @@ pub async fn start_mevshare_collector(mevshare_sse_url: String) ->
             }
         }
     });
-    
+
     receiver_fb
 
-}
\ No newline at end of file
+}
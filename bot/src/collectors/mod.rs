@@ -13,4 +13,31 @@ pub mod mevshare_collector;
 /// This collector listens to a stream of flash block
 pub mod flash_block_collector;
 pub mod feed_client;
-pub mod feed_clients;
\ No newline at end of file
+pub mod feed_clients;
+
+/// Decodes the Arbitrum sequencer feed
+pub mod arbitrum_feed;
+
+/// This collector listens to the Arbitrum sequencer feed
+pub mod arbitrum_feed_collector;
+
+/// Checks that a feed belongs to the expected chain
+pub mod chain_check;
+
+/// Merges redundant flashblocks connections into one stream
+pub mod feed_merger;
+
+/// Keeps flashblocks frames that fail to decode
+pub mod frame_quarantine;
+
+/// Backoff and connection budget of the flashblocks connections
+pub mod reconnect_policy;
+
+/// Records raw flashblocks frames to disk
+pub mod feed_recorder;
+
+/// This collector replays a flashblocks recording
+pub mod replay_collector;
+
+/// This collector emits the current unix time on a fixed interval
+pub mod time_collector;
\ No newline at end of file
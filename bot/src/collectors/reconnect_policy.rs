@@ -0,0 +1,68 @@
+use std::time::Duration;
+
+use ethers::core::rand::{thread_rng, Rng};
+
+/// How [RelayClients](crate::collectors::feed_clients::RelayClients) keeps its
+/// pool of connections to one feed url.
+///
+/// A lost connection is replaced after an exponential backoff that grows with
+/// every failed attempt. Once `min_connections` are open the pool grows one
+/// connection per `max_backoff` until it reaches `max_connections`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt after a disconnect
+    pub initial_backoff: Duration,
+    /// Upper bound of the delay, also the pace of growing past `min_connections`
+    pub max_backoff: Duration,
+    /// Factor the delay grows by with every failed attempt
+    pub multiplier: f64,
+    /// Each delay is scaled by a random factor in `1 ± jitter`
+    pub jitter: f64,
+    /// Connections opened up front and restored first
+    pub min_connections: u32,
+    /// Connection budget for the url
+    pub max_connections: u32,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            multiplier: 2.0,
+            jitter: 0.2,
+            min_connections: 1,
+            max_connections: 20,
+        }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Delay before the next attempt, after `failures` failed attempts in a row.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let jitter = if self.jitter > 0.0 {
+            thread_rng().gen_range(-self.jitter..=self.jitter)
+        } else {
+            0.0
+        };
+
+        self.backoff_with_jitter(failures, jitter)
+    }
+
+    /// [ReconnectPolicy::backoff] with a fixed jitter in `-jitter..=jitter`.
+    pub fn backoff_with_jitter(&self, failures: u32, jitter: f64) -> Duration {
+        let max = self.max_backoff.as_secs_f64();
+        let delay = self.initial_backoff.as_secs_f64() * self.multiplier.powi(failures.min(64) as i32);
+        Duration::from_secs_f64((delay.min(max) * (1.0 + jitter)).clamp(0.0, max))
+    }
+
+    /// Delay before opening another connection when `active` are open and
+    /// the last attempt succeeded.
+    pub fn growth_delay(&self, active: u32) -> Duration {
+        if active < self.min_connections {
+            self.backoff(0)
+        } else {
+            self.max_backoff
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use crate::{collectors::{feed_client::decode_frame, feed_merger::FeedMerger, feed_recorder::read_recording}, errors::RelayError, types::{Collector, CollectorStream, FlashblocksPayloadV1}};
+use anyhow::Result;
+use async_trait::async_trait;
+use log::*;
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::{sleep_until, Duration, Instant};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// A collector that plays a flashblocks recording back as a stream of
+/// [FlashblocksPayloadV1] events, in the order they were received.
+pub struct ReplayCollector {
+    path: String,
+    speed: f64,
+}
+
+impl ReplayCollector {
+    /// `speed` scales the recorded gaps between frames: 1.0 replays in real
+    /// time, 10.0 ten times faster, and 0.0 as fast as possible.
+    pub fn new(path: String, speed: f64) -> Self {
+        Self { path, speed }
+    }
+}
+
+#[async_trait]
+impl Collector<FlashblocksPayloadV1> for ReplayCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, FlashblocksPayloadV1>> {
+        let (sender, receiver) = unbounded_channel();
+        let path = self.path.clone();
+        let speed = self.speed;
+
+        tokio::spawn(async move {
+            match replay_recording(&path, speed, sender).await {
+                Ok(count) => info!("Replay finished | Frames: {}", count),
+                Err(e) => error!("Replay failed: {}", e),
+            }
+        });
+
+        Ok(Box::pin(UnboundedReceiverStream::new(receiver)))
+    }
+}
+
+/// Decodes every frame of the recording at `path` and sends it to `sender`,
+/// the same way the relay clients would, so copies of a flashblock recorded
+/// from several connections are only sent once. Returns the number sent.
+pub async fn replay_recording(
+    path: &str,
+    speed: f64,
+    sender: UnboundedSender<FlashblocksPayloadV1>,
+) -> Result<usize, RelayError> {
+    let frames = read_recording(path)?;
+    info!("Replaying {} frames from {}", frames.len(), path);
+
+    let start = Instant::now();
+    let first_received_at_us = frames.first().map(|f| f.received_at_us).unwrap_or_default();
+    let merger = FeedMerger::new();
+    let mut count = 0;
+
+    for frame in frames {
+        if speed > 0.0 {
+            // Schedule against the start of the replay so gaps don't accumulate drift
+            let offset_us = frame.received_at_us.saturating_sub(first_received_at_us) as f64 / speed;
+            sleep_until(start + Duration::from_micros(offset_us as u64)).await;
+        }
+
+        let flashblock = match decode_frame(&frame.data) {
+            Ok(flashblock) => flashblock,
+            Err(e) => {
+                warn!("Skipping bad frame | Client Id: {} | {}", frame.client_id, e);
+                continue;
+            }
+        };
+        if !merger.observe(frame.client_id, &flashblock) {
+            continue;
+        }
+
+        if sender.send(flashblock).is_err() {
+            break;
+        }
+
+        count += 1;
+    }
+
+    Ok(count)
+}
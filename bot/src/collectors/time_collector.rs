@@ -21,7 +21,7 @@ impl TimerCollector {
 impl Collector<u64> for TimerCollector
 where
 {
-    async fn get_event_stream(&self) -> Result<CollectorStream<'_, u64>> {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, u64>> {
         let base_instant = Instant::now();
         let base_time = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
 
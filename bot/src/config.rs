@@ -0,0 +1,672 @@
+//! Bot configuration. A config file has a shared `[common]` section and one
+//! section per platform (`[base]`, `[arbitrum]`, `[op]`, `[mainnet]`). Only
+//! the section of the platform being run is required.
+//!
+//! Any key can be overridden from the environment with
+//! `MS_BOT__<SECTION>__<KEY>`, e.g. `MS_BOT__COMMON__RPC_URL` or
+//! `MS_BOT__OP__LAUNCHERS__0__MIN_PROFIT` for array entries.
+
+use std::fs::read_to_string;
+use std::time::Duration;
+
+use ethers::types::{H160, U256};
+use ethers::utils::WEI_IN_ETHER;
+use serde::{Deserialize, Serialize};
+use toml::{Table, Value};
+
+use crate::collectors::arbitrum_feed::ARB1_GENESIS_BLOCK;
+use crate::collectors::feed_clients::FeedEndpoint;
+use crate::collectors::reconnect_policy::ReconnectPolicy;
+use crate::errors::{ConfigError, ConfigResult};
+use crate::trade_search::{SearchAlgorithm, SearchParams};
+
+/// Prefix of the environment variables that override config keys
+pub const ENV_PREFIX: &str = "MS_BOT__";
+
+/// The chain / strategy family the bot runs on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, clap::ValueEnum)]
+pub enum Platform {
+    Base,
+    Arbitrum,
+    Op,
+    Mainnet,
+}
+
+impl Platform {
+    /// Name of the platform, which is also the name of its config section.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Platform::Base => "base",
+            Platform::Arbitrum => "arbitrum",
+            Platform::Op => "op",
+            Platform::Mainnet => "mainnet",
+        }
+    }
+
+    /// The config file used when none is given on the command line.
+    pub fn default_config_path(&self) -> String {
+        format!("config_{}.toml", self.as_str())
+    }
+}
+
+impl std::fmt::Display for Platform {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Converts an amount expressed in 1/10000 ETH, as used by the config files, to wei.
+pub fn to_wei(amount: u64) -> U256 {
+    WEI_IN_ETHER * amount / 10000
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Config {
+    pub common: CommonConfig,
+    pub base: Option<BaseConfig>,
+    pub arbitrum: Option<ArbitrumConfig>,
+    pub op: Option<OpConfig>,
+    pub mainnet: Option<MainnetConfig>,
+}
+
+/// Settings shared by every strategy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CommonConfig {
+    pub chain_id: u64,
+    pub rpc_url: String,
+    pub ws_url: String,
+    pub from_addr: H160,
+    /// Path of the encrypted keystore holding the `from_addr` key
+    pub keystore: String,
+    pub gas_limit: u64,
+    #[serde(default)]
+    pub test_mode: bool,
+}
+
+impl CommonConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &["chain_id", "rpc_url", "ws_url", "from_addr", "keystore", "gas_limit"];
+    const OPTIONAL_KEYS: &'static [&'static str] = &["test_mode"];
+}
+
+/// Settings of the Base flashblocks strategy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct BaseConfig {
+    pub to_addr: H160,
+    pub simulator_addr: H160,
+    /// Primary flashblocks feed, used with priority 0
+    pub flashblocks_url: String,
+    /// Further feeds to spread connections over and fail over to
+    #[serde(default)]
+    pub flashblocks_endpoints: Vec<FeedEndpointConfig>,
+    pub trade_server_url: String,
+    pub sequencer_url: String,
+    #[serde(default)]
+    pub execution_mode: bool,
+    #[serde(default)]
+    pub simulation_mode: bool,
+    /// In simulation mode, simulate on top of the flashblocks of the pending
+    /// block instead of the last block
+    #[serde(default)]
+    pub simulate_pending: bool,
+    /// The trade ladder steps `1 / step_count` of the pool balance apart
+    pub step_count: u64,
+    /// Trades in the ladder of each pool and side
+    pub regression_count: u64,
+    /// In 1/10000 ETH
+    pub min_profit: u64,
+    /// In 1/10000 ETH
+    pub min_swap_amount: u64,
+    /// Append every raw flashblocks frame to this file for later replay
+    #[serde(default)]
+    pub record_path: Option<String>,
+    /// Cache the pools of the launcher in this file
+    #[serde(default)]
+    pub pool_cache_path: Option<String>,
+    /// Quote trades natively from pool snapshots instead of simulating
+    /// each of them
+    #[serde(default)]
+    pub native_quoting: bool,
+    /// How the flashblocks connections are kept up
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    /// How the trades of each pool are searched for
+    #[serde(default)]
+    pub search: SearchConfig,
+}
+
+impl BaseConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &[
+        "to_addr", "simulator_addr", "flashblocks_url", "trade_server_url", "sequencer_url",
+        "step_count", "regression_count", "min_profit", "min_swap_amount",
+    ];
+    const OPTIONAL_KEYS: &'static [&'static str] = &[
+        "execution_mode", "simulation_mode", "simulate_pending", "record_path", "pool_cache_path", "native_quoting", "reconnect", "flashblocks_endpoints",
+        "search",
+    ];
+
+    /// The search settings with the ladder of `step_count` and `regression_count`.
+    pub fn search_params(&self) -> SearchParams {
+        SearchParams::from(&self.search).with_regression(self.step_count, self.regression_count as usize)
+    }
+
+    /// Checks the settings allow replaying a recording. A replay only drives
+    /// the pending simulation: the executor also reads the live trade server
+    /// and the clock, which a recording doesn't hold, so nothing is executed.
+    pub fn check_replay(&self) -> ConfigResult<()> {
+        let mut issues = vec![];
+        if self.execution_mode {
+            issues.push("`base.execution_mode` must be off to replay".to_string());
+        }
+        if !self.simulation_mode || !self.simulate_pending {
+            issues.push("`base.simulation_mode` and `base.simulate_pending` must be on to replay".to_string());
+        }
+
+        match issues.is_empty() {
+            true => Ok(()),
+            false => Err(ConfigError::Invalid(issues)),
+        }
+    }
+
+    /// The primary feed followed by the extra ones.
+    pub fn feed_endpoints(&self) -> Vec<FeedEndpoint> {
+        let mut endpoints = vec![FeedEndpoint::new(self.flashblocks_url.clone(), 0)];
+        endpoints.extend(self.flashblocks_endpoints.iter().map(|e| FeedEndpoint::new(e.url.clone(), e.priority)));
+        endpoints
+    }
+}
+
+/// An extra flashblocks feed. Lower priorities are preferred.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct FeedEndpointConfig {
+    pub url: String,
+    pub priority: u32,
+}
+
+impl FeedEndpointConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &["url", "priority"];
+    const OPTIONAL_KEYS: &'static [&'static str] = &[];
+}
+
+/// Settings of the base strategy on an Arbitrum chain, following its
+/// sequencer feed (see [ArbitrumCodec](crate::collectors::arbitrum_feed::ArbitrumCodec))
+/// instead of flashblocks. The feed carries no receipts to follow pool prices
+/// with, so the strategy only simulates.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ArbitrumConfig {
+    pub to_addr: H160,
+    pub simulator_addr: H160,
+    pub feed_url: String,
+    /// L2 block produced by the feed message with sequence number 0
+    #[serde(default = "default_genesis_block")]
+    pub genesis_block: u64,
+    pub trade_server_url: String,
+    /// Simulate on top of the transactions of each feed message instead of
+    /// the last block
+    #[serde(default)]
+    pub simulate_pending: bool,
+    /// The trade ladder steps `1 / step_count` of the pool balance apart
+    pub step_count: u64,
+    /// Trades in the ladder of each pool and side
+    pub regression_count: u64,
+    /// In 1/10000 ETH
+    pub min_profit: u64,
+    /// In 1/10000 ETH
+    pub min_swap_amount: u64,
+    /// Quote trades natively from pool snapshots instead of simulating
+    /// each of them
+    #[serde(default)]
+    pub native_quoting: bool,
+    /// How the feed connection is kept up
+    #[serde(default)]
+    pub reconnect: ReconnectConfig,
+    /// How the trades of each pool are searched for
+    #[serde(default)]
+    pub search: SearchConfig,
+}
+
+impl ArbitrumConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &[
+        "to_addr", "simulator_addr", "feed_url", "trade_server_url",
+        "step_count", "regression_count", "min_profit", "min_swap_amount",
+    ];
+    const OPTIONAL_KEYS: &'static [&'static str] = &[
+        "genesis_block", "simulate_pending", "native_quoting", "reconnect", "search",
+    ];
+
+    /// The search settings with the ladder of `step_count` and `regression_count`.
+    pub fn search_params(&self) -> SearchParams {
+        SearchParams::from(&self.search).with_regression(self.step_count, self.regression_count as usize)
+    }
+}
+
+fn default_genesis_block() -> u64 {
+    ARB1_GENESIS_BLOCK
+}
+
+/// Reconnection settings of a feed, see [ReconnectPolicy].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ReconnectConfig {
+    pub initial_backoff_ms: u64,
+    pub max_backoff_ms: u64,
+    pub backoff_multiplier: f64,
+    /// Fraction of each delay to randomize, between 0 and 1
+    pub jitter: f64,
+    pub min_connections: u32,
+    /// Connection budget per feed url
+    pub max_connections: u32,
+}
+
+impl ReconnectConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &[];
+    const OPTIONAL_KEYS: &'static [&'static str] = &[
+        "initial_backoff_ms", "max_backoff_ms", "backoff_multiplier", "jitter", "min_connections", "max_connections",
+    ];
+
+    fn validate(&self, path: &str, issues: &mut Vec<String>) {
+        if self.initial_backoff_ms > self.max_backoff_ms {
+            issues.push(format!("`{path}.initial_backoff_ms` is larger than `{path}.max_backoff_ms`"));
+        }
+        if self.backoff_multiplier < 1.0 {
+            issues.push(format!("`{path}.backoff_multiplier` must be at least 1"));
+        }
+        if !(0.0..=1.0).contains(&self.jitter) {
+            issues.push(format!("`{path}.jitter` must be between 0 and 1"));
+        }
+        if self.max_connections == 0 || self.min_connections > self.max_connections {
+            issues.push(format!("`{path}.min_connections` must be at most `{path}.max_connections`, which must be positive"));
+        }
+    }
+}
+
+impl Default for ReconnectConfig {
+    fn default() -> Self {
+        (&ReconnectPolicy::default()).into()
+    }
+}
+
+impl From<&ReconnectPolicy> for ReconnectConfig {
+    fn from(policy: &ReconnectPolicy) -> Self {
+        Self {
+            initial_backoff_ms: policy.initial_backoff.as_millis() as u64,
+            max_backoff_ms: policy.max_backoff.as_millis() as u64,
+            backoff_multiplier: policy.multiplier,
+            jitter: policy.jitter,
+            min_connections: policy.min_connections,
+            max_connections: policy.max_connections,
+        }
+    }
+}
+
+impl From<&ReconnectConfig> for ReconnectPolicy {
+    fn from(config: &ReconnectConfig) -> Self {
+        Self {
+            initial_backoff: Duration::from_millis(config.initial_backoff_ms),
+            max_backoff: Duration::from_millis(config.max_backoff_ms),
+            multiplier: config.backoff_multiplier,
+            jitter: config.jitter,
+            min_connections: config.min_connections,
+            max_connections: config.max_connections,
+        }
+    }
+}
+
+/// Trade search settings, see [SearchParams].
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SearchConfig {
+    /// `bisection`, `golden_section`, `adaptive_step` or `newton`
+    pub algorithm: SearchAlgorithm,
+    /// In 1/10000 ETH
+    pub tolerance: u64,
+    pub max_evaluations: usize,
+    /// In 1/10000 ETH
+    pub max_regression_step: u64,
+    pub gas_limit: u64,
+}
+
+impl SearchConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &[];
+    const OPTIONAL_KEYS: &'static [&'static str] = &[
+        "algorithm", "tolerance", "max_evaluations", "max_regression_step", "gas_limit",
+    ];
+
+    fn validate(&self, path: &str, issues: &mut Vec<String>) {
+        if self.tolerance == 0 {
+            issues.push(format!("`{path}.tolerance` must be positive"));
+        }
+        if self.max_evaluations == 0 {
+            issues.push(format!("`{path}.max_evaluations` must be positive"));
+        }
+        if self.gas_limit == 0 {
+            issues.push(format!("`{path}.gas_limit` must be positive"));
+        }
+    }
+}
+
+impl Default for SearchConfig {
+    fn default() -> Self {
+        let params = SearchParams::default();
+        Self {
+            algorithm: params.algorithm,
+            tolerance: (params.tolerance * U256::from(10000) / WEI_IN_ETHER).as_u64(),
+            max_evaluations: params.max_evaluations,
+            max_regression_step: (params.max_regression_step * U256::from(10000) / WEI_IN_ETHER).as_u64(),
+            gas_limit: params.gas_limit.as_u64(),
+        }
+    }
+}
+
+impl From<&SearchConfig> for SearchParams {
+    fn from(config: &SearchConfig) -> Self {
+        Self {
+            algorithm: config.algorithm,
+            tolerance: to_wei(config.tolerance),
+            max_evaluations: config.max_evaluations,
+            max_regression_step: to_wei(config.max_regression_step),
+            gas_limit: U256::from(config.gas_limit),
+            ..SearchParams::default()
+        }
+    }
+}
+
+/// Settings of the OP strategy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct OpConfig {
+    pub sequencer_url: String,
+    #[serde(default)]
+    pub execution_mode: bool,
+    pub launchers: Vec<LauncherConfig>,
+    /// How the trades of each pool are searched for
+    #[serde(default)]
+    pub search: SearchConfig,
+}
+
+impl OpConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &["sequencer_url", "launchers"];
+    const OPTIONAL_KEYS: &'static [&'static str] = &["execution_mode", "search"];
+}
+
+/// A launcher / simulator pair and the pair of tokens it trades.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct LauncherConfig {
+    pub name: String,
+    pub launcher_addr: H160,
+    pub simulator_addr: H160,
+    pub base_token_addr: H160,
+    /// In 1/10000 ETH
+    pub min_profit: u64,
+    /// In 1/10000 ETH
+    pub min_swap_amount: u64,
+}
+
+impl LauncherConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &[
+        "name", "launcher_addr", "simulator_addr", "base_token_addr",
+        "min_profit", "min_swap_amount",
+    ];
+    const OPTIONAL_KEYS: &'static [&'static str] = &[];
+}
+
+/// Settings of the Ethereum mainnet strategy.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct MainnetConfig {
+    pub to_addr: H160,
+    pub simulator_addr: H160,
+    pub launcher_addr: H160,
+    #[serde(default)]
+    pub mevshare_url: String,
+    pub step_count: u64,
+    pub regression_count: u64,
+}
+
+impl MainnetConfig {
+    const REQUIRED_KEYS: &'static [&'static str] = &[
+        "to_addr", "simulator_addr", "launcher_addr", "step_count", "regression_count",
+    ];
+    const OPTIONAL_KEYS: &'static [&'static str] = &["mevshare_url"];
+}
+
+const SECTIONS: &[&str] = &["common", "base", "arbitrum", "op", "mainnet"];
+
+impl Config {
+    /// Reads the config file at `path`, applies environment overrides and
+    /// validates it for `platform`. Every problem found is reported at once.
+    pub fn load(path: &str, platform: Platform) -> ConfigResult<Self> {
+        let mut root: Table = toml::from_str(&read_to_string(path)?)?;
+        let overrides = apply_env_overrides(&mut root, std::env::vars());
+        match Self::from_table(root, platform) {
+            Err(ConfigError::Invalid(issues)) => Err(ConfigError::Invalid([overrides, issues].concat())),
+            _ if !overrides.is_empty() => Err(ConfigError::Invalid(overrides)),
+            result => result,
+        }
+    }
+
+    /// Validates an already parsed config table for `platform`.
+    pub fn from_table(root: Table, platform: Platform) -> ConfigResult<Self> {
+        let mut issues = vec![];
+
+        for key in root.keys() {
+            if !SECTIONS.contains(&key.as_str()) {
+                issues.push(format!("unknown key `{key}`"));
+            }
+        }
+
+        for section in ["common", platform.as_str()] {
+            if !root.contains_key(section) {
+                issues.push(format!("missing key `{section}`"));
+            }
+        }
+
+        if let Some(common) = root.get("common") {
+            check_table("common", common, CommonConfig::REQUIRED_KEYS, CommonConfig::OPTIONAL_KEYS, &mut issues);
+        }
+        if let Some(base) = root.get("base") {
+            check_table("base", base, BaseConfig::REQUIRED_KEYS, BaseConfig::OPTIONAL_KEYS, &mut issues);
+            if let Some(reconnect) = base.get("reconnect") {
+                check_table("base.reconnect", reconnect,
+                    ReconnectConfig::REQUIRED_KEYS, ReconnectConfig::OPTIONAL_KEYS, &mut issues);
+            }
+            if let Some(search) = base.get("search") {
+                check_table("base.search", search, SearchConfig::REQUIRED_KEYS, SearchConfig::OPTIONAL_KEYS, &mut issues);
+            }
+
+            match base.get("flashblocks_endpoints") {
+                Some(Value::Array(endpoints)) => {
+                    for (i, endpoint) in endpoints.iter().enumerate() {
+                        check_table(&format!("base.flashblocks_endpoints[{i}]"), endpoint,
+                            FeedEndpointConfig::REQUIRED_KEYS, FeedEndpointConfig::OPTIONAL_KEYS, &mut issues);
+                    }
+                }
+                Some(_) => issues.push("expected an array of tables at `base.flashblocks_endpoints`".to_string()),
+                None => {}
+            }
+        }
+        if let Some(arbitrum) = root.get("arbitrum") {
+            check_table("arbitrum", arbitrum, ArbitrumConfig::REQUIRED_KEYS, ArbitrumConfig::OPTIONAL_KEYS, &mut issues);
+            if let Some(reconnect) = arbitrum.get("reconnect") {
+                check_table("arbitrum.reconnect", reconnect,
+                    ReconnectConfig::REQUIRED_KEYS, ReconnectConfig::OPTIONAL_KEYS, &mut issues);
+            }
+            if let Some(search) = arbitrum.get("search") {
+                check_table("arbitrum.search", search, SearchConfig::REQUIRED_KEYS, SearchConfig::OPTIONAL_KEYS, &mut issues);
+            }
+        }
+        if let Some(mainnet) = root.get("mainnet") {
+            check_table("mainnet", mainnet, MainnetConfig::REQUIRED_KEYS, MainnetConfig::OPTIONAL_KEYS, &mut issues);
+        }
+        if let Some(op) = root.get("op") {
+            check_table("op", op, OpConfig::REQUIRED_KEYS, OpConfig::OPTIONAL_KEYS, &mut issues);
+            if let Some(search) = op.get("search") {
+                check_table("op.search", search, SearchConfig::REQUIRED_KEYS, SearchConfig::OPTIONAL_KEYS, &mut issues);
+            }
+
+            match op.get("launchers") {
+                Some(Value::Array(launchers)) => {
+                    for (i, launcher) in launchers.iter().enumerate() {
+                        check_table(&format!("op.launchers[{i}]"), launcher,
+                            LauncherConfig::REQUIRED_KEYS, LauncherConfig::OPTIONAL_KEYS, &mut issues);
+                    }
+                }
+                Some(_) => issues.push("expected an array of tables at `op.launchers`".to_string()),
+                None => {}
+            }
+        }
+
+        if !issues.is_empty() {
+            return Err(ConfigError::Invalid(issues));
+        }
+
+        // Every key is known and present, so what is left are value errors
+        let common = deserialize_section(&root, "common", &mut issues);
+        let base: Option<BaseConfig> = deserialize_section(&root, "base", &mut issues);
+        if let Some(base) = &base {
+            base.reconnect.validate("base.reconnect", &mut issues);
+            base.search.validate("base.search", &mut issues);
+            if base.step_count == 0 {
+                issues.push("`base.step_count` must be positive".to_string());
+            }
+        }
+        let arbitrum: Option<ArbitrumConfig> = deserialize_section(&root, "arbitrum", &mut issues);
+        if let Some(arbitrum) = &arbitrum {
+            arbitrum.reconnect.validate("arbitrum.reconnect", &mut issues);
+            arbitrum.search.validate("arbitrum.search", &mut issues);
+            if arbitrum.step_count == 0 {
+                issues.push("`arbitrum.step_count` must be positive".to_string());
+            }
+        }
+        let op: Option<OpConfig> = deserialize_section(&root, "op", &mut issues);
+        if let Some(op) = &op {
+            op.search.validate("op.search", &mut issues);
+        }
+        let mainnet = deserialize_section(&root, "mainnet", &mut issues);
+
+        match common {
+            Some(common) if issues.is_empty() => Ok(Config { common, base, arbitrum, op, mainnet }),
+            _ => Err(ConfigError::Invalid(issues)),
+        }
+    }
+}
+
+fn check_table(path: &str, value: &Value, required: &[&str], optional: &[&str], issues: &mut Vec<String>) {
+    let Value::Table(table) = value else {
+        issues.push(format!("expected a table at `{path}`"));
+        return;
+    };
+
+    for key in table.keys() {
+        if !required.contains(&key.as_str()) && !optional.contains(&key.as_str()) {
+            issues.push(format!("unknown key `{path}.{key}`"));
+        }
+    }
+
+    for key in required {
+        if !table.contains_key(*key) {
+            issues.push(format!("missing key `{path}.{key}`"));
+        }
+    }
+}
+
+fn deserialize_section<T: serde::de::DeserializeOwned>(root: &Table, section: &str, issues: &mut Vec<String>) -> Option<T> {
+    // Go through the text form so the error carries a span we can map back to a key
+    let text = toml::to_string(root.get(section)?).ok()?;
+    match toml::from_str::<T>(&text) {
+        Ok(section) => Some(section),
+        Err(e) => {
+            let path = e.span()
+                .map(|span| key_path_at(section, &text, span.start))
+                .unwrap_or_else(|| section.to_string());
+            issues.push(format!("invalid value at `{path}`: {}", e.message().trim()));
+            None
+        }
+    }
+}
+
+/// Finds the dotted path of the key defined on the line containing `offset`.
+fn key_path_at(section: &str, text: &str, offset: usize) -> String {
+    let mut path = section.to_string();
+    let mut table: Option<(String, Option<usize>)> = None;
+    let mut line_start = 0;
+
+    for line in text.split_inclusive('\n') {
+        let trimmed = line.trim();
+        if let Some(name) = trimmed.strip_prefix("[[").and_then(|l| l.strip_suffix("]]")) {
+            table = match table {
+                Some((current, Some(i))) if current == name => Some((current, Some(i + 1))),
+                _ => Some((name.to_string(), Some(0))),
+            };
+        } else if let Some(name) = trimmed.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            table = Some((name.to_string(), None));
+        }
+
+        if offset < line_start + line.len() {
+            if let Some((name, i)) = &table {
+                path = match i {
+                    Some(i) => format!("{path}.{name}[{i}]"),
+                    None => format!("{path}.{name}"),
+                };
+            }
+            if let Some((key, _)) = trimmed.split_once('=') {
+                path = format!("{path}.{}", key.trim());
+            }
+            break;
+        }
+
+        line_start += line.len();
+    }
+
+    path
+}
+
+/// Overrides config keys from `MS_BOT__SECTION__KEY=value` variables.
+/// A value replacing a string stays a string; other values are read as TOML
+/// scalars when possible and as strings otherwise. Returns the overrides that
+/// couldn't be applied, e.g. an array entry that doesn't exist.
+pub fn apply_env_overrides(root: &mut Table, vars: impl Iterator<Item = (String, String)>) -> Vec<String> {
+    let mut issues = vec![];
+
+    for (name, raw) in vars {
+        let Some(path) = name.strip_prefix(ENV_PREFIX) else {
+            continue;
+        };
+
+        let path: Vec<String> = path.split("__").map(|p| p.to_lowercase()).collect();
+        let Some((section, keys)) = path.split_first() else {
+            continue;
+        };
+
+        let entry = root.entry(section.clone()).or_insert_with(|| Value::Table(Table::new()));
+        if let Err(issue) = set_path(entry, section.clone(), keys, raw) {
+            issues.push(format!("`{name}` can't be applied: {issue}"));
+        }
+    }
+
+    issues
+}
+
+fn set_path(current: &mut Value, at: String, path: &[String], raw: String) -> Result<(), String> {
+    let Some((key, rest)) = path.split_first() else {
+        *current = match current {
+            Value::String(_) => Value::String(raw),
+            _ => toml::from_str::<Table>(&format!("v = {raw}"))
+                .ok()
+                .and_then(|mut t| t.remove("v"))
+                .unwrap_or(Value::String(raw)),
+        };
+        return Ok(());
+    };
+
+    let (next, at) = match current {
+        Value::Table(table) => (
+            table.entry(key.clone()).or_insert_with(|| Value::Table(Table::new())),
+            format!("{at}.{key}"),
+        ),
+        Value::Array(array) => match key.parse::<usize>().ok().and_then(|i| array.get_mut(i)) {
+            Some(item) => (item, format!("{at}[{key}]")),
+            None => return Err(format!("no entry `{key}` in the array at `{at}`")),
+        },
+        _ => return Err(format!("`{at}` isn't a table")),
+    };
+
+    set_path(next, at, rest, raw)
+}
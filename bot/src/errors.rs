@@ -1,3 +1,4 @@
+use ethers::types::{H160, H256, U256};
 use thiserror::Error;
 use tokio::io;
 
@@ -14,8 +15,9 @@ pub enum RelayError {
     #[error(transparent)]
     HTTP(#[from] tungstenite::http::Error),
 
+    // Boxed, it would make every RelayError result as large as itself
     #[error(transparent)]
-    Tungstenite(#[from] tungstenite::Error),
+    Tungstenite(Box<tungstenite::Error>),
 
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
@@ -23,6 +25,18 @@ pub enum RelayError {
     #[error(transparent)]
     SendError(#[from] tokio::sync::mpsc::error::SendError<ConnectionUpdate>),
 
+    #[error("Failed to decompress frame: {0}")]
+    Decompress(io::Error),
+
+    #[error("Frame is not valid utf8: {0}")]
+    InvalidUtf8(#[from] std::string::FromUtf8Error),
+
+    #[error("Frame is not a valid feed message: {0}")]
+    InvalidPayload(serde_json::Error),
+
+    #[error("Invalid L2 message: {0}")]
+    InvalidL2Message(String),
+
     #[error("Invalid Url")]
     InvalidUrl,
 
@@ -33,8 +47,132 @@ pub enum RelayError {
     Msg(String),
 }
 
+impl From<tungstenite::Error> for RelayError {
+    fn from(e: tungstenite::Error) -> Self {
+        RelayError::Tungstenite(Box::new(e))
+    }
+}
+
 #[derive(Debug)]
 pub enum ConnectionUpdate {
     StoppedSendingFrames(u32),
+    /// The connection is open but has not delivered a frame for too long
+    Stalled(u32),
+    /// The server closed the connection or the stream ended
+    Closed(u32),
     Unknown(u32),
 }
+
+#[derive(Debug, Error)]
+pub enum FlashblockError {
+    #[error("Flashblock {index} of block {block_number} has {transactions} transactions but {receipts} receipts")]
+    ReceiptCount { block_number: u64, index: u64, transactions: usize, receipts: usize },
+
+    #[error("Flashblock {index} of block {block_number} has no receipt for transaction {hash:?}")]
+    MissingReceipt { block_number: u64, index: u64, hash: H256 },
+
+    #[error("Flashblock 0 of block {0} has no base")]
+    MissingBase(u64),
+
+    #[error("Transaction {0:?} can't be replayed: {1}")]
+    InvalidTransaction(H256, String),
+}
+
+#[derive(Debug, Error)]
+pub enum SwapEventError {
+    #[error(transparent)]
+    Abi(#[from] ethers::abi::Error),
+
+    #[error("Log has no topic {0}")]
+    MissingTopic(usize),
+
+    #[error("Invalid swap event: {0}")]
+    InvalidValue(String),
+}
+
+#[derive(Debug, Error)]
+#[error("Invalid pool type: {0}")]
+pub struct InvalidPoolType(pub u8);
+
+#[derive(Debug, Error)]
+pub enum PoolCatalogError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("Pool {index}: {source}")]
+    PoolType { index: usize, source: InvalidPoolType },
+
+    #[error("RPC call failed: {0}")]
+    Rpc(String),
+
+    #[error("Pool {index} at {address:?} has no code")]
+    NoCode { index: usize, address: H160 },
+
+    #[error("Pool {index} trades {found:?} instead of {expected:?}")]
+    TokenMismatch { index: usize, expected: (H160, H160), found: (H160, H160) },
+
+    #[error("V4 pool {index}: {reason}")]
+    V4Pool { index: usize, reason: String },
+
+    #[error("Pool catalog is stale: {0}")]
+    Stale(String),
+}
+
+#[derive(Debug, Error)]
+pub enum TokenError {
+    #[error("RPC call failed: {0}")]
+    Rpc(String),
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum MathError {
+    #[error("Division by zero")]
+    DivisionByZero,
+
+    #[error("Result overflows 256 bits")]
+    Overflow,
+
+    #[error("Tick {0} is out of range")]
+    TickOutOfRange(i32),
+
+    #[error("Sqrt price {0} is out of range")]
+    SqrtPriceOutOfRange(U256),
+
+    #[error("Not enough liquidity for the output")]
+    NotEnoughLiquidity,
+}
+
+#[derive(Debug, Error, PartialEq, Eq)]
+pub enum QuoteError {
+    #[error(transparent)]
+    Math(#[from] MathError),
+
+    #[error("RPC call failed: {0}")]
+    Rpc(String),
+
+    #[error("Tick bitmap word {0} is not loaded")]
+    TicksNotLoaded(i16),
+
+    #[error("Invalid sqrt price limit {0}")]
+    InvalidPriceLimit(U256),
+
+    #[error("Pool {index} can't be quoted: {reason}")]
+    UnsupportedPool { index: usize, reason: String },
+}
+
+pub type ConfigResult<T> = std::result::Result<T, ConfigError>;
+
+#[derive(Debug, Error)]
+pub enum ConfigError {
+    #[error(transparent)]
+    IO(#[from] io::Error),
+
+    #[error(transparent)]
+    Parse(#[from] toml::de::Error),
+
+    #[error("Invalid config:\n{}", .0.join("\n"))]
+    Invalid(Vec<String>),
+}
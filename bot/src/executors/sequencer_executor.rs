@@ -1,42 +1,96 @@
 use std::sync::Arc;
 
+use anyhow::Result;
+use async_trait::async_trait;
 use ethers::{middleware::SignerMiddleware, providers::Middleware, signers::{LocalWallet, Signer}, types::{transaction::eip2718::TypedTransaction, U256}};
 use ethers_providers::{Http, Provider};
-use tokio::sync::mpsc::UnboundedReceiver;
+use tokio::sync::{mpsc::UnboundedReceiver, Mutex};
+
+use crate::errors::RelayError;
+use crate::types::Executor;
+
+/// An executor that signs transactions locally and submits them to the
+/// sequencer, tracking the sender nonce itself.
+pub struct SequencerExecutor {
+    sequencer_client: Arc<SignerMiddleware<Arc<Provider<Http>>, LocalWallet>>,
+    wallet: LocalWallet,
+    chain_id: u64,
+    nonce: Mutex<U256>,
+}
+
+impl SequencerExecutor {
+    pub fn new(sequencer_url: String, wallet: LocalWallet, chain_id: u64, starting_nonce: U256) -> Self {
+        let sequencer_client = Arc::new(Provider::<Http>::try_from(sequencer_url).unwrap());
+        let sequencer_client = Arc::new(SignerMiddleware::new(sequencer_client, wallet.clone()));
+
+        Self {
+            sequencer_client,
+            wallet,
+            chain_id,
+            nonce: Mutex::new(starting_nonce),
+        }
+    }
+
+    /// Fails with [RelayError::InvalidChainId] when the sequencer reports
+    /// another chain. Sequencers that don't serve `eth_chainId` are let through.
+    pub async fn verify_chain_id(&self) -> Result<()> {
+        match self.sequencer_client.get_chainid().await {
+            Ok(chain_id) if chain_id.as_u64() != self.chain_id => {
+                println!("Sequencer is on chain {} instead of {}", chain_id, self.chain_id);
+                Err(RelayError::InvalidChainId.into())
+            }
+            Ok(_) => Ok(()),
+            Err(e) => {
+                println!("Could not verify the sequencer chain id: {}", e);
+                Ok(())
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl Executor<TypedTransaction> for SequencerExecutor {
+    async fn execute(&self, mut tx: TypedTransaction) -> Result<()> {
+        let mut nonce = self.nonce.lock().await;
+        tx.set_nonce(*nonce);
+        tx.set_chain_id(self.chain_id);
+
+        let signature = self.wallet.sign_transaction(&tx).await?;
+        let signed_tx = tx.rlp_signed(&signature);
+        let sequencer_client = self.sequencer_client.clone();
+
+        tokio::spawn(async move {
+            match sequencer_client.send_raw_transaction(signed_tx).await {
+                Ok(pending_tx) => {
+                    println!("[MS] Tx sent: {:?}", tx);
+                    match pending_tx.await {
+                        Ok(tx) => println!("[MS] Tx mined: {:?}", tx),
+                        Err(e) => println!("[MS] Tx error: {}", e),
+                    }
+                }
+                Err(e) => println!("[MS] Send error: {}", e),
+            }
+        });
+
+        *nonce += U256::from(1);
+        Ok(())
+    }
+}
 
 pub async fn start_sequencer_executor(
     sequencer_url: String,
     wallet: LocalWallet,
     chain_id: u64,
-    mut starting_nonce: U256,
+    starting_nonce: U256,
     mut tx_receiver: UnboundedReceiver<TypedTransaction>
 ) {
-    let sequencer_client = Arc::new(Provider::<Http>::try_from(sequencer_url).unwrap());
-    let sequencer_client = SignerMiddleware::new(Arc::new(sequencer_client), wallet.clone());
+    let executor = SequencerExecutor::new(sequencer_url, wallet, chain_id, starting_nonce);
 
     tokio::spawn(async move {
-        while let Some(mut tx) = tx_receiver.recv().await {
-            tx.set_nonce(starting_nonce);
-            tx.set_chain_id(chain_id);
-    
-            let signature = wallet.sign_transaction(&tx).await.unwrap();
-            let signed_tx = tx.rlp_signed(&signature);
-            let sequencer_client_clone = sequencer_client.clone();
-        
-            tokio::spawn(async move {
-                match sequencer_client_clone.send_raw_transaction(signed_tx).await {
-                    Ok(pending_tx) => {
-                        println!("[MS] Tx sent: {:?}", tx);
-                        match pending_tx.await {
-                            Ok(tx) => println!("[MS] Tx mined: {:?}", tx),
-                            Err(e) => println!("[MS] Tx error: {}", e),
-                        }
-                    }
-                    Err(e) => println!("[MS] Send error: {}", e),
-                }
-            });
-        
-            starting_nonce = starting_nonce + U256::from(1);            
+        while let Some(tx) = tx_receiver.recv().await {
+            if let Err(e) = executor.execute(tx).await {
+                println!("[MS] Sign error: {}", e);
+            }
         }
     });
-}
\ No newline at end of file
+}
@@ -0,0 +1,309 @@
+use std::collections::{BTreeMap, HashMap};
+
+use alloy_primitives::TxKind;
+use ethers::types::{Bloom, Bytes, Transaction, Withdrawal, H160, H256, U256};
+use ethers::utils::rlp::{Decodable, Rlp};
+use revm_trace::SimulationTx;
+
+use crate::errors::FlashblockError;
+use crate::types::{ExecutionPayloadBaseV1, FlashblockReceipt, FlashblocksPayloadV1, LogItem, PendingCall};
+
+/// Type of OP stack deposit transactions
+pub const DEPOSIT_TX_TYPE: u8 = 0x7e;
+
+/// A transaction of the pending block with its receipt.
+#[derive(Clone, Debug)]
+pub struct PendingTransaction {
+    pub hash: H256,
+    /// Position in the block
+    pub index: usize,
+    /// Index of the flashblock that added it
+    pub flashblock_index: u64,
+    /// The signed transaction envelope
+    pub raw: Bytes,
+    pub gas_used: u64,
+    /// Index in the block of the first log of the transaction
+    pub first_log_index: u64,
+    pub receipt: FlashblockReceipt,
+}
+
+impl PendingTransaction {
+    /// The call revm replays for this transaction. Signed transactions are
+    /// sent from the recovered signer, deposits from their `from` once their
+    /// ETH is minted to it.
+    pub fn pending_call(&self) -> Result<PendingCall, FlashblockError> {
+        let invalid = |reason: String| FlashblockError::InvalidTransaction(self.hash, reason);
+
+        let (from, to, mint, value, data) = match self.raw.first() {
+            Some(&DEPOSIT_TX_TYPE) => {
+                // rlp([source_hash, from, to, mint, value, gas, is_system_tx, data])
+                let rlp = Rlp::new(&self.raw[1..]);
+                let decode = |e| invalid(format!("bad deposit: {}", e));
+                let from = rlp.val_at::<H160>(1).map_err(decode)?;
+                let to = rlp.at(2).map_err(decode)?;
+                let to = if to.is_empty() { None } else { Some(to.as_val::<H160>().map_err(decode)?) };
+                let mint = rlp.val_at::<U256>(3).map_err(decode)?;
+                (from, to, mint, rlp.val_at::<U256>(4).map_err(decode)?, rlp.val_at::<Vec<u8>>(7).map_err(decode)?)
+            }
+            Some(0x01 | 0x02 | 0xc0..=0xff) => {
+                let tx = Transaction::decode(&Rlp::new(&self.raw)).map_err(|e| invalid(format!("bad transaction: {}", e)))?;
+                let from = tx.recover_from().map_err(|e| invalid(format!("bad signature: {}", e)))?;
+                (from, tx.to, U256::zero(), tx.value, tx.input.to_vec())
+            }
+            Some(tx_type) => return Err(invalid(format!("unsupported transaction type {}", tx_type))),
+            None => return Err(invalid("empty transaction".to_string())),
+        };
+
+        let tx = SimulationTx {
+            caller: from.0.into(),
+            transact_to: to.map_or(TxKind::Create, |to| TxKind::Call(to.0.into())),
+            value: alloy_primitives::U256::from_limbs(value.0),
+            data: data.into(),
+        };
+        Ok(PendingCall { tx, mint })
+    }
+}
+
+/// A log of the pending block.
+#[derive(Clone, Copy, Debug)]
+pub struct PendingLog<'a> {
+    /// Index of the log in the block
+    pub log_index: u64,
+    pub transaction: &'a PendingTransaction,
+    pub log: &'a LogItem,
+}
+
+/// What [FlashblockAccumulator::apply] did with a flashblock.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Progress {
+    /// The flashblock was applied, with any queued ones that followed it.
+    /// `index` is the last flashblock applied.
+    Applied { index: u64, new_block: bool },
+    /// An earlier flashblock of its block is still missing
+    Queued,
+    /// Already applied, or of an older or failed block
+    Ignored,
+}
+
+/// Rebuilds the pending block from its flashblocks.
+///
+/// Flashblock 0 carries the base of a block and starts it over, the others
+/// add their transactions and receipts in index order and replace the roots,
+/// bloom and gas used. Flashblocks that arrive ahead of a missing one are
+/// queued until it shows up. A block with a flashblock that can't be applied
+/// is dropped as a whole, so the pending block is never partially wrong.
+#[derive(Debug, Default)]
+pub struct FlashblockAccumulator {
+    base: Option<ExecutionPayloadBaseV1>,
+    // Block being rebuilt, or that failed to
+    block_number: u64,
+    // Index of the next flashblock to apply
+    next_index: u64,
+    transactions: Vec<PendingTransaction>,
+    withdrawals: Vec<Withdrawal>,
+    balances: HashMap<H160, U256>,
+    state_root: H256,
+    receipts_root: H256,
+    logs_bloom: Bloom,
+    gas_used: u64,
+    block_hash: H256,
+    withdrawals_root: H256,
+    // (block_number, index) -> flashblock that arrived early
+    queued: BTreeMap<(u64, u64), FlashblocksPayloadV1>,
+    // Set when the current block failed, its flashblocks are ignored
+    failed: bool,
+}
+
+impl FlashblockAccumulator {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Applies `flashblock`. On an error the block it belongs to is dropped.
+    pub fn apply(&mut self, flashblock: FlashblocksPayloadV1) -> Result<Progress, FlashblockError> {
+        let key = (flashblock.metadata.block_number, flashblock.index);
+        let current = self.block_number;
+
+        let stale = key.0 < current
+            || (key.0 == current && (self.failed || key.1 < self.next_index));
+        if stale {
+            return Ok(Progress::Ignored);
+        }
+
+        let next = if key.0 == current { self.next_index } else { 0 };
+        if key.1 != next {
+            self.queued.insert(key, flashblock);
+            return Ok(Progress::Queued);
+        }
+
+        let new_block = key.0 != current;
+        let mut pending = Some(flashblock);
+        while let Some(flashblock) = pending {
+            let index = flashblock.index;
+            if let Err(e) = self.apply_next(flashblock) {
+                self.fail(key.0);
+                return Err(e);
+            }
+
+            pending = self.queued.remove(&(self.block_number, index + 1));
+        }
+
+        // Whatever is still queued for older blocks will never apply
+        self.queued = self.queued.split_off(&(self.block_number, 0));
+
+        Ok(Progress::Applied { index: self.next_index - 1, new_block })
+    }
+
+    // Applies the flashblock that is next in order
+    fn apply_next(&mut self, flashblock: FlashblocksPayloadV1) -> Result<(), FlashblockError> {
+        let block_number = flashblock.metadata.block_number;
+        let index = flashblock.index;
+
+        // Everything is checked before the block is touched
+        if flashblock.metadata.receipts.len() != flashblock.diff.transactions.len() {
+            return Err(FlashblockError::ReceiptCount {
+                block_number,
+                index,
+                transactions: flashblock.diff.transactions.len(),
+                receipts: flashblock.metadata.receipts.len(),
+            });
+        }
+
+        let receipts = flashblock.diff.transactions.iter()
+            .map(|raw| match flashblock.metadata.receipt_of(raw) {
+                (hash, Some(receipt)) => Ok((hash, receipt.clone())),
+                (hash, None) => Err(FlashblockError::MissingReceipt { block_number, index, hash }),
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        if index == 0 {
+            let base = flashblock.base.ok_or(FlashblockError::MissingBase(block_number))?;
+            self.reset(block_number);
+            self.base = Some(base);
+        }
+
+        for (raw, (hash, receipt)) in flashblock.diff.transactions.iter().zip(receipts) {
+            let (previous_gas, first_log_index) = self.transactions.last()
+                .map(|tx| (tx.receipt.cumulative_gas_used(), tx.first_log_index + tx.receipt.logs().len() as u64))
+                .unwrap_or_default();
+
+            self.transactions.push(PendingTransaction {
+                hash,
+                index: self.transactions.len(),
+                flashblock_index: index,
+                raw: raw.clone(),
+                gas_used: receipt.cumulative_gas_used().saturating_sub(previous_gas),
+                first_log_index,
+                receipt,
+            });
+        }
+
+        let diff = flashblock.diff;
+        self.withdrawals.extend(diff.withdrawals);
+        self.balances.extend(flashblock.metadata.new_account_balances);
+        self.state_root = diff.state_root;
+        self.receipts_root = diff.receipts_root;
+        self.logs_bloom = diff.logs_bloom;
+        self.gas_used = diff.gas_used;
+        self.block_hash = diff.block_hash;
+        self.withdrawals_root = diff.withdrawals_root;
+        self.next_index = index + 1;
+
+        Ok(())
+    }
+
+    fn reset(&mut self, block_number: u64) {
+        let queued = std::mem::take(&mut self.queued);
+        *self = Self { block_number, queued, ..Self::default() };
+    }
+
+    fn fail(&mut self, block_number: u64) {
+        self.reset(block_number);
+        self.failed = true;
+        self.queued.retain(|(queued_block, _), _| *queued_block > block_number);
+    }
+
+    /// The base of the pending block, `None` until one was applied.
+    pub fn base(&self) -> Option<&ExecutionPayloadBaseV1> {
+        self.base.as_ref()
+    }
+
+    pub fn block_number(&self) -> Option<u64> {
+        self.base.as_ref().map(|_| self.block_number)
+    }
+
+    /// Index of the last flashblock applied.
+    pub fn flashblock_index(&self) -> Option<u64> {
+        self.base.as_ref().map(|_| self.next_index - 1)
+    }
+
+    /// All transactions of the pending block, in block order.
+    pub fn transactions(&self) -> &[PendingTransaction] {
+        &self.transactions
+    }
+
+    pub fn transaction(&self, hash: H256) -> Option<&PendingTransaction> {
+        self.transactions.iter().find(|tx| tx.hash == hash)
+    }
+
+    /// All logs of the pending block, in block order.
+    pub fn logs(&self) -> impl Iterator<Item = PendingLog<'_>> {
+        self.transactions.iter().flat_map(|transaction| {
+            transaction.receipt.logs().iter().zip(transaction.first_log_index..).map(move |(log, log_index)| {
+                PendingLog { log_index, transaction, log }
+            })
+        })
+    }
+
+    pub fn logs_by_address(&self, address: H160) -> impl Iterator<Item = PendingLog<'_>> {
+        self.logs().filter(move |pending| pending.log.address == address)
+    }
+
+    /// Logs whose first topic, the event signature, is `topic0`.
+    pub fn logs_by_topic(&self, topic0: H256) -> impl Iterator<Item = PendingLog<'_>> {
+        self.logs().filter(move |pending| pending.log.topics.first() == Some(&topic0))
+    }
+
+    /// Gas used by the transactions of the pending block, from their receipts.
+    pub fn cumulative_gas_used(&self) -> u64 {
+        self.transactions.last().map(|tx| tx.receipt.cumulative_gas_used()).unwrap_or_default()
+    }
+
+    /// Gas used as reported by the last flashblock.
+    pub fn gas_used(&self) -> u64 {
+        self.gas_used
+    }
+
+    pub fn state_root(&self) -> H256 {
+        self.state_root
+    }
+
+    pub fn receipts_root(&self) -> H256 {
+        self.receipts_root
+    }
+
+    pub fn logs_bloom(&self) -> Bloom {
+        self.logs_bloom
+    }
+
+    pub fn block_hash(&self) -> H256 {
+        self.block_hash
+    }
+
+    pub fn withdrawals(&self) -> &[Withdrawal] {
+        &self.withdrawals
+    }
+
+    pub fn withdrawals_root(&self) -> H256 {
+        self.withdrawals_root
+    }
+
+    /// Balance of `address` after the pending block, if a flashblock changed it.
+    pub fn balance(&self, address: H160) -> Option<U256> {
+        self.balances.get(&address).copied()
+    }
+
+    pub fn balances(&self) -> &HashMap<H160, U256> {
+        &self.balances
+    }
+}
@@ -1,6 +1,16 @@
 pub mod types;
+pub mod config;
 pub mod errors;
 pub mod abi;
+pub mod engine;
+pub mod flashblock_accumulator;
+pub mod swap_events;
+pub mod uniswap_v4;
+pub mod pool_catalog;
+pub mod tokens;
+pub mod price_math;
+pub mod cl_quoter;
+pub mod trade_search;
 
 pub mod executors;
 pub mod collectors;
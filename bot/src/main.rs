@@ -1,138 +1,407 @@
-use std::env;
-use std::fs::read_to_string;
-use std::str::FromStr;
+mod cli;
+
+use std::path::Path;
 use std::sync::Arc;
 
+use anyhow::{bail, Result};
+use clap::Parser;
+use ethers::core::rand::thread_rng;
 use ethers::signers::{LocalWallet, Signer};
-use ethers::types::H160;
-use ethers::utils::WEI_IN_ETHER;
 use ethers_providers::{Http, Middleware};
 use ethers_providers::Provider;
-use ms_bot::executors::sequencer_executor::start_sequencer_executor;
-use ms_bot::executors::titan_executor::start_titan_executor;
+use ms_bot::collectors::arbitrum_feed::ArbitrumCodec;
+use ms_bot::collectors::arbitrum_feed_collector::ArbitrumFeedCollector;
+use ms_bot::collectors::binance_collector::BinanceCollector;
+use ms_bot::collectors::block_collector::BlockCollector;
+use ms_bot::collectors::chain_check::ChainCheck;
+use ms_bot::collectors::feed_clients::FeedEndpoint;
+use ms_bot::collectors::flash_block_collector::FlashBlockCollector;
+use ms_bot::collectors::replay_collector::ReplayCollector;
+use ms_bot::config::{to_wei, CommonConfig, Config, Platform};
+use ms_bot::engine::Engine;
+use ms_bot::executors::sequencer_executor::SequencerExecutor;
+use ms_bot::executors::titan_executor::TitanExecutor;
 use ms_bot::strategies::base_strategy::BaseStrategy;
 use ms_bot::strategies::mainnet_strategy::MainnetStrategy;
 use ms_bot::strategies::op_strategy::OpStrategy;
-use serde::Deserialize;
-use serde::Serialize;
-
-
-#[derive(Clone, Debug, Serialize, Deserialize)]
-pub struct Config {
-    pub chain_id: u64,
-    pub rpc_url: String,
-    pub ws_url: String,
-    pub flashblocks_url: String,
-    pub trade_server_url: String,
-    pub mevshare_url: String,
-    pub sequencer_url: String,
-    pub from_addr: String,
-    pub to_addr: String,
-    pub simulator_addr: String,
-    pub data: String,
-    pub simulation_mode: bool,
-    pub test_mode: bool,
-    pub gas_limit: u64,
-    pub step_count: u64,
-    pub regression_count: u64,
-    pub min_profit: u64,
-    pub min_swap_amount: u64,
-    pub exeuction_mode: bool,
-}
+use ms_bot::types::{format_trade_info_table, Action, CollectorMap, Event, ExecutorMap};
+
+use crate::cli::{Cli, Command, KeystoreCommand, Replay, Target};
 
 #[tokio::main]
 async fn main() {
-    let args: Vec<String> = env::args().collect();
-    let platform = &args[1];
+    let cli = Cli::parse();
+
+    let result = match cli.command {
+        Command::Run { target, simulation_mode, execution_mode, record, replay, replay_speed } => {
+            let replay = replay.map(|path| Replay { path, speed: replay_speed });
+            run(target, simulation_mode, execution_mode, record, replay).await
+        }
+        Command::SimulateOnce { target, block } => simulate_once(target, block).await,
+        Command::CheckConfig { target } => check_config(target),
+        Command::Keystore { command } => keystore(command),
+    };
+
+    if let Err(e) = result {
+        eprintln!("{e}");
+        std::process::exit(1);
+    }
+}
+
+fn load_config(target: &Target) -> Result<Config> {
+    Ok(Config::load(&target.config_path(), target.platform)?)
+}
+
+fn load_wallet(common: &CommonConfig) -> Result<LocalWallet> {
+    let password = rpassword::prompt_password("")?;
+    let wallet = LocalWallet::decrypt_keystore(&common.keystore, password)?;
+    Ok(wallet.with_chain_id(common.chain_id))
+}
+
+async fn add_sequencer_executor(
+    engine: &mut Engine<Event, Action>,
+    common: &CommonConfig,
+    rpc_client: Arc<Provider<Http>>,
+    sequencer_url: String,
+) -> Result<()> {
+    println!("Adding sequencer executor...");
+    let wallet = load_wallet(common)?;
+
+    let nonce = rpc_client.get_transaction_count(common.from_addr, None).await?;
+    println!("Nonce: {:?}", nonce);
+    let executor = Box::new(SequencerExecutor::new(sequencer_url, wallet, common.chain_id, nonce));
+    executor.verify_chain_id().await?;
+    let executor = ExecutorMap::new(executor, |action| match action {
+        Action::SubmitTx(tx) => Some(tx),
+        _ => None,
+    });
+    engine.add_executor(Box::new(executor));
+
+    Ok(())
+}
+
+async fn run(
+    target: Target,
+    simulation_mode: Option<bool>,
+    execution_mode: Option<bool>,
+    record: Option<String>,
+    replay: Option<Replay>,
+) -> Result<()> {
+    let platform = target.platform;
     println!("Starting bot on {platform}");
+    if platform != Platform::Base && (record.is_some() || replay.is_some()) {
+        bail!("--record and --replay are only supported on base");
+    }
+    // The Arbitrum feed carries no receipts, so pool prices can't be followed to execute trades
+    if platform == Platform::Arbitrum && (execution_mode == Some(true) || simulation_mode == Some(false)) {
+        bail!("arbitrum only runs in simulation mode, its feed carries no receipts to follow pool prices with");
+    }
 
-    let cfg = read_to_string(format!("config_{platform}.toml")).unwrap();
-    let cfg: Config = toml::de::from_str(&cfg).unwrap();
-    let from_addr = H160::from_str(&cfg.from_addr).unwrap();
-    let to_addr = H160::from_str(&cfg.to_addr).unwrap();
-    let simulator_addr = H160::from_str(&cfg.simulator_addr).unwrap();
-
-    println!("Connecting {}", cfg.rpc_url);
-    let rpc_client = Arc::new(Provider::<Http>::try_from(cfg.rpc_url.clone()).unwrap());
-
-    if platform == "base" || platform == "op" {
-        let (tx_sender, tx_receiver) = tokio::sync::mpsc::unbounded_channel();
-
-        if cfg.exeuction_mode {
-            println!("Adding sequencer executor...");
-            let password = rpassword::prompt_password("").unwrap();
-            let wallet = LocalWallet::decrypt_keystore(cfg.data, password).unwrap();
-            let wallet = wallet.with_chain_id(cfg.chain_id);
-            
-            let nonce = rpc_client.clone().get_transaction_count(from_addr, None).await.unwrap();
-            println!("Nonce: {:?}", nonce);
-            start_sequencer_executor(cfg.sequencer_url, wallet, cfg.chain_id, nonce, tx_receiver).await;
+    let mut cfg = load_config(&target)?;
+    if let Some(base) = cfg.base.as_mut() {
+        base.simulation_mode = simulation_mode.unwrap_or(base.simulation_mode);
+        base.execution_mode = execution_mode.unwrap_or(base.execution_mode);
+        base.record_path = record.or(base.record_path.take());
+        if replay.is_some() {
+            // Recorded flashblocks are stale, they must never reach the sequencer
+            if execution_mode == Some(true) {
+                bail!("--replay can't be combined with --execution-mode true");
+            }
+            base.execution_mode = false;
+            base.check_replay()?;
         }
-            
-        if platform == "base" {
+    }
+    if let Some(op) = cfg.op.as_mut() {
+        op.execution_mode = execution_mode.unwrap_or(op.execution_mode);
+    }
+
+    let common = cfg.common;
+    let from_addr = common.from_addr;
+
+    println!("Connecting {}", common.rpc_url);
+    let rpc_client = Arc::new(Provider::<Http>::try_from(common.rpc_url.clone())?);
+
+    let mut engine: Engine<Event, Action> = Engine::default();
+
+    match platform {
+        Platform::Base => {
+            let base = cfg.base.expect("config is validated for the platform");
+            if base.execution_mode {
+                add_sequencer_executor(&mut engine, &common, rpc_client.clone(), base.sequencer_url.clone()).await?;
+            }
+
             println!("Adding base strategy...");
-    
-            let mut strategy = BaseStrategy::new(
-                cfg.rpc_url.clone(),
-                cfg.ws_url.clone(),
-                cfg.flashblocks_url.clone(),
-                cfg.trade_server_url.clone(),
-                rpc_client.clone(), 
-                from_addr, 
-                to_addr,
-                simulator_addr,
-                cfg.chain_id,
-                tx_sender.clone(),
-                cfg.gas_limit,
-                cfg.simulation_mode,
-                cfg.test_mode,
-                cfg.step_count,
-                cfg.regression_count,
-                WEI_IN_ETHER *  cfg.min_profit / 10000,
-                WEI_IN_ETHER *  cfg.min_swap_amount / 10000,
-            ).await;
-    
-            strategy.run().await;
-        }  else if platform == "op" {
+
+            if let Some(replay) = replay {
+                let replay_collector = Box::new(ReplayCollector::new(replay.path, replay.speed));
+                engine.add_collector(Box::new(CollectorMap::new(replay_collector, |fb| Event::FlashBlock(Box::new(fb)))));
+            } else {
+                if base.simulation_mode {
+                    let block_collector = Box::new(BlockCollector::new(common.ws_url.clone()));
+                    engine.add_collector(Box::new(CollectorMap::new(block_collector, Event::NewBlock)));
+                }
+                if !base.simulation_mode || base.simulate_pending {
+                    let chain_check = Arc::new(ChainCheck::new(common.chain_id, (*rpc_client).clone()));
+                    let flash_block_collector = Box::new(
+                        FlashBlockCollector::new(base.feed_endpoints(), common.chain_id)
+                            .with_record_path(base.record_path.clone())
+                            .with_reconnect_policy((&base.reconnect).into())
+                            .with_chain_check(chain_check));
+                    engine.add_collector(Box::new(CollectorMap::new(flash_block_collector, |fb| Event::FlashBlock(Box::new(fb)))));
+                }
+            }
+
+            let strategy = BaseStrategy::new(
+                common.rpc_url.clone(),
+                common.ws_url.clone(),
+                base.flashblocks_url.clone(),
+                base.trade_server_url.clone(),
+                rpc_client.clone(),
+                from_addr,
+                base.to_addr,
+                base.simulator_addr,
+                common.chain_id,
+                common.gas_limit,
+                base.simulation_mode,
+                base.simulate_pending,
+                common.test_mode,
+                to_wei(base.min_profit),
+                to_wei(base.min_swap_amount),
+            ).await
+            .with_pool_cache(base.pool_cache_path.clone())
+            .with_native_quoting(base.native_quoting)
+            .with_search_params(base.search_params());
+
+            engine.add_strategy(Box::new(strategy));
+        }
+        Platform::Arbitrum => {
+            let arbitrum = cfg.arbitrum.expect("config is validated for the platform");
+
+            println!("Adding base strategy...");
+
+            let block_collector = Box::new(BlockCollector::new(common.ws_url.clone()));
+            engine.add_collector(Box::new(CollectorMap::new(block_collector, Event::NewBlock)));
+            if arbitrum.simulate_pending {
+                let chain_check = Arc::new(ChainCheck::new(common.chain_id, (*rpc_client).clone()));
+                let arbitrum_feed_collector = Box::new(
+                    ArbitrumFeedCollector::new(vec![FeedEndpoint::new(arbitrum.feed_url.clone(), 0)], common.chain_id)
+                        .with_codec(ArbitrumCodec::new(arbitrum.genesis_block))
+                        .with_reconnect_policy((&arbitrum.reconnect).into())
+                        .with_chain_check(chain_check));
+                engine.add_collector(Box::new(CollectorMap::new(arbitrum_feed_collector, Event::ArbitrumFeed)));
+            }
+
+            let strategy = BaseStrategy::new(
+                common.rpc_url.clone(),
+                common.ws_url.clone(),
+                arbitrum.feed_url.clone(),
+                arbitrum.trade_server_url.clone(),
+                rpc_client.clone(),
+                from_addr,
+                arbitrum.to_addr,
+                arbitrum.simulator_addr,
+                common.chain_id,
+                common.gas_limit,
+                true,
+                arbitrum.simulate_pending,
+                common.test_mode,
+                to_wei(arbitrum.min_profit),
+                to_wei(arbitrum.min_swap_amount),
+            ).await
+            .with_native_quoting(arbitrum.native_quoting)
+            .with_search_params(arbitrum.search_params());
+
+            engine.add_strategy(Box::new(strategy));
+        }
+        Platform::Op => {
+            let op = cfg.op.expect("config is validated for the platform");
+            if op.execution_mode {
+                add_sequencer_executor(&mut engine, &common, rpc_client.clone(), op.sequencer_url.clone()).await?;
+            }
+
             println!("Adding op strategy...");
-    
-            let mut strategy = OpStrategy::new(
-                cfg.rpc_url.clone(),
+
+            let block_collector = Box::new(BlockCollector::new(common.ws_url.clone()));
+            engine.add_collector(Box::new(CollectorMap::new(block_collector, Event::NewBlock)));
+
+            let binance_collector = Box::new(BinanceCollector::new(vec!["ETHUSDT".to_string(), "OPUSDT".to_string()]));
+            engine.add_collector(Box::new(CollectorMap::new(binance_collector, Event::BinancePrice)));
+
+            let strategy = OpStrategy::new(
+                common.rpc_url.clone(),
+                rpc_client.clone(),
+                from_addr,
+                common.chain_id,
+                op.launchers.iter().map(Into::into).collect(),
+                common.gas_limit,
+                common.test_mode
+            ).await
+            .with_search_params((&op.search).into());
+
+            engine.add_strategy(Box::new(strategy));
+        }
+        Platform::Mainnet => {
+            let mainnet = cfg.mainnet.expect("config is validated for the platform");
+            let wallet = load_wallet(&common)?;
+            println!("Adding titan executor...");
+            let executor = Box::new(TitanExecutor::new(rpc_client.clone(), wallet, common.chain_id));
+            let executor = ExecutorMap::new(executor, |action| match action {
+                Action::SubmitBundle(tx, target_pools, block_number) => Some((tx, target_pools, block_number)),
+                _ => None,
+            });
+            engine.add_executor(Box::new(executor));
+
+            let block_collector = Box::new(BlockCollector::new(common.ws_url.clone()));
+            engine.add_collector(Box::new(CollectorMap::new(block_collector, Event::NewBlock)));
+
+            println!("Adding mainnet strategy...");
+            let strategy = MainnetStrategy::new(
                 rpc_client.clone(),
+                mainnet.mevshare_url,
                 from_addr,
-                cfg.chain_id,
-                cfg.ws_url,
-                tx_sender.clone(),
-                cfg.gas_limit,
-                cfg.test_mode
+                mainnet.to_addr,
+                mainnet.simulator_addr,
+                mainnet.launcher_addr,
+                common.gas_limit,
+                common.test_mode,
+                mainnet.step_count,
+                mainnet.regression_count,
             ).await;
-    
-            strategy.run().await;
-        }        
-    } else if platform == "mainnet" {
-        let (tx_sender, tx_receiver) = tokio::sync::mpsc::unbounded_channel();
-
-        let password = rpassword::prompt_password("").unwrap();
-        let wallet = LocalWallet::decrypt_keystore(cfg.data, password).unwrap();
-        let wallet = wallet.with_chain_id(cfg.chain_id);
-        println!("Adding titan executor...");
-        start_titan_executor(rpc_client.clone(), wallet, cfg.chain_id, tx_receiver).await;
-
-        println!("Adding mainnet strategy...");
-        let mut strategy = MainnetStrategy::new(    
-            rpc_client.clone(), 
-            cfg.ws_url, 
-            cfg.mevshare_url,
-            from_addr, 
-            to_addr,
-            simulator_addr,
-            tx_sender.clone(),
-            cfg.gas_limit,
-            cfg.test_mode,
-            cfg.step_count,
-            cfg.regression_count,
-        ).await;
-
-        strategy.run().await;
+
+            engine.add_strategy(Box::new(strategy));
+        }
+    }
+
+    // Start engine
+    let mut set = engine.run().await?;
+    while let Some(res) = set.join_next().await {
+        println!("Engine task finished: {:?}", res);
     }
-}
\ No newline at end of file
+
+    Ok(())
+}
+
+async fn simulate_once(target: Target, block_number: u64) -> Result<()> {
+    let cfg = load_config(&target)?;
+    let common = cfg.common;
+    let rpc_client = Arc::new(Provider::<Http>::try_from(common.rpc_url.clone())?);
+
+    println!("Simulating block {block_number} on {}", target.platform);
+
+    let trade_info_list = match target.platform {
+        Platform::Base => {
+            let base = cfg.base.expect("config is validated for the platform");
+            let mut strategy = BaseStrategy::new(
+                common.rpc_url.clone(),
+                common.ws_url.clone(),
+                base.flashblocks_url.clone(),
+                base.trade_server_url.clone(),
+                rpc_client.clone(),
+                common.from_addr,
+                base.to_addr,
+                base.simulator_addr,
+                common.chain_id,
+                common.gas_limit,
+                true,
+                false,
+                common.test_mode,
+                to_wei(base.min_profit),
+                to_wei(base.min_swap_amount),
+            ).await
+            .with_native_quoting(base.native_quoting)
+            .with_search_params(base.search_params());
+            strategy.load_tokens().await?;
+
+            strategy.simulate_block(block_number).await?
+        }
+        Platform::Arbitrum => {
+            let arbitrum = cfg.arbitrum.expect("config is validated for the platform");
+            let mut strategy = BaseStrategy::new(
+                common.rpc_url.clone(),
+                common.ws_url.clone(),
+                arbitrum.feed_url.clone(),
+                arbitrum.trade_server_url.clone(),
+                rpc_client.clone(),
+                common.from_addr,
+                arbitrum.to_addr,
+                arbitrum.simulator_addr,
+                common.chain_id,
+                common.gas_limit,
+                true,
+                false,
+                common.test_mode,
+                to_wei(arbitrum.min_profit),
+                to_wei(arbitrum.min_swap_amount),
+            ).await
+            .with_native_quoting(arbitrum.native_quoting)
+            .with_search_params(arbitrum.search_params());
+            strategy.load_tokens().await?;
+
+            strategy.simulate_block(block_number).await?
+        }
+        Platform::Op => {
+            let op = cfg.op.expect("config is validated for the platform");
+            let mut strategy = OpStrategy::new(
+                common.rpc_url.clone(),
+                rpc_client.clone(),
+                common.from_addr,
+                common.chain_id,
+                op.launchers.iter().map(Into::into).collect(),
+                common.gas_limit,
+                common.test_mode
+            ).await
+            .with_search_params((&op.search).into());
+            strategy.load_tokens().await?;
+
+            strategy.simulate_block(block_number).await?
+        }
+        Platform::Mainnet => bail!("simulate-once is not supported on mainnet"),
+    };
+
+    print!("{}", format_trade_info_table(&trade_info_list));
+    Ok(())
+}
+
+fn check_config(target: Target) -> Result<()> {
+    let path = target.config_path();
+    let cfg = load_config(&target)?;
+    let common = cfg.common;
+
+    println!("{path} is valid for {}", target.platform);
+    println!("Chain id: {}", common.chain_id);
+    println!("RPC: {}", common.rpc_url);
+    println!("From: {:?}", common.from_addr);
+    println!("Keystore: {}{}", common.keystore,
+        if Path::new(&common.keystore).exists() { "" } else { " (not found)" });
+
+    if let (Platform::Op, Some(op)) = (target.platform, cfg.op) {
+        for launcher in op.launchers {
+            println!("Launcher {}: {:?}", launcher.name, launcher.launcher_addr);
+        }
+    }
+
+    Ok(())
+}
+
+fn keystore(command: KeystoreCommand) -> Result<()> {
+    match command {
+        KeystoreCommand::Inspect { path } => {
+            let password = rpassword::prompt_password("Password: ")?;
+            let wallet = LocalWallet::decrypt_keystore(&path, password)?;
+            println!("Address: {:?}", wallet.address());
+        }
+        KeystoreCommand::New { dir, name } => {
+            let password = rpassword::prompt_password("Password: ")?;
+            if password != rpassword::prompt_password("Repeat password: ")? {
+                bail!("Passwords do not match");
+            }
+
+            let (wallet, uuid) = LocalWallet::new_keystore(&dir, &mut thread_rng(), password, name.as_deref())?;
+            let file_name = name.unwrap_or(uuid);
+            println!("Address: {:?}", wallet.address());
+            println!("Keystore: {}", Path::new(&dir).join(file_name).display());
+        }
+    }
+
+    Ok(())
+}
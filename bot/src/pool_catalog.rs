@@ -0,0 +1,369 @@
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ethers::types::{H160, H256};
+use ethers_providers::{Http, Middleware, Provider};
+use serde::{Deserialize, Serialize};
+
+use crate::abi::{IPool, MSLauncher, IERC20};
+use crate::errors::PoolCatalogError;
+use crate::types::PoolType;
+use crate::uniswap_v4::{PoolKey, V4Pool};
+
+/// WETH on OP stack chains, which V4 pools trade as native ETH
+pub const OP_STACK_WETH: H160 = H160([
+    0x42, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+    0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x06,
+]);
+
+/// The V4 pool a launcher is set up for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct LauncherV4Pool {
+    pub manager: H160,
+    pub id: H256,
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub hooks: H160,
+}
+
+impl LauncherV4Pool {
+    /// The key of the pool, of the launcher tokens `base` and `quote` or of
+    /// native ETH in place of WETH, whichever hashes to `id`.
+    pub fn key(&self, base: H160, quote: H160) -> Option<PoolKey> {
+        let native = |token: H160| if token == OP_STACK_WETH { H160::zero() } else { token };
+        [(native(base), native(quote)), (base, quote)].into_iter()
+            .map(|(a, b)| PoolKey::new(a, b, self.fee, self.tick_spacing, self.hooks))
+            .find(|key| key.id() == self.id)
+    }
+}
+
+/// What the catalog reads from the chain, normally the launcher and the RPC
+/// node.
+#[async_trait]
+pub trait PoolSource: Send + Sync {
+    fn launcher(&self) -> H160;
+    async fn pool_count(&self) -> Result<usize, PoolCatalogError>;
+    /// The pool at `index` and its launcher pool type.
+    async fn pool_info(&self, index: usize) -> Result<(H160, u8), PoolCatalogError>;
+    /// The base and quote tokens of the launcher.
+    async fn launcher_tokens(&self) -> Result<(H160, H160), PoolCatalogError>;
+    async fn launcher_v4_pool(&self) -> Result<LauncherV4Pool, PoolCatalogError>;
+    async fn has_code(&self, address: H160) -> Result<bool, PoolCatalogError>;
+    async fn pool_tokens(&self, pool: H160) -> Result<(H160, H160), PoolCatalogError>;
+    async fn decimals(&self, token: H160) -> Result<u8, PoolCatalogError>;
+}
+
+/// Reads the pools of an `MSLauncher` over RPC.
+pub struct LauncherSource {
+    client: Arc<Provider<Http>>,
+    launcher: MSLauncher<Provider<Http>>,
+}
+
+impl LauncherSource {
+    pub fn new(launcher: H160, client: Arc<Provider<Http>>) -> Self {
+        Self { launcher: MSLauncher::new(launcher, client.clone()), client }
+    }
+}
+
+fn rpc(e: impl std::fmt::Display) -> PoolCatalogError {
+    PoolCatalogError::Rpc(e.to_string())
+}
+
+#[async_trait]
+impl PoolSource for LauncherSource {
+    fn launcher(&self) -> H160 {
+        self.launcher.address()
+    }
+
+    async fn pool_count(&self) -> Result<usize, PoolCatalogError> {
+        Ok(self.launcher.get_pool_count().call().await.map_err(rpc)?.as_usize())
+    }
+
+    async fn pool_info(&self, index: usize) -> Result<(H160, u8), PoolCatalogError> {
+        self.launcher.get_pool_info(index.into()).call().await.map_err(rpc)
+    }
+
+    async fn launcher_tokens(&self) -> Result<(H160, H160), PoolCatalogError> {
+        let base = self.launcher.get_base_token().call().await.map_err(rpc)?;
+        let quote = self.launcher.get_quote_token().call().await.map_err(rpc)?;
+        Ok((base, quote))
+    }
+
+    async fn launcher_v4_pool(&self) -> Result<LauncherV4Pool, PoolCatalogError> {
+        Ok(LauncherV4Pool {
+            manager: self.launcher.v_4_pool_manager().call().await.map_err(rpc)?,
+            id: H256::from(self.launcher.v_4_pool_id().call().await.map_err(rpc)?),
+            fee: self.launcher.v_4_fee().call().await.map_err(rpc)?,
+            tick_spacing: self.launcher.v_4_tick_spacing().call().await.map_err(rpc)?,
+            hooks: self.launcher.v_4_hooks().call().await.map_err(rpc)?,
+        })
+    }
+
+    async fn has_code(&self, address: H160) -> Result<bool, PoolCatalogError> {
+        Ok(!self.client.get_code(address, None).await.map_err(rpc)?.is_empty())
+    }
+
+    async fn pool_tokens(&self, pool: H160) -> Result<(H160, H160), PoolCatalogError> {
+        let pool = IPool::new(pool, self.client.clone());
+        Ok((pool.token_0().call().await.map_err(rpc)?, pool.token_1().call().await.map_err(rpc)?))
+    }
+
+    async fn decimals(&self, token: H160) -> Result<u8, PoolCatalogError> {
+        IERC20::new(token, self.client.clone()).decimals().call().await.map_err(rpc)
+    }
+}
+
+/// How often and how fast failed RPC calls are retried.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    /// Calls made before giving up, the first included
+    pub attempts: u32,
+    /// Delay before the first retry, doubled after every failure
+    pub initial_delay: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self { attempts: 3, initial_delay: Duration::from_millis(500) }
+    }
+}
+
+impl RetryPolicy {
+    /// Runs `call` until it succeeds, or fails with an error other than an
+    /// RPC failure, or `attempts` times.
+    pub async fn run<T, F, Fut>(&self, mut call: F) -> Result<T, PoolCatalogError>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, PoolCatalogError>>,
+    {
+        let mut delay = self.initial_delay;
+        let mut attempt = 1;
+        loop {
+            match call().await {
+                Err(PoolCatalogError::Rpc(e)) if attempt < self.attempts => {
+                    println!("RPC call failed ({}), retrying in {:?}", e, delay);
+                    tokio::time::sleep(delay).await;
+                    delay *= 2;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// A token of a pool.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenInfo {
+    /// The zero address for native ETH
+    pub address: H160,
+    pub decimals: u8,
+}
+
+/// A pool of the launcher.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CatalogPool {
+    /// Index of the pool in the launcher
+    pub index: usize,
+    /// Where the pool logs from, the PoolManager for a V4 pool
+    pub address: H160,
+    pub pool_type: PoolType,
+    pub token0: TokenInfo,
+    pub token1: TokenInfo,
+    pub v4_pool: Option<V4Pool>,
+}
+
+/// The pools a launcher trades, checked against the chain.
+///
+/// Pools are loaded from the launcher with their tokens and decimals, and
+/// can be cached to disk so only the checks are run on the next start.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolCatalog {
+    pub chain_id: u64,
+    pub launcher: H160,
+    pub pools: Vec<CatalogPool>,
+}
+
+impl PoolCatalog {
+    /// Uses the catalog cached at `cache_path` if it is of the same chain and
+    /// launcher, and loads it from `source` otherwise or once the launcher's
+    /// pools changed. Either way the pools are validated before the catalog
+    /// is returned and cached again.
+    pub async fn load(
+        cache_path: Option<&str>,
+        chain_id: u64,
+        source: &impl PoolSource,
+        retry: RetryPolicy,
+    ) -> Result<Self, PoolCatalogError> {
+        let cached = match cache_path {
+            Some(path) => match Self::read(path) {
+                Ok(catalog) if catalog.chain_id == chain_id && catalog.launcher == source.launcher() => Some(catalog),
+                Ok(_) => None,
+                Err(PoolCatalogError::IO(e)) if e.kind() == std::io::ErrorKind::NotFound => None,
+                Err(e) => {
+                    println!("Ignoring pool cache {}: {}", path, e);
+                    None
+                }
+            },
+            None => None,
+        };
+
+        let validated = match cached {
+            Some(catalog) => match catalog.validate(source, retry).await {
+                Ok(()) => Some(catalog),
+                Err(PoolCatalogError::Stale(reason)) => {
+                    println!("Reloading the pool cache: {}", reason);
+                    None
+                }
+                Err(e) => return Err(e),
+            },
+            None => None,
+        };
+
+        let catalog = match validated {
+            Some(catalog) => catalog,
+            None => {
+                let catalog = Self::fetch(chain_id, source, retry).await?;
+                catalog.validate(source, retry).await?;
+                catalog
+            }
+        };
+
+        if let Some(path) = cache_path {
+            catalog.write(path)?;
+        }
+
+        Ok(catalog)
+    }
+
+    /// Loads the pools of the launcher of `source`.
+    pub async fn fetch(chain_id: u64, source: &impl PoolSource, retry: RetryPolicy) -> Result<Self, PoolCatalogError> {
+        let pool_count = retry.run(|| source.pool_count()).await?;
+
+        let mut pools = Vec::with_capacity(pool_count);
+        for index in 0..pool_count {
+            let (address, pool_type) = retry.run(|| source.pool_info(index)).await?;
+            let pool_type = PoolType::try_from(pool_type).map_err(|source| PoolCatalogError::PoolType { index, source })?;
+
+            let (address, tokens, v4_pool) = if pool_type == PoolType::UniswapV4 {
+                let v4_pool = load_v4_pool(index, source, retry).await?;
+                let key = v4_pool.key.unwrap();
+                (v4_pool.manager, (key.currency0, key.currency1), Some(v4_pool))
+            } else {
+                (address, retry.run(|| source.pool_tokens(address)).await?, None)
+            };
+
+            pools.push(CatalogPool {
+                index,
+                address,
+                pool_type,
+                token0: token_info(tokens.0, source, retry).await?,
+                token1: token_info(tokens.1, source, retry).await?,
+                v4_pool,
+            });
+        }
+
+        Ok(Self { chain_id, launcher: source.launcher(), pools })
+    }
+
+    /// Checks that the launcher still has the pools of the catalog, failing
+    /// with [PoolCatalogError::Stale] otherwise, that every pool has code and
+    /// trades the tokens of the launcher, and that pools cached with tokens
+    /// still trade them. V4 pools are checked against their cached key, which
+    /// has to hash to their id.
+    pub async fn validate(&self, source: &impl PoolSource, retry: RetryPolicy) -> Result<(), PoolCatalogError> {
+        let pool_count = retry.run(|| source.pool_count()).await?;
+        if pool_count != self.pools.len() {
+            return Err(PoolCatalogError::Stale(format!("the launcher has {} pools instead of {}", pool_count, self.pools.len())));
+        }
+
+        let (base, quote) = retry.run(|| source.launcher_tokens()).await?;
+        let mut launcher_v4_pool = None;
+
+        for pool in &self.pools {
+            let index = pool.index;
+            let (address, pool_type) = retry.run(|| source.pool_info(index)).await?;
+            if PoolType::try_from(pool_type).ok() != Some(pool.pool_type) {
+                return Err(PoolCatalogError::Stale(format!("pool {} is of type {} instead of {:?}", index, pool_type, pool.pool_type)));
+            }
+
+            // A V4 pool is the one the launcher is set up for, the others are at their address
+            match pool.v4_pool {
+                Some(v4_pool) => {
+                    if launcher_v4_pool.is_none() {
+                        launcher_v4_pool = Some(retry.run(|| source.launcher_v4_pool()).await?);
+                    }
+                    let launcher_v4_pool = launcher_v4_pool.unwrap();
+                    if (launcher_v4_pool.manager, launcher_v4_pool.id) != (pool.address, v4_pool.id) {
+                        return Err(PoolCatalogError::Stale(format!(
+                            "V4 pool {} is {:?} of {:?} instead of {:?} of {:?}",
+                            index, launcher_v4_pool.id, launcher_v4_pool.manager, v4_pool.id, pool.address)));
+                    }
+                }
+                None if address != pool.address => {
+                    return Err(PoolCatalogError::Stale(format!("pool {} is at {:?} instead of {:?}", index, address, pool.address)));
+                }
+                None => {}
+            }
+
+            if !retry.run(|| source.has_code(pool.address)).await? {
+                return Err(PoolCatalogError::NoCode { index, address: pool.address });
+            }
+
+            let tokens = (pool.token0.address, pool.token1.address);
+            let on_chain = match pool.v4_pool {
+                Some(V4Pool { id, key: Some(key), .. }) if key.id() == id => (key.currency0, key.currency1),
+                Some(v4_pool) => {
+                    return Err(PoolCatalogError::V4Pool { index, reason: format!("no key hashing to {:?}", v4_pool.id) });
+                }
+                None => retry.run(|| source.pool_tokens(pool.address)).await?,
+            };
+            if on_chain != tokens {
+                return Err(PoolCatalogError::TokenMismatch { index, expected: tokens, found: on_chain });
+            }
+
+            let trades_launcher_tokens = (same_token(tokens.0, base) && same_token(tokens.1, quote))
+                || (same_token(tokens.0, quote) && same_token(tokens.1, base));
+            if !trades_launcher_tokens {
+                return Err(PoolCatalogError::TokenMismatch { index, expected: (base, quote), found: tokens });
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn read(path: &str) -> Result<Self, PoolCatalogError> {
+        Ok(serde_json::from_slice(&std::fs::read(path)?)?)
+    }
+
+    pub fn write(&self, path: &str) -> Result<(), PoolCatalogError> {
+        std::fs::write(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+}
+
+// Native ETH stands for WETH
+fn same_token(pool_token: H160, token: H160) -> bool {
+    pool_token == token || (pool_token.is_zero() && token == OP_STACK_WETH)
+}
+
+async fn token_info(address: H160, source: &impl PoolSource, retry: RetryPolicy) -> Result<TokenInfo, PoolCatalogError> {
+    let decimals = if address.is_zero() { 18 } else { retry.run(|| source.decimals(address)).await? };
+    Ok(TokenInfo { address, decimals })
+}
+
+// The V4 pool of the launcher, pool `index`, with the key the launcher is
+// set up for, which has to hash to the pool id
+async fn load_v4_pool(index: usize, source: &impl PoolSource, retry: RetryPolicy) -> Result<V4Pool, PoolCatalogError> {
+    let launcher_pool = retry.run(|| source.launcher_v4_pool()).await?;
+    let (base, quote) = retry.run(|| source.launcher_tokens()).await?;
+
+    let key = launcher_pool.key(base, quote).ok_or_else(|| PoolCatalogError::V4Pool {
+        index,
+        reason: format!("no key of the launcher tokens with {:?} hashes to {:?}", launcher_pool, launcher_pool.id),
+    })?;
+
+    Ok(V4Pool { manager: launcher_pool.manager, id: launcher_pool.id, key: Some(key) })
+}
@@ -0,0 +1,364 @@
+use ethers::types::{I256, U256, U512};
+
+use crate::errors::MathError;
+use crate::tokens::TokenMetadata;
+
+/// The lowest tick of a Uniswap V3 style pool
+pub const MIN_TICK: i32 = -887272;
+/// The highest tick of a Uniswap V3 style pool
+pub const MAX_TICK: i32 = -MIN_TICK;
+
+/// `sqrt(1.0001^MIN_TICK) * 2^96`, the lowest sqrt price of a pool
+pub const MIN_SQRT_RATIO: U256 = U256([4295128739, 0, 0, 0]);
+/// `sqrt(1.0001^MAX_TICK) * 2^96`, one above the highest sqrt price of a pool
+pub const MAX_SQRT_RATIO: U256 = U256([0x5d951d5263988d26, 0xefd1fc6a50648849, 0xfffd8963, 0]);
+
+/// Prices are scaled by 10^PRICE_DECIMALS
+pub const PRICE_DECIMALS: i32 = 8;
+
+/// 2^96, the scale of sqrt prices
+pub const Q96: U256 = U256([0, 1 << 32, 0, 0]);
+
+/// Pool fees are in hundredths of a bip
+pub const FEE_DENOMINATOR: u32 = 1_000_000;
+
+// 1 / sqrt(1.0001^(2^i)) as Q128.128, for every bit i of a tick
+const TICK_RATIOS: [u128; 19] = [
+    0xfff97272373d413259a46990580e213a,
+    0xfff2e50f5f656932ef12357cf3c7fdcc,
+    0xffe5caca7e10e4e61c3624eaa0941cd0,
+    0xffcb9843d60f6159c9db58835c926644,
+    0xff973b41fa98c081472e6896dfb254c0,
+    0xff2ea16466c96a3843ec78b326b52861,
+    0xfe5dee046a99a2a811c461f1969c3053,
+    0xfcbe86c7900a88aedcffc83b479aa3a4,
+    0xf987a7253ac413176f2b074cf7815e54,
+    0xf3392b0822b70005940c7a398e4b70f3,
+    0xe7159475a2c29b7443b29c7fa6e889d9,
+    0xd097f3bdfd2022b8845ad8f792aa5825,
+    0xa9f746462d870fdf8a65dc1f90e061e5,
+    0x70d869a156d2a1b890bb3df62baf32f7,
+    0x31be135f97d08fd981231505542fcfa6,
+    0x9aa508b5b7a84e1c677de54f3e99bc9,
+    0x5d6af8dedb81196699c329225ee604,
+    0x2216e584f5fa1ea926041bedfe98,
+    0x48a170391f7dc42444e8fa2,
+];
+const TICK_RATIO_BIT_0: u128 = 0xfffcb933bd6fad37aa2d162d1a594001;
+
+/// `floor(a * b / denominator)`, with the product kept in 512 bits so it
+/// can't overflow. Fails if the result doesn't fit in 256 bits.
+pub fn mul_div(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    if denominator.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+    U256::try_from(a.full_mul(b) / U512::from(denominator)).map_err(|_| MathError::Overflow)
+}
+
+/// `ceil(a * b / denominator)`, see [mul_div].
+pub fn mul_div_rounding_up(a: U256, b: U256, denominator: U256) -> Result<U256, MathError> {
+    let result = mul_div(a, b, denominator)?;
+    if (a.full_mul(b) % U512::from(denominator)).is_zero() {
+        Ok(result)
+    } else {
+        result.checked_add(U256::one()).ok_or(MathError::Overflow)
+    }
+}
+
+// ceil(a / b)
+fn div_rounding_up(a: U256, b: U256) -> Result<U256, MathError> {
+    if b.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+    let (quotient, remainder) = a.div_mod(b);
+    Ok(if remainder.is_zero() { quotient } else { quotient + 1 })
+}
+
+// Sqrt prices are uint160 on chain
+fn to_uint160(value: U256) -> Result<U256, MathError> {
+    if value.bits() > 160 {
+        return Err(MathError::Overflow);
+    }
+    Ok(value)
+}
+
+/// `sqrt(1.0001^tick) * 2^96`, rounded like Uniswap's `TickMath`.
+pub fn get_sqrt_ratio_at_tick(tick: i32) -> Result<U256, MathError> {
+    let abs_tick = tick.unsigned_abs();
+    if abs_tick > MAX_TICK as u32 {
+        return Err(MathError::TickOutOfRange(tick));
+    }
+
+    let mut ratio = if abs_tick & 1 != 0 {
+        U256::from(TICK_RATIO_BIT_0)
+    } else {
+        U256::one() << 128
+    };
+    for (bit, tick_ratio) in TICK_RATIOS.into_iter().enumerate() {
+        if abs_tick & (2 << bit) != 0 {
+            let product = ratio.full_mul(U256::from(tick_ratio)) >> 128;
+            ratio = U256::try_from(product).unwrap();
+        }
+    }
+
+    if tick > 0 {
+        ratio = U256::MAX / ratio;
+    }
+
+    // Q128.128 to Q64.96, rounding up
+    let rounding = if ratio.low_u32() == 0 { U256::zero() } else { U256::one() };
+    Ok((ratio >> 32) + rounding)
+}
+
+/// The greatest tick whose sqrt ratio is at most `sqrt_price_x96`, like
+/// Uniswap's `TickMath.getTickAtSqrtRatio`.
+pub fn get_tick_at_sqrt_ratio(sqrt_price_x96: U256) -> Result<i32, MathError> {
+    if sqrt_price_x96 < MIN_SQRT_RATIO || sqrt_price_x96 >= MAX_SQRT_RATIO {
+        return Err(MathError::SqrtPriceOutOfRange(sqrt_price_x96));
+    }
+
+    // get_sqrt_ratio_at_tick is increasing, the answer is in [low, high]
+    let (mut low, mut high) = (MIN_TICK, MAX_TICK);
+    while low < high {
+        let middle = low + (high - low + 1) / 2;
+        if get_sqrt_ratio_at_tick(middle)? <= sqrt_price_x96 {
+            low = middle;
+        } else {
+            high = middle - 1;
+        }
+    }
+
+    Ok(low)
+}
+
+/// The sqrt price of the inverse pair, `2^192 / sqrt_price_x96` rounded down.
+pub fn invert_sqrt_price_x96(sqrt_price_x96: U256) -> Result<U256, MathError> {
+    mul_div(U256::one() << 96, U256::one() << 96, sqrt_price_x96)
+}
+
+/// The price at `sqrt_price_x96` of a pool of `token0` and `token1`, scaled
+/// by 1e8 and rounded down. That is the price of token0 in token1 if
+/// `zero_for_one`, and of token1 in token0 otherwise. Saturates at
+/// `U256::MAX`.
+pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256, zero_for_one: bool, token0: &TokenMetadata, token1: &TokenMetadata) -> U256 {
+    if sqrt_price_x96.is_zero() {
+        return U256::zero();
+    }
+
+    // sqrt_price_x96^2 / 2^192 is token1 per token0 in raw units
+    let squared = sqrt_price_x96.full_mul(sqrt_price_x96);
+    let q192 = U512::one() << 192;
+    let decimals = token0.decimals as i32 - token1.decimals as i32;
+
+    let price = if zero_for_one {
+        ratio(squared, q192, decimals + PRICE_DECIMALS)
+    } else {
+        ratio(q192, squared, PRICE_DECIMALS - decimals)
+    };
+    saturate(price)
+}
+
+/// The sqrt price at which the price of [sqrt_price_x96_to_price] is
+/// `price`, rounded down. Saturates at `U256::MAX`.
+pub fn price_to_sqrt_price_x96(price: U256, zero_for_one: bool, token0: &TokenMetadata, token1: &TokenMetadata) -> U256 {
+    let q192 = U512::one() << 192;
+    let decimals = token0.decimals as i32 - token1.decimals as i32;
+
+    let squared = if zero_for_one {
+        ratio(U512::from(price) * q192, U512::one(), -decimals - PRICE_DECIMALS)
+    } else if price.is_zero() {
+        None
+    } else {
+        ratio(q192, U512::from(price), PRICE_DECIMALS - decimals)
+    };
+
+    // The square root of a 512 bit number fits in 256 bits
+    squared.map_or(U256::MAX, |squared| U256::try_from(squared.integer_sqrt()).unwrap())
+}
+
+// numerator * 10^exponent / denominator, rounded down. None on overflow.
+fn ratio(numerator: U512, denominator: U512, exponent: i32) -> Option<U512> {
+    let power = U512::from(10).checked_pow(exponent.unsigned_abs().into());
+    if exponent >= 0 {
+        Some(numerator.checked_mul(power?)? / denominator)
+    } else {
+        // Scaling the denominator past 512 bits rounds to zero
+        let denominator = power.and_then(|power| denominator.checked_mul(power));
+        Some(denominator.map_or(U512::zero(), |denominator| numerator / denominator))
+    }
+}
+
+fn saturate(value: Option<U512>) -> U256 {
+    value.and_then(|value| U256::try_from(value).ok()).unwrap_or(U256::MAX)
+}
+
+/// The amount of token0 between two sqrt prices for `liquidity`, like
+/// Uniswap's `SqrtPriceMath.getAmount0Delta`.
+pub fn get_amount0_delta(sqrt_price_a: U256, sqrt_price_b: U256, liquidity: u128, round_up: bool) -> Result<U256, MathError> {
+    let (low, high) = if sqrt_price_a < sqrt_price_b { (sqrt_price_a, sqrt_price_b) } else { (sqrt_price_b, sqrt_price_a) };
+    if low.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+
+    let numerator1 = U256::from(liquidity) << 96;
+    let numerator2 = high - low;
+    if round_up {
+        div_rounding_up(mul_div_rounding_up(numerator1, numerator2, high)?, low)
+    } else {
+        Ok(mul_div(numerator1, numerator2, high)? / low)
+    }
+}
+
+/// The amount of token1 between two sqrt prices for `liquidity`, like
+/// Uniswap's `SqrtPriceMath.getAmount1Delta`.
+pub fn get_amount1_delta(sqrt_price_a: U256, sqrt_price_b: U256, liquidity: u128, round_up: bool) -> Result<U256, MathError> {
+    let difference = sqrt_price_a.abs_diff(sqrt_price_b);
+    if round_up {
+        mul_div_rounding_up(U256::from(liquidity), difference, Q96)
+    } else {
+        mul_div(U256::from(liquidity), difference, Q96)
+    }
+}
+
+/// The sqrt price after `amount_in` is swapped in, rounded so the pool
+/// never gives out too much.
+pub fn get_next_sqrt_price_from_input(sqrt_price: U256, liquidity: u128, amount_in: U256, zero_for_one: bool) -> Result<U256, MathError> {
+    if zero_for_one {
+        next_sqrt_price_from_amount0_rounding_up(sqrt_price, liquidity, amount_in, true)
+    } else {
+        next_sqrt_price_from_amount1_rounding_down(sqrt_price, liquidity, amount_in, true)
+    }
+}
+
+/// The sqrt price after `amount_out` is swapped out, rounded so the pool
+/// never gives out too much.
+pub fn get_next_sqrt_price_from_output(sqrt_price: U256, liquidity: u128, amount_out: U256, zero_for_one: bool) -> Result<U256, MathError> {
+    if zero_for_one {
+        next_sqrt_price_from_amount1_rounding_down(sqrt_price, liquidity, amount_out, false)
+    } else {
+        next_sqrt_price_from_amount0_rounding_up(sqrt_price, liquidity, amount_out, false)
+    }
+}
+
+// liquidity * sqrt_price / (liquidity +- amount * sqrt_price)
+fn next_sqrt_price_from_amount0_rounding_up(sqrt_price: U256, liquidity: u128, amount: U256, add: bool) -> Result<U256, MathError> {
+    if amount.is_zero() {
+        return Ok(sqrt_price);
+    }
+    if liquidity == 0 || sqrt_price.is_zero() {
+        return Err(MathError::DivisionByZero);
+    }
+
+    let numerator1 = U256::from(liquidity) << 96;
+    let product = amount.checked_mul(sqrt_price);
+    if add {
+        if let Some(denominator) = product.and_then(|product| numerator1.checked_add(product)) {
+            return mul_div_rounding_up(numerator1, sqrt_price, denominator);
+        }
+        let denominator = (numerator1 / sqrt_price).checked_add(amount).ok_or(MathError::Overflow)?;
+        div_rounding_up(numerator1, denominator)
+    } else {
+        match product {
+            Some(product) if product < numerator1 => to_uint160(mul_div_rounding_up(numerator1, sqrt_price, numerator1 - product)?),
+            _ => Err(MathError::NotEnoughLiquidity),
+        }
+    }
+}
+
+// sqrt_price +- amount / liquidity
+fn next_sqrt_price_from_amount1_rounding_down(sqrt_price: U256, liquidity: u128, amount: U256, add: bool) -> Result<U256, MathError> {
+    if liquidity == 0 {
+        return Err(MathError::DivisionByZero);
+    }
+
+    let liquidity = U256::from(liquidity);
+    let fits_160_bits = amount.bits() <= 160;
+    if add {
+        let quotient = if fits_160_bits { (amount << 96) / liquidity } else { mul_div(amount, Q96, liquidity)? };
+        to_uint160(sqrt_price.checked_add(quotient).ok_or(MathError::Overflow)?)
+    } else {
+        let quotient = if fits_160_bits { div_rounding_up(amount << 96, liquidity)? } else { mul_div_rounding_up(amount, Q96, liquidity)? };
+        if sqrt_price <= quotient {
+            return Err(MathError::NotEnoughLiquidity);
+        }
+        Ok(sqrt_price - quotient)
+    }
+}
+
+/// The result of swapping within a single range of liquidity.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SwapStep {
+    pub sqrt_price_next: U256,
+    pub amount_in: U256,
+    pub amount_out: U256,
+    pub fee_amount: U256,
+}
+
+/// Swaps `amount_remaining` from `sqrt_price_current` towards
+/// `sqrt_price_target`, like Uniswap's `SwapMath.computeSwapStep`. A
+/// positive amount is an exact input, a negative one an exact output.
+pub fn compute_swap_step(
+    sqrt_price_current: U256,
+    sqrt_price_target: U256,
+    liquidity: u128,
+    amount_remaining: I256,
+    fee: u32,
+) -> Result<SwapStep, MathError> {
+    let zero_for_one = sqrt_price_current >= sqrt_price_target;
+    let exact_in = !amount_remaining.is_negative();
+    let amount_remaining = amount_remaining.unsigned_abs();
+    let fee_denominator = U256::from(FEE_DENOMINATOR);
+
+    // What it takes to reach the target
+    let to_target = if exact_in && zero_for_one {
+        get_amount0_delta(sqrt_price_target, sqrt_price_current, liquidity, true)?
+    } else if exact_in {
+        get_amount1_delta(sqrt_price_current, sqrt_price_target, liquidity, true)?
+    } else if zero_for_one {
+        get_amount1_delta(sqrt_price_target, sqrt_price_current, liquidity, false)?
+    } else {
+        get_amount0_delta(sqrt_price_current, sqrt_price_target, liquidity, false)?
+    };
+
+    let sqrt_price_next = if exact_in {
+        let amount_less_fee = mul_div(amount_remaining, U256::from(FEE_DENOMINATOR.saturating_sub(fee)), fee_denominator)?;
+        if amount_less_fee >= to_target {
+            sqrt_price_target
+        } else {
+            get_next_sqrt_price_from_input(sqrt_price_current, liquidity, amount_less_fee, zero_for_one)?
+        }
+    } else if amount_remaining >= to_target {
+        sqrt_price_target
+    } else {
+        get_next_sqrt_price_from_output(sqrt_price_current, liquidity, amount_remaining, zero_for_one)?
+    };
+
+    let reached_target = sqrt_price_next == sqrt_price_target;
+    let (amount_in, mut amount_out) = if zero_for_one {
+        (
+            if reached_target && exact_in { to_target } else { get_amount0_delta(sqrt_price_next, sqrt_price_current, liquidity, true)? },
+            if reached_target && !exact_in { to_target } else { get_amount1_delta(sqrt_price_next, sqrt_price_current, liquidity, false)? },
+        )
+    } else {
+        (
+            if reached_target && exact_in { to_target } else { get_amount1_delta(sqrt_price_current, sqrt_price_next, liquidity, true)? },
+            if reached_target && !exact_in { to_target } else { get_amount0_delta(sqrt_price_current, sqrt_price_next, liquidity, false)? },
+        )
+    };
+
+    // The output can't be more than asked for
+    if !exact_in && amount_out > amount_remaining {
+        amount_out = amount_remaining;
+    }
+
+    let fee_amount = if exact_in && !reached_target {
+        // What is left of the input is all fee
+        amount_remaining - amount_in
+    } else if fee >= FEE_DENOMINATOR {
+        amount_in
+    } else {
+        mul_div_rounding_up(amount_in, U256::from(fee), U256::from(FEE_DENOMINATOR - fee))?
+    };
+
+    Ok(SwapStep { sqrt_price_next, amount_in, amount_out, fee_amount })
+}
@@ -2,8 +2,10 @@ use alloy_primitives::{hex::const_check_raw, TxKind};
 use grouping_by::GroupingBy;
 use jsonrpsee::{core::client::{ClientT, SubscriptionClientT}, rpc_params, ws_client::WsClientBuilder};
 use jsonrpsee_ws_server::{RpcModule, SubscriptionSink, WsServerBuilder};
-use revm_trace::{alloy::rpc::client, create_shared_backend, evm::builder::get_provider, revm::{bytecode::eof::printer::print, context::result::ExecutionResult, precompile::blake2}, types::StateOverride, SimulationBatch, SimulationTx, TransactionTrace};
+use revm_trace::{alloy::rpc::client, create_shared_backend, evm::builder::get_provider, revm::{bytecode::eof::printer::print, context::result::ExecutionResult, precompile::blake2}, types::StateOverride, SimulationBatch, TransactionTrace};
 use tokio::{sync::mpsc::{UnboundedReceiver, UnboundedSender}, task::JoinSet};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
 use ethers::{core::k256::elliptic_curve::consts::U25, middleware::gas_oracle::cache, types::{transaction::eip2718::TypedTransaction, BlockId, BlockNumber, Eip1559TransactionRequest, H160, H256, I256, U256}, utils::{format_ether, keccak256, WEI_IN_ETHER}};
 use ethers_providers::{spoof, Http, Middleware, Provider, RawCall};
 use tokio::sync::{Mutex, RwLock};
@@ -11,13 +13,15 @@ use tracing::subscriber;
 use std::{collections::HashMap, str::FromStr, sync::Arc, time::SystemTime};
 use ethers::abi::AbiDecode;
 
-use crate::{abi::{MSLauncher, IERC20}, collectors::{binance_collector::start_binance_collector, block_collector::{start_block_collector, BlockInfo}, flash_block_collector::start_flash_block_collector}, types::{broadcast_trade, calculate_balance_slot, decode_price, find_best_trade_list, price_to_sqrt_price_x96, sqrt_price_x96_to_price, start_trade_collector, start_trade_server, FlashblocksPayloadV1, LogReceipts, PoolType, TradeInfo}};
+use crate::{abi::MSLauncher, collectors::arbitrum_feed::ArbitrumFeedMessage, cl_quoter::{LauncherQuoter, DEFAULT_WORDS_AROUND}, trade_search::SearchParams, flashblock_accumulator::{FlashblockAccumulator, Progress}, pool_catalog::{LauncherSource, PoolCatalog, RetryPolicy}, swap_events::SwapEventDecoder, tokens::{RpcTokenSource, TokenPair, TokenRegistry}, uniswap_v4::V4Pool, collectors::block_collector::BlockInfo, types::{broadcast_trade, find_best_trade_list, find_best_trade_list_native, replay_transactions, to_state_override, verify_trade_list, PendingCall, PendingState, SimulationSession, start_trade_collector, start_trade_server, Action, Event, FlashblocksPayloadV1, LogItem, PoolType, Strategy, TradeInfo, TradeMap, TradeSearchContext}};
 
 
 pub struct BaseStrategy {
     pub chain_id: u64,
 
     pub simulation_mode: bool,
+    /// Simulate on top of the transactions of the pending block
+    pub simulate_pending: bool,
 
     pub from_addr: H160,
     pub to_addr: H160,
@@ -32,28 +36,49 @@ pub struct BaseStrategy {
     pub min_swap_amount: U256,
 
     pub block_info: BlockInfo,
-
+    /// The pending block rebuilt from flashblocks
+    pub pending_block: FlashblockAccumulator,
+    /// The state left by the first `replayed_transactions` transactions of
+    /// the pending block
+    pub pending_state: PendingState,
+    pub replayed_transactions: usize,
+
+    /// Where the pools of the launcher are cached
+    pub pool_cache_path: Option<String>,
+    pub pool_catalog: PoolCatalog,
     pub pools: Vec<H160>,
     pub pool_types: Vec<PoolType>,
-
-    pub trade_info_map: Arc<RwLock<HashMap<(usize, bool), Vec<TradeInfo>>>>,
+    /// For V4 pools, which log from the PoolManager in `pools`, the pool
+    pub v4_pools: Vec<Option<V4Pool>>,
+
+    /// Metadata of the tokens seen so far
+    pub tokens: TokenRegistry,
+    /// The base and quote tokens of the launcher
+    pub token_pair: TokenPair,
+    /// Quote trades with the native quoter instead of the simulator,
+    /// except on top of pending transactions
+    pub native_quoting: bool,
+    /// How the trades of each pool are searched for
+    pub search_params: SearchParams,
+
+    pub trade_info_map: Arc<RwLock<TradeMap>>,
     pub pool_prices: Vec<U256>,
 
     pub chainlink_price: Arc<RwLock<U256>>,
 
-    pub swap_topic_list: Vec<H256>,
+    /// Decodes the swaps of `pools`
+    pub swap_decoder: SwapEventDecoder,
     pub update_state_address_topic_list: Vec<(H160, H256)>,
     pub last_simulated_block_number: Arc<RwLock<U256>>,
 
     // pub flash_block_receiver: UnboundedReceiver<FlashblocksPayloadV1>,
-    pub tx_sender: UnboundedSender<TypedTransaction>,
-    pub last_seq_num: u64,
+    pub subscribers: Arc<RwLock<Vec<SubscriptionSink>>>,
+    pub base_balance_list: Vec<U256>,
+    pub last_base_balance_update_block_number: U256,
     pub test_mode: bool,
     pub gas_limit: u64,
     pub last_tx_time: u128,
     // pub last_tx_limit_price: i64
-    pub step_count: u64,
-    pub regression_count: u64,
     // pub simulate_interval: u64,
 }
 
@@ -68,23 +93,16 @@ impl BaseStrategy {
         to_addr: H160,
         simulator_addr: H160,
         chain_id: u64,
-        tx_sender: UnboundedSender<TypedTransaction>,
         gas_limit: u64,
         simulation_mode: bool,
+        simulate_pending: bool,
         test_mode: bool,
-        step_count: u64,
-        regression_count: u64,
         min_profit: U256,
         min_swap_amount: U256,
     ) -> Self {
         let chain_id = chain_id;
         let from_addr = from_addr;
         let to_addr = to_addr;
-        let swap_topic_list = vec![
-            H256::from_str("0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67").unwrap(),
-            H256::from_str("0x19b47279256b2a23a1665c810c8d55a1758940ee09377d4f8d26497a3577dc83").unwrap(),
-        ];
-
             // let binance_price_reader = Arc::new(RwLock::new(U256::from(0)));
         // start_binance_collector(vec!["ETHUSDT".to_string()], binance_price_reader.clone()).await;
 
@@ -103,14 +121,25 @@ impl BaseStrategy {
             trade_server_url,
             chain_id,
             simulation_mode,
+            simulate_pending,
             from_addr,
             to_addr,
             simulator_addr,
             min_profit,
             min_swap_amount,
             block_info: BlockInfo::default(),
+            pending_block: FlashblockAccumulator::new(),
+            pending_state: PendingState::default(),
+            replayed_transactions: 0,
             pools: vec![],
+            pool_cache_path: None,
+            pool_catalog: PoolCatalog::default(),
             pool_types: vec![],
+            v4_pools: vec![],
+            tokens: TokenRegistry::for_chain(chain_id),
+            token_pair: TokenPair::default(),
+            native_quoting: false,
+            search_params: SearchParams::default(),
             trade_info_map: Arc::new(RwLock::new(HashMap::new())),
             chainlink_price: Arc::new(RwLock::new(U256::from(0))),
             pool_prices: vec![],
@@ -119,122 +148,324 @@ impl BaseStrategy {
             // block_number: U256::from(0),
             // block_timestamp: U256::from(0),
             // base_fee_per_gas: U256::from(0),
-            swap_topic_list,
+            swap_decoder: SwapEventDecoder::default(),
             update_state_address_topic_list,
 
             // flash_block_receiver,
-            tx_sender,
+            subscribers: Arc::new(RwLock::new(vec![])),
+            base_balance_list: vec![],
+            last_base_balance_update_block_number: U256::from(0),
 
-            last_seq_num: 0,        
             test_mode,
             gas_limit,
             last_tx_time: 0,    
-            step_count,
-            regression_count,
             // simulate_interval,
             // last_tx_limit_price: 0
         }
     }
 
-    pub async fn run(&mut self) {
+    pub fn with_pool_cache(mut self, path: Option<String>) -> Self {
+        self.pool_cache_path = path;
+        self
+    }
+
+    pub fn with_native_quoting(mut self, native_quoting: bool) -> Self {
+        self.native_quoting = native_quoting;
+        self
+    }
+
+    pub fn with_search_params(mut self, search_params: SearchParams) -> Self {
+        self.search_params = search_params;
+        self
+    }
+
+    /// Resolves the base and quote tokens of the launcher, which prices are
+    /// shown in and the simulator is funded with.
+    pub async fn load_tokens(&mut self) -> Result<()> {
+        let launcher = MSLauncher::new(self.to_addr, self.rpc_client.clone());
+        let base = launcher.get_base_token().call().await?;
+        let quote = launcher.get_quote_token().call().await?;
+
+        // The launcher holds both tokens, so their balance slots can be found
+        let source = RpcTokenSource::new(self.rpc_client.clone());
+        let base = self.tokens.resolve(&source, base, self.to_addr).await?;
+        let quote = self.tokens.resolve(&source, quote, self.to_addr).await?;
+
         if self.simulation_mode {
-            self.run_simulator().await;
-        } else {
-            self.run_executor().await;
+            if let Some(token) = [&base, &quote].into_iter().find(|token| token.balance_slot.is_none()) {
+                return Err(anyhow!("Balance slot of {} ({:?}) is unknown, the simulator can't be funded", token.symbol, token.address));
+            }
         }
+
+        println!("Trading {} against {}", base.symbol, quote.symbol);
+        self.token_pair = TokenPair::new(base, quote);
+        Ok(())
     }
-        
-    pub async fn run_simulator(&mut self) {
-        let block_info_reader = start_block_collector(self.ws_url.clone()).await;
-        let mut base_balance_list = vec![];
-        let mut last_base_balance_update_block_number = U256::from(0);
-
-        let subscribers = start_trade_server(self.trade_server_url.clone()).await;
-
-        loop {
-            let block_info = block_info_reader.read().await.clone();
-    
-            if block_info.number != self.block_info.number {
-                println!("Block: {}", block_info.number);
-                self.block_info = block_info;
-    
-                // Update base balance list
-                if self.block_info.number > last_base_balance_update_block_number + 100 {            
-                    last_base_balance_update_block_number = self.block_info.number;
-                    let launcher = MSLauncher::new(self.to_addr, self.rpc_client.clone());
-                    base_balance_list = launcher.get_base_balance_list().call().await.unwrap();
-                }
-    
-                // Simulate trade
-                let trade_info_list_new: Vec<TradeInfo> =
-                    simulate_trade(
-                        self.rpc_client.clone(),
-                        self.rpc_url.as_str(),
-                        block_info,
-                        self.from_addr,
-                        self.to_addr,
-                        self.simulator_addr,
-                        base_balance_list.clone(),
-                        self.min_profit,
-                        self.min_swap_amount).await;
-
-                let trade_info_list_new_json = serde_json::to_value(trade_info_list_new).unwrap();
-                broadcast_trade(subscribers.clone(), trade_info_list_new_json).await;
+
+    /// Runs one simulation round against `block_number` and returns the
+    /// trades it would publish to the trade server.
+    pub async fn simulate_block(&self, block_number: u64) -> Result<Vec<TradeInfo>> {
+        let block = self.rpc_client.get_block(block_number).await?
+            .ok_or_else(|| anyhow!("Block {} not found", block_number))?;
+        let launcher = MSLauncher::new(self.to_addr, self.rpc_client.clone());
+        let base_balance_list = launcher.get_base_balance_list().block(block_number).call().await?;
+
+        let (trade_info_list, _) = self.simulate_trade(BlockInfo::from(&block), base_balance_list, &PendingState::default(), vec![]).await?;
+
+        if self.native_quoting {
+            self.verify_trades(block_number, &trade_info_list).await?;
+        }
+
+        Ok(trade_info_list)
+    }
+
+    /// Prints how far the native quotes of `trade_info_list` are from the
+    /// simulator at `block_number`.
+    async fn verify_trades(&self, block_number: u64, trade_info_list: &[TradeInfo]) -> Result<()> {
+        let shared_backend = create_shared_backend(self.rpc_url.as_str(), Some(block_number)).await?;
+        let provider = Arc::new(get_provider(self.rpc_url.as_str()).await?);
+        let state_override = to_state_override(&self.token_pair.simulator_balances(self.simulator_addr));
+        let mut session = SimulationSession::new(shared_backend, provider, self.from_addr, state_override).await?;
+        let simulated = verify_trade_list(
+            &mut session,
+            self.rpc_client.clone(),
+            self.simulator_addr,
+            trade_info_list,
+            self.search_params.gas_limit);
+
+        for (t, outcome) in trade_info_list.iter().zip(simulated) {
+            let side = if t.sell_base_token { "Bid" } else { "Ask" };
+            match outcome.decode::<(U256, I256, U256)>() {
+                Some((sqrt_price_x96, profit, _)) => println!(
+                    "Pool {} {} delta {}: price {} native, {} simulated, profit {} native, {} simulated",
+                    t.pool_index, side, format_ether(t.delta),
+                    self.token_pair.price(t.sqrt_price_x96), self.token_pair.price(sqrt_price_x96),
+                    I256::from_raw(t.profit), profit),
+                None => println!("Pool {} {} delta {}: {} in the simulator", t.pool_index, side, format_ether(t.delta), outcome),
             }
-            
-            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
         }
+
+        Ok(())
     }
 
+    /// Searches the trades of every pool at `block_info` with `pending_state`
+    /// applied, once `pending_transactions` are replayed into it. Returns the
+    /// trades and the pending state with the replayed transactions.
+    async fn simulate_trade(
+        &self,
+        block_info: BlockInfo,
+        base_balance_list: Vec<U256>,
+        pending_state: &PendingState,
+        pending_transactions: Vec<PendingCall>,
+    ) -> Result<(Vec<TradeInfo>, PendingState)> {
+        // Snapshots can't see the pending transactions, those are simulated
+        let quoter = if self.native_quoting && pending_state.is_empty() && pending_transactions.is_empty() {
+            match LauncherQuoter::load(self.rpc_client.clone(), self.to_addr, block_info.number.as_u64(), DEFAULT_WORDS_AROUND).await {
+                Ok(quoter) => Some(quoter),
+                Err(e) => {
+                    println!("Native quoting failed, simulating: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+
+        let shared_backend = create_shared_backend(self.rpc_url.as_str(), Some(block_info.number.as_u64())).await?;
+        let provider = Arc::new(get_provider(self.rpc_url.as_str()).await?);
+
+        let mut join_set = JoinSet::new();
+
+        let pending_state = match pending_transactions.is_empty() {
+            true => pending_state.clone(),
+            false => replay_transactions(shared_backend.clone(), provider.clone(), pending_state, pending_transactions).await?,
+        };
+        // The simulator balances win over the pending changes
+        let mut state = pending_state.clone();
+        for (address, slot, value) in self.token_pair.simulator_balances(self.simulator_addr) {
+            state.store(address, slot, value);
+        }
 
+        let search = TradeSearchContext {
+            from_addr: self.from_addr,
+            launcher_addr: self.to_addr,
+            simulator_addr: self.simulator_addr,
+            min_profit: self.min_profit,
+            min_swap_amount: self.min_swap_amount,
+            tokens: self.token_pair.clone(),
+            state_override: state.to_state_override(),
+            params: self.search_params,
+            ..Default::default()
+        };
+
+        let mut searches = vec![];
+
+        for sell_base_token in [true, false] {
+            for (pool_index, base_balance) in base_balance_list.iter().enumerate() {
+                let search = TradeSearchContext { pool_index, sell_base_token, max_delta: base_balance / 3, ..search.clone() };
+
+                if let Some(quoter) = &quoter {
+                    match find_best_trade_list_native(quoter, &search) {
+                        Ok(search) => {
+                            searches.push(search);
+                            continue;
+                        }
+                        Err(e) => println!("Pool {} can't be quoted natively, simulating: {}", pool_index, e),
+                    }
+                }
 
-    pub async fn run_executor(&mut self) {
-        let mut flash_block_receiver = 
-            start_flash_block_collector(self.flashblocks_url.clone(), self.chain_id).await;        
-        
-        // Start trade collector
-        self.trade_info_map = start_trade_collector(self.trade_server_url.clone()).await;
+                // Clone values that need to be moved into spawned tasks
+                let shared_backend = shared_backend.clone();
+                let rpc_client = self.rpc_client.clone();
+                let provider = provider.clone();
 
-        let launcher = MSLauncher::new(self.to_addr, self.rpc_client.clone());
-        let pool_count = launcher.get_pool_count().call().await.unwrap().as_u64() as usize;
+                join_set.spawn(async move {
+                    find_best_trade_list(shared_backend, provider, rpc_client, search).await
+                });
+            }
+        }
 
-        for i in 0..pool_count {
-            let (pool, pool_type) = launcher.get_pool_info(i.into()).call().await.unwrap_or_default();
+        searches.extend(join_set.join_all().await);
+        let evaluations: usize = searches.iter().map(|search| search.evaluations).sum();
+        println!("Searched {} pools and sides with {:?} in {} evaluations", searches.len(), self.search_params.algorithm, evaluations);
 
-            self.pools.push(pool);
-            self.pool_types.push(pool_type.into());
-            self.pool_prices.push(U256::from(0));
+        Ok((searches.into_iter().flat_map(|search| search.trades).collect(), pending_state))
+    }
+
+    async fn on_new_block(&mut self, block_info: BlockInfo) {
+        if block_info.number == self.block_info.number {
+            return;
         }
 
-        loop {
-            // Receive flashblock
-            let flashblock = flash_block_receiver
-                .recv()
-                .await
-                .expect("Failed to receive data from feed client");
+        println!("Block: {}", block_info.number);
+        self.block_info = block_info;
 
-            let seq_num = flashblock.index + flashblock.metadata.block_number * 100;
-            if self.last_seq_num >= seq_num {
-                continue;
-            }
+        // Update base balance list
+        if self.block_info.number > self.last_base_balance_update_block_number + 100 {
+            self.last_base_balance_update_block_number = self.block_info.number;
+            let launcher = MSLauncher::new(self.to_addr, self.rpc_client.clone());
+            self.base_balance_list = launcher.get_base_balance_list().call().await.unwrap();
+        }
 
-            self.last_seq_num = seq_num;
+        // The flashblocks of the next block drive the simulation
+        if self.simulate_pending {
+            return;
+        }
 
-            let drift = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as i128
-             + 2000 - self.block_info.timestamp.as_u128() as i128 * 1000;
-            println!("Drift: {:?}", drift);
+        // Simulate trade
+        let trade_info_list_new =
+            match self.simulate_trade(block_info, self.base_balance_list.clone(), &PendingState::default(), vec![]).await {
+                Ok((trade_info_list, _)) => trade_info_list,
+                Err(e) => {
+                    println!("Simulation failed: {}", e);
+                    return;
+                }
+            };
 
-            // Process flashblock to get pool prices
-            self.process_flash_block(flashblock).await;
+        let trade_info_list_new_json = serde_json::to_value(trade_info_list_new).unwrap();
+        broadcast_trade(self.subscribers.clone(), trade_info_list_new_json).await;
+    }
 
-            // Find profitable trade
-            let (bid_prices, ask_prices, mut max_profit) = self.find_profitable_trade().await;
-            if max_profit > U256::zero() {
-                self.send_tx(bid_prices, ask_prices, max_profit).await;
+    /// Simulates on top of the parent of the pending block with the
+    /// transactions of its flashblocks replayed, each time one is applied.
+    async fn on_pending_flash_block(&mut self, flashblock: FlashblocksPayloadV1) {
+        match self.pending_block.apply(flashblock) {
+            Ok(Progress::Applied { new_block, .. }) => {
+                if new_block {
+                    self.pending_state = PendingState::default();
+                    self.replayed_transactions = 0;
+                }
             }
+            Ok(_) => return,
+            Err(e) => {
+                println!("Dropping pending block: {}", e);
+                return;
+            }
+        }
 
-            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        let block_number = self.pending_block.block_number().unwrap();
+        println!("Pending block: {}, flashblock {}", block_number, self.pending_block.flashblock_index().unwrap());
+
+        // Only the transactions of the new flashblocks are replayed, on top of
+        // the state the earlier ones left. Reverted ones changed no storage.
+        // One that can't be decoded fails the block like a failed replay, the
+        // later flashblocks retry from it and the next block starts over.
+        let transactions = self.pending_block.transactions();
+        let replayed_transactions = transactions.len();
+        let pending_transactions = match transactions[self.replayed_transactions..].iter()
+            .filter(|tx| tx.receipt.status())
+            .map(|tx| tx.pending_call())
+            .collect::<Result<Vec<_>, _>>() {
+            Ok(pending_transactions) => pending_transactions,
+            Err(e) => {
+                println!("Pending simulation failed: {}", e);
+                return;
+            }
+        };
+
+        let parent = BlockInfo { number: U256::from(block_number - 1), ..self.block_info };
+        let trade_info_list_new =
+            match self.simulate_trade(parent, self.base_balance_list.clone(), &self.pending_state, pending_transactions).await {
+                Ok((trade_info_list, pending_state)) => {
+                    self.pending_state = pending_state;
+                    self.replayed_transactions = replayed_transactions;
+                    trade_info_list
+                }
+                Err(e) => {
+                    println!("Pending simulation failed: {}", e);
+                    return;
+                }
+            };
+
+        let trade_info_list_new_json = serde_json::to_value(trade_info_list_new).unwrap();
+        broadcast_trade(self.subscribers.clone(), trade_info_list_new_json).await;
+    }
+
+    /// Simulates on top of the parent of the block an Arbitrum feed message
+    /// produces, with the transactions of the message replayed.
+    async fn on_pending_arbitrum_message(&mut self, message: ArbitrumFeedMessage) {
+        println!("Pending block: {}, sequence number {}", message.block_number, message.sequence_number);
+
+        let pending_transactions = message.transactions.iter()
+            .filter_map(|tx| {
+                let simulation_tx = tx.simulation_tx();
+                if simulation_tx.is_none() {
+                    println!("Skipping pending transaction: unsupported transaction type");
+                }
+                simulation_tx.map(PendingCall::from)
+            })
+            .collect();
+
+        // Every message is a block of its own, so nothing carries over
+        let parent = BlockInfo { number: U256::from(message.block_number - 1), ..self.block_info };
+        let trade_info_list_new =
+            match self.simulate_trade(parent, self.base_balance_list.clone(), &PendingState::default(), pending_transactions).await {
+                Ok((trade_info_list, _)) => trade_info_list,
+                Err(e) => {
+                    println!("Pending simulation failed: {}", e);
+                    return;
+                }
+            };
+
+        let trade_info_list_new_json = serde_json::to_value(trade_info_list_new).unwrap();
+        broadcast_trade(self.subscribers.clone(), trade_info_list_new_json).await;
+    }
+
+    async fn on_flash_block(&mut self, flashblock: FlashblocksPayloadV1) -> Option<TypedTransaction> {
+        let drift = SystemTime::now().duration_since(SystemTime::UNIX_EPOCH).unwrap().as_millis() as i128
+         + 2000 - self.block_info.timestamp.as_u128() as i128 * 1000;
+        println!("Drift: {:?}", drift);
+
+        // Process flashblock to get pool prices
+        self.process_flash_block(flashblock).await;
+
+        // Find profitable trade
+        let (bid_prices, ask_prices, max_profit) = self.find_profitable_trade().await;
+        if max_profit > U256::zero() {
+            return Some(self.build_tx(bid_prices, ask_prices, max_profit));
         }
+
+        None
     }
 
     async fn find_profitable_trade(&self) -> (Vec<U256>, Vec<U256>, U256) {
@@ -260,7 +491,7 @@ impl BaseStrategy {
                         t.pool_index,
                         if t.sell_base_token {"Bid"} else {"Ask"},
                         &format_ether(t.delta),
-                        sqrt_price_x96_to_price(t.sqrt_price_x96, true, 18, 6),
+                        self.token_pair.price(t.sqrt_price_x96),
                         &format_ether(t.swap_amount),
                         &format_ether(t.profit),
                     );
@@ -290,9 +521,9 @@ impl BaseStrategy {
             // if bid_prices[i] > U256::zero() && ask_prices[i] > U256::zero()
             {
                 println!("Pool{i}: [{:.4}] < [{:.4}] < [{:.4}]", 
-                    sqrt_price_x96_to_price(ask_prices[i], true, 18, 6).as_u64() as f64 / 100000000.0,
-                    sqrt_price_x96_to_price(pool_price, true, 18, 6).as_u64() as f64 / 100000000.0,
-                    sqrt_price_x96_to_price(bid_prices[i], true, 18, 6).as_u64() as f64 / 100000000.0);
+                    self.token_pair.price(ask_prices[i]).as_u64() as f64 / 100000000.0,
+                    self.token_pair.price(pool_price).as_u64() as f64 / 100000000.0,
+                    self.token_pair.price(bid_prices[i]).as_u64() as f64 / 100000000.0);
             }
         }
 
@@ -311,8 +542,7 @@ impl BaseStrategy {
     // }
 
     async fn process_flash_block(&mut self, flash_block: FlashblocksPayloadV1) {
-        if flash_block.base.is_some() {
-            let base = flash_block.base.unwrap();
+        if let Some(base) = flash_block.base {
             if self.block_info.timestamp != U256::from(base.timestamp) {
                 self.block_info.number = U256::from(base.block_number);
                 self.block_info.base_fee_per_gas = base.base_fee_per_gas;
@@ -322,48 +552,59 @@ impl BaseStrategy {
             }
         }
 
-         // Flashblocks Price
-         for (hash, receipt) in flash_block.metadata.receipts.as_object().unwrap() {
-            for (key, value) in receipt.as_object().unwrap() {
-                let log_receipts = serde_json::from_value::<LogReceipts>(value.clone()).unwrap_or_default();
-
-                for log in log_receipts.logs {
-                    if log.topics.is_empty() {
-                        continue;
-                    }
+         // Flashblocks Price, a reverted swap moved no price
+         let receipts = flash_block.diff.transactions.iter().filter_map(|raw| flash_block.metadata.receipt_of(raw).1);
+         for receipt in receipts {
+            if !receipt.status() {
+                continue;
+            }
 
-                    if self.pools.contains(&log.address) {
-                        let pool_index = self.pools.iter().position(|p| p == &log.address).unwrap();
-                        let topic0_index = self.swap_topic_list.iter().position(|t| t == &log.topics[0]);
+            for log in receipt.logs() {
+                if log.topics.is_empty() {
+                    continue;
+                }
 
-                        if topic0_index.is_some() {
-                            let pool_type = self.pool_types[pool_index];
+                if let Some(pool_index) = self.pool_index(log) {
+                    let pool_type = self.pool_types[pool_index];
 
+                    match self.swap_decoder.decode(pool_type, log) {
+                        Some(Ok(swap)) => {
                             println!("Pool Type: {:?}", pool_type);
-                            let price = decode_price(log.data.to_string(), pool_type);
-
-                            println!("Pool {pool_index} : {} - {price}", sqrt_price_x96_to_price(price, true, 18, 6));
-                            self.pool_prices[pool_index] = price;
+                            if let Some(price) = swap.sqrt_price_x96 {
+                                println!("Pool {pool_index} : {} - {price}", self.token_pair.price(price));
+                                self.pool_prices[pool_index] = price;
+                            }
                         }
+                        Some(Err(e)) => println!("Pool {pool_index} : bad swap event: {}", e),
+                        None => {}
                     }
-
-                    // if self.update_state_address_topic_list.contains(&(log.address, log.topics[0])) {
-                    //     println!("Simulation Required: {:?}, {:?}", log.address, log.topics[0]);
-                    //     *self.last_simulated_block_number.write().await = U256::from(0);
-
-                    //     // Reset bid and ask prices if chainlink price is updated
-                    //     if self.update_state_address_topic_list[0].0 == log.address {
-                    //         // *self.limit_price_map.write().await.clear();
-                    //         // *self.bid_prices.write().await = vec![U256::from(0); self.pools.len()];
-                    //         // *self.ask_prices.write().await = vec![U256::from(0); self.pools.len()];
-                    //     }
-                    // }
                 }
+
+                // if self.update_state_address_topic_list.contains(&(log.address, log.topics[0])) {
+                //     println!("Simulation Required: {:?}, {:?}", log.address, log.topics[0]);
+                //     *self.last_simulated_block_number.write().await = U256::from(0);
+
+                //     // Reset bid and ask prices if chainlink price is updated
+                //     if self.update_state_address_topic_list[0].0 == log.address {
+                //         // *self.limit_price_map.write().await.clear();
+                //         // *self.bid_prices.write().await = vec![U256::from(0); self.pools.len()];
+                //         // *self.ask_prices.write().await = vec![U256::from(0); self.pools.len()];
+                //     }
+                // }
             }
         }
     }
 
-    async fn send_tx(&mut self, bid_prices: Vec<U256>, ask_prices: Vec<U256>, max_profit: U256) {
+    /// Index of the pool that emitted `log`. V4 pools all log from the
+    /// PoolManager and are told apart by their id.
+    fn pool_index(&self, log: &LogItem) -> Option<usize> {
+        self.pools.iter().zip(&self.v4_pools).position(|(pool, v4_pool)| match v4_pool {
+            Some(v4_pool) => v4_pool.emitted(log),
+            None => *pool == log.address,
+        })
+    }
+
+    fn build_tx(&self, bid_prices: Vec<U256>, ask_prices: Vec<U256>, max_profit: U256) -> TypedTransaction {
         let mut gas_price = max_profit / 30000000;
         let base_price = U256::from(self.block_info.base_fee_per_gas) * 3 / 2;
         
@@ -389,7 +630,7 @@ impl BaseStrategy {
             encoded.extend_from_slice(&ask_bytes[20..32]);
         }
 
-        let tx = TypedTransaction::Eip1559(
+        TypedTransaction::Eip1559(
             Eip1559TransactionRequest::new()
                 .from(self.from_addr)
                 .to(self.to_addr)
@@ -398,76 +639,65 @@ impl BaseStrategy {
                 .gas(U256::from(self.gas_limit))
                 .max_fee_per_gas(gas_price)
                 .max_priority_fee_per_gas(gas_price),
-        );
-
-        self.tx_sender.send(tx);
+        )
     }
 
 }
 
-async fn simulate_trade(
-    rpc_client: Arc<Provider<Http>>,
-    rpc_url: &str,
-    block_info: BlockInfo,
-    from_addr: H160,
-    launcher_addr: H160,
-    simulator_addr: H160,
-    base_balance_list: Vec<U256>,
-    min_profit: U256,
-    min_swap_amount: U256,
-) -> Vec<TradeInfo>
-{
-    let shared_backend = create_shared_backend(
-        rpc_url.clone(),
-        Some(block_info.number.as_u64().into())).await.unwrap();
-    let provider = Arc::new(get_provider(rpc_url.clone()).await.unwrap());
-
-    let mut join_set = JoinSet::new();
-
-    // let launcher_addr = H160::from_str("0x558b6738759a5DBa97aab14CE602b8d20ba05087").unwrap();
-    // let simulator_addr = H160::from_str("0x6F804aeE9d94DcE18B874defbc5DFC0334C14c99").unwrap();
-    let weth_addr = H160::from_str("0x4200000000000000000000000000000000000006").unwrap();
-    let weth_balance_slot = calculate_balance_slot(simulator_addr, 3);
-    let weth_balance_value = H256::from_str("0x00000000000000000000000000000000000000000000d3c21bcecceda1000000").unwrap();
-    let usdc_addr = H160::from_str("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913").unwrap();
-    let usdc_balance_slot = calculate_balance_slot(simulator_addr, 9);
-    let usdc_balance_value = H256::from_str("0x0000000000000000000000000000000000000000000000000de0b6b3a7640000").unwrap();                
-    let pool_count = base_balance_list.len();
-
-    let storage_changes = vec![
-        (weth_addr, weth_balance_slot, weth_balance_value),
-        (usdc_addr, usdc_balance_slot, usdc_balance_value)];
-
-    // Clone values that need to be moved into spawned tasks
-    let storage_changes_clone = storage_changes.clone();
-    let base_balance_list_clone = base_balance_list.clone();
-
-    for sell_base_token in [true, false] {                    
-        for pool_index in 0..pool_count {
-            let shared_backend = shared_backend.clone();
-            let rpc_client = rpc_client.clone();
-            let provider = provider.clone();
-            let storage_changes = storage_changes_clone.clone();
-            let base_balance_list = base_balance_list_clone.clone();
-            
-            join_set.spawn(async move {                
-                find_best_trade_list(
-                    shared_backend,
-                    provider,
-                    rpc_client,
-                    from_addr,
-                    launcher_addr,
-                    simulator_addr,
-                    min_profit,
-                    min_swap_amount,
-                    storage_changes,    
-                    pool_index,
-                    sell_base_token,
-                    base_balance_list[pool_index] / 3).await
-            });
+#[async_trait]
+impl Strategy<Event, Action> for BaseStrategy {
+    async fn sync_state(&mut self) -> Result<()> {
+        self.load_tokens().await?;
+
+        if self.simulation_mode {
+            self.subscribers = start_trade_server(self.trade_server_url.clone()).await;
+            return Ok(());
         }
+
+        // Start trade collector
+        self.trade_info_map = start_trade_collector(self.trade_server_url.clone()).await;
+
+        let source = LauncherSource::new(self.to_addr, self.rpc_client.clone());
+        self.pool_catalog = PoolCatalog::load(
+            self.pool_cache_path.as_deref(), self.chain_id, &source, RetryPolicy::default()).await?;
+
+        for pool in &self.pool_catalog.pools {
+            // The swaps of a V4 pool come from the PoolManager
+            if let Some(key) = pool.v4_pool.and_then(|v4_pool| v4_pool.key) {
+                println!("V4 pool {:?}: fee {}, hooks {:?}, hook flags {:#06x}", key.id(), key.fee, key.hooks, key.hook_flags());
+            }
+
+            self.pools.push(pool.address);
+            self.pool_types.push(pool.pool_type);
+            self.v4_pools.push(pool.v4_pool);
+            self.pool_prices.push(U256::from(0));
+        }
+
+        Ok(())
     }
 
-    let trade_info_list: Vec<TradeInfo> = join_set.join_all().await.into_iter().flatten().collect();
-    trade_info_list
+    async fn process_event(&mut self, event: Event) -> Vec<Action> {
+        match event {
+            Event::NewBlock(block_info) if self.simulation_mode => {
+                self.on_new_block(block_info).await;
+                vec![]
+            }
+            Event::FlashBlock(flashblock) if self.simulation_mode && self.simulate_pending => {
+                self.on_pending_flash_block(*flashblock).await;
+                vec![]
+            }
+            Event::FlashBlock(flashblock) if !self.simulation_mode => {
+                self.on_flash_block(*flashblock).await
+                    .map(Action::SubmitTx)
+                    .into_iter()
+                    .collect()
+            }
+            Event::ArbitrumFeed(message) if self.simulation_mode && self.simulate_pending => {
+                self.on_pending_arbitrum_message(message).await;
+                vec![]
+            }
+            _ => vec![],
+        }
+    }
 }
+
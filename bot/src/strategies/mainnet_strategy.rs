@@ -1,10 +1,11 @@
-use tokio::{sync::mpsc::{UnboundedSender}};
 use ethers::{types::{transaction::eip2718::TypedTransaction, H160, H256, U256}, utils::{keccak256, WEI_IN_ETHER}};
 use ethers_providers::{Http, Provider};
 use tokio::sync::RwLock;
 use std::{str::FromStr, sync::Arc};
+use anyhow::Result;
+use async_trait::async_trait;
 
-use crate::{abi::{MSLauncherMainnet, MSLauncherRouterMainnet, MSSimulatorMainnet, IERC20}, collectors::{block_collector::{start_block_collector, BlockInfo}}, types::{decode_price, price_to_sqrt_price_x96, sqrt_price_x96_to_price, FlashblocksPayloadV1, LogReceipts, PoolType}};
+use crate::{abi::{MSLauncherMainnet, MSLauncherRouterMainnet, IERC20}, collectors::block_collector::BlockInfo, types::{Action, Event, PoolType, Strategy}};
 
 pub struct MainnetStrategy {
 
@@ -27,9 +28,7 @@ pub struct MainnetStrategy {
     pub chainlink_price: Arc<RwLock<U256>>,
 
     pub block_info: BlockInfo,
-    pub block_info_reader: Arc<RwLock<BlockInfo>>,
 
-    pub tx_sender: UnboundedSender<(TypedTransaction, Vec<H160>, U256)>,
     pub test_mode: bool,
     pub gas_limit: u64,
     pub step_count: u64,
@@ -40,29 +39,23 @@ pub struct MainnetStrategy {
 impl MainnetStrategy {
     pub async fn new(
         rpc_client: Arc<Provider<Http>>,
-        block_ws_url: String,
         mevshare_url: String,
         from_addr: H160,
         to_addr: H160,
         simulator_addr: H160,
-        tx_sender: UnboundedSender<(TypedTransaction, Vec<H160>, U256)>,
+        launcher_addr: H160,
         gas_limit: u64,
         test_mode: bool,
         step_count: u64,
         regression_count: u64
     ) -> Self {
-
-        let block_info_reader = start_block_collector(block_ws_url).await;
-        let block_info = block_info_reader.read().await.clone();
-
         Self {  
             rpc_client,
-            block_info,
-            block_info_reader,
+            block_info: BlockInfo::default(),
             mevshare_url,
             from_addr,
             to_addr,
-            launcher_addr: H160::from_str("0xa50d6ac929F1E7BA5d520662dBbF1Cb5D87E00d8").unwrap(),
+            launcher_addr,
             simulator_addr,
             pools: vec![],
             target_pools: vec![],
@@ -73,8 +66,6 @@ impl MainnetStrategy {
             chainlink_price: Arc::new(RwLock::new(U256::from(0))),
             pool_prices: Arc::new(RwLock::new(vec![])),
 
-            tx_sender,
-
             test_mode,
             gas_limit,
             step_count,
@@ -83,18 +74,17 @@ impl MainnetStrategy {
         }
     }
 
-    pub async fn run(&mut self) {
-        let launcher = MSLauncherMainnet::new(
-            H160::from_str("0xa50d6ac929F1E7BA5d520662dBbF1Cb5D87E00d8").unwrap(), self.rpc_client.clone());
+    async fn load_pools(&mut self) -> Result<()> {
+        let launcher = MSLauncherMainnet::new(self.launcher_addr, self.rpc_client.clone());
         let weth_addr = H160::from_str("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2").unwrap();
         let weth = IERC20::new(weth_addr, self.rpc_client.clone());
-        let pool_count = launcher.get_pool_count().call().await.unwrap();
+        let pool_count = launcher.get_pool_count().call().await?;
 
         for i in 0..pool_count.as_u64() as usize {
-            let (pool, pool_type) = launcher.get_pool_info(U256::from(i)).call().await.unwrap();
+            let (pool, pool_type) = launcher.get_pool_info(U256::from(i)).call().await?;
             let weth_balance = if pool_type != PoolType::UniswapV4 as u8 {
                 self.target_pools.push(pool);
-                weth.balance_of(pool).call().await.unwrap()
+                weth.balance_of(pool).call().await?
             } else {
                 self.target_pools.push(H160::from_str("0x000000000004444c5dc75cB358380D2e3dE08A90").unwrap());
                 WEI_IN_ETHER * 800
@@ -109,27 +99,28 @@ impl MainnetStrategy {
 
         // self.start_mevshare_loop().await;
 
-        loop {
-            let block_info = self.block_info_reader.read().await.clone();
-            if block_info.number == self.block_info.number {
-                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
-                continue;
-            }
+        Ok(())
+    }
 
-            self.block_info = block_info;
-            println!("Block Number: {:?}, Current Base Fee: {:?}, Next Base Fee: {:?}",
-                block_info.number,
-                block_info.base_fee_per_gas,
-                block_info.next_base_fee);
+    async fn on_new_block(&mut self, block_info: BlockInfo) -> Option<(TypedTransaction, Vec<H160>, U256)> {
+        if block_info.number == self.block_info.number {
+            return None;
+        }
 
-            for i in 0..self.pools.len() {
-                let sqrt_price_x96 = launcher.get_sqrt_price_x96(U256::from(i)).call().await.unwrap();
-                self.pool_prices.write().await[i] = sqrt_price_x96;
-            }
-            
-            // self.simulate().await;
-            self.send_tx().await;
+        self.block_info = block_info;
+        println!("Block Number: {:?}, Current Base Fee: {:?}, Next Base Fee: {:?}",
+            block_info.number,
+            block_info.base_fee_per_gas,
+            block_info.next_base_fee);
+
+        let launcher = MSLauncherMainnet::new(self.launcher_addr, self.rpc_client.clone());
+        for i in 0..self.pools.len() {
+            let sqrt_price_x96 = launcher.get_sqrt_price_x96(U256::from(i)).call().await.unwrap();
+            self.pool_prices.write().await[i] = sqrt_price_x96;
         }
+
+        // self.simulate().await;
+        Some(self.build_tx().await)
     }
 /*
     async fn start_mevshare_loop(&mut self) {
@@ -308,7 +299,7 @@ impl MainnetStrategy {
 
     }
  */
-    async fn send_tx(&mut self) {
+    async fn build_tx(&self) -> (TypedTransaction, Vec<H160>, U256) {
         let router = MSLauncherRouterMainnet::new(self.to_addr, self.rpc_client.clone());
         let mut tx = router.launch(
             self.bid_prices.read().await.clone(),
@@ -321,7 +312,7 @@ impl MainnetStrategy {
         tx.set_gas_price(self.block_info.next_base_fee * 5 / 4);   
 
         let next_block_number=  self.block_info.number + 2;
-        self.tx_sender.send((tx, self.target_pools.clone(), next_block_number));
+        (tx, self.target_pools.clone(), next_block_number)
     }
 
 
@@ -339,4 +330,21 @@ impl MainnetStrategy {
         H256::from_slice(&hash)
     }
 
-}
\ No newline at end of file
+}
+
+#[async_trait]
+impl Strategy<Event, Action> for MainnetStrategy {
+    async fn sync_state(&mut self) -> Result<()> {
+        self.load_pools().await
+    }
+
+    async fn process_event(&mut self, event: Event) -> Vec<Action> {
+        match event {
+            Event::NewBlock(block_info) => self.on_new_block(block_info).await
+                .map(|(tx, target_pools, block_number)| Action::SubmitBundle(tx, target_pools, block_number))
+                .into_iter()
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
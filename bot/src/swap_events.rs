@@ -0,0 +1,254 @@
+use std::collections::HashMap;
+
+use ethers::abi::{decode, ParamType, Token};
+use ethers::types::{H160, H256, I256, U256};
+use ethers::utils::keccak256;
+
+use crate::errors::SwapEventError;
+use crate::types::{LogItem, PoolType};
+
+// Signatures of the events the registry knows out of the box
+pub const UNISWAP_V3_SWAP: &str = "Swap(address,address,int256,int256,uint160,uint128,int24)";
+pub const PANCAKE_V3_SWAP: &str = "Swap(address,address,int256,int256,uint160,uint128,int24,uint128,uint128)";
+pub const UNISWAP_V4_SWAP: &str = "Swap(bytes32,address,int128,int128,uint160,uint128,int24,uint24)";
+pub const UNISWAP_V2_SWAP: &str = "Swap(address,uint256,uint256,uint256,uint256,address)";
+pub const UNISWAP_V2_SYNC: &str = "Sync(uint112,uint112)";
+pub const SOLIDLY_SWAP: &str = "Swap(address,address,uint256,uint256,uint256,uint256)";
+pub const SOLIDLY_SYNC: &str = "Sync(uint256,uint256)";
+pub const CURVE_TOKEN_EXCHANGE: &str = "TokenExchange(address,int128,uint256,int128,uint256)";
+pub const CURVE_CRYPTO_TOKEN_EXCHANGE: &str = "TokenExchange(address,uint256,uint256,uint256,uint256)";
+
+/// The topic0 of the event with `signature`.
+pub fn event_topic(signature: &str) -> H256 {
+    H256::from(keccak256(signature))
+}
+
+/// A swap, or a reserve update, decoded from a pool event. Amounts are seen
+/// from the pool, positive when the token flows in. Fields an event doesn't
+/// carry are `None`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SwapEvent {
+    /// The contract that emitted the event
+    pub address: H160,
+    /// Pool id of a pool living in a singleton, like the Uniswap V4 PoolManager
+    pub pool_id: Option<H256>,
+    pub amount0: I256,
+    pub amount1: I256,
+    pub sqrt_price_x96: Option<U256>,
+    pub liquidity: Option<u128>,
+    pub tick: Option<i32>,
+    /// In hundredths of a bip
+    pub fee: Option<u32>,
+    /// Reserves after the event, for constant product pools
+    pub reserves: Option<(U256, U256)>,
+}
+
+/// Decodes the logs of one event of one kind of pool.
+pub trait SwapDecoder: Send + Sync {
+    fn decode(&self, log: &LogItem) -> Result<SwapEvent, SwapEventError>;
+}
+
+impl<F> SwapDecoder for F
+where
+    F: Fn(&LogItem) -> Result<SwapEvent, SwapEventError> + Send + Sync,
+{
+    fn decode(&self, log: &LogItem) -> Result<SwapEvent, SwapEventError> {
+        self(log)
+    }
+}
+
+/// Layouts of the events decoded out of the box.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum SwapLayout {
+    /// Uniswap V3 and the pools that copied its event, Aerodrome CL and Algebra
+    UniswapV3,
+    /// The Uniswap V3 event followed by the protocol fees
+    PancakeV3,
+    /// Emitted by the PoolManager with the pool id as first topic
+    UniswapV4,
+    /// Amounts in and out of Uniswap V2 and Solidly pools
+    AmountsInOut,
+    /// Reserves of Uniswap V2 and Solidly pools
+    Sync,
+    /// A trade between coins `i` and `j` of a two coin Curve pool
+    CurveExchange { signed_ids: bool },
+}
+
+impl SwapDecoder for SwapLayout {
+    fn decode(&self, log: &LogItem) -> Result<SwapEvent, SwapEventError> {
+        let mut swap = SwapEvent { address: log.address, ..SwapEvent::default() };
+
+        match self {
+            SwapLayout::UniswapV3 | SwapLayout::PancakeV3 => {
+                let mut params = vec![
+                    ParamType::Int(256),  // amount0
+                    ParamType::Int(256),  // amount1
+                    ParamType::Uint(160), // sqrtPriceX96
+                    ParamType::Uint(128), // liquidity
+                    ParamType::Int(24),   // tick
+                ];
+                if *self == SwapLayout::PancakeV3 {
+                    // protocolFeesToken0, protocolFeesToken1
+                    params.extend([ParamType::Uint(128), ParamType::Uint(128)]);
+                }
+
+                let tokens = decode(&params, &log.data)?;
+                swap.amount0 = int(&tokens[0]);
+                swap.amount1 = int(&tokens[1]);
+                set_price(&mut swap, &tokens[2..])?;
+            }
+            SwapLayout::UniswapV4 => {
+                swap.pool_id = Some(topic(log, 1)?);
+
+                let tokens = decode(&[
+                    ParamType::Int(128),  // amount0
+                    ParamType::Int(128),  // amount1
+                    ParamType::Uint(160), // sqrtPriceX96
+                    ParamType::Uint(128), // liquidity
+                    ParamType::Int(24),   // tick
+                    ParamType::Uint(24),  // fee
+                ], &log.data)?;
+                // The PoolManager reports the swapper's balance changes
+                swap.amount0 = -int(&tokens[0]);
+                swap.amount1 = -int(&tokens[1]);
+                set_price(&mut swap, &tokens[2..5])?;
+                swap.fee = Some(narrow(uint(&tokens[5]), 24, "fee")?.as_u32());
+            }
+            SwapLayout::AmountsInOut => {
+                let tokens = decode(&[
+                    ParamType::Uint(256), // amount0In
+                    ParamType::Uint(256), // amount1In
+                    ParamType::Uint(256), // amount0Out
+                    ParamType::Uint(256), // amount1Out
+                ], &log.data)?;
+                let [in0, in1, out0, out1] = [0, 1, 2, 3].map(|i| signed(uint(&tokens[i])));
+                swap.amount0 = in0? - out0?;
+                swap.amount1 = in1? - out1?;
+            }
+            SwapLayout::Sync => {
+                let tokens = decode(&[ParamType::Uint(256), ParamType::Uint(256)], &log.data)?;
+                swap.reserves = Some((uint(&tokens[0]), uint(&tokens[1])));
+            }
+            SwapLayout::CurveExchange { signed_ids } => {
+                let id = if *signed_ids { ParamType::Int(128) } else { ParamType::Uint(256) };
+                let tokens = decode(&[id.clone(), ParamType::Uint(256), id, ParamType::Uint(256)], &log.data)?;
+                let sold = signed(uint(&tokens[1]))?;
+                let bought = signed(uint(&tokens[3]))?;
+
+                (swap.amount0, swap.amount1) = match (coin(&tokens[0])?, coin(&tokens[2])?) {
+                    (0, 1) => (sold, -bought),
+                    (1, 0) => (-bought, sold),
+                    (i, j) => return Err(SwapEventError::InvalidValue(format!("exchange of coins {} and {} in a two coin pool", i, j))),
+                };
+            }
+        }
+
+        Ok(swap)
+    }
+}
+
+/// The decoders of swap events, by pool type and event.
+///
+/// The default registry decodes the swaps of every [PoolType]. A new kind of
+/// pool only needs its decoders registered, strategies look them up by the
+/// type of the pool and the topic0 of the log.
+pub struct SwapEventDecoder {
+    decoders: HashMap<(PoolType, H256), Box<dyn SwapDecoder>>,
+}
+
+impl SwapEventDecoder {
+    /// A registry without any decoder.
+    pub fn empty() -> Self {
+        Self { decoders: HashMap::new() }
+    }
+
+    /// Decodes the logs of `pool_type` whose topic0 is `topic0` with `decoder`,
+    /// replacing the one registered before.
+    pub fn register(&mut self, pool_type: PoolType, topic0: H256, decoder: impl SwapDecoder + 'static) {
+        self.decoders.insert((pool_type, topic0), Box::new(decoder));
+    }
+
+    pub fn with_decoder(mut self, pool_type: PoolType, topic0: H256, decoder: impl SwapDecoder + 'static) -> Self {
+        self.register(pool_type, topic0, decoder);
+        self
+    }
+
+    pub fn decoder(&self, pool_type: PoolType, topic0: H256) -> Option<&dyn SwapDecoder> {
+        self.decoders.get(&(pool_type, topic0)).map(|decoder| decoder.as_ref())
+    }
+
+    /// The topics of the events registered for `pool_type`.
+    pub fn topics(&self, pool_type: PoolType) -> impl Iterator<Item = H256> + '_ {
+        self.decoders.keys().filter(move |(t, _)| *t == pool_type).map(|(_, topic0)| *topic0)
+    }
+
+    /// Decodes `log` of a pool of `pool_type`, `None` when it isn't one of
+    /// the events registered for the type.
+    pub fn decode(&self, pool_type: PoolType, log: &LogItem) -> Option<Result<SwapEvent, SwapEventError>> {
+        let topic0 = log.topics.first()?;
+        self.decoder(pool_type, *topic0).map(|decoder| decoder.decode(log))
+    }
+}
+
+impl Default for SwapEventDecoder {
+    fn default() -> Self {
+        Self::empty()
+            .with_decoder(PoolType::UniswapV3, event_topic(UNISWAP_V3_SWAP), SwapLayout::UniswapV3)
+            .with_decoder(PoolType::Aerodrome, event_topic(UNISWAP_V3_SWAP), SwapLayout::UniswapV3)
+            .with_decoder(PoolType::Algebra, event_topic(UNISWAP_V3_SWAP), SwapLayout::UniswapV3)
+            .with_decoder(PoolType::PancakeV3, event_topic(PANCAKE_V3_SWAP), SwapLayout::PancakeV3)
+            .with_decoder(PoolType::UniswapV4, event_topic(UNISWAP_V4_SWAP), SwapLayout::UniswapV4)
+            .with_decoder(PoolType::UniswapV2, event_topic(UNISWAP_V2_SWAP), SwapLayout::AmountsInOut)
+            .with_decoder(PoolType::UniswapV2, event_topic(UNISWAP_V2_SYNC), SwapLayout::Sync)
+            .with_decoder(PoolType::AerodromeClassic, event_topic(SOLIDLY_SWAP), SwapLayout::AmountsInOut)
+            .with_decoder(PoolType::AerodromeClassic, event_topic(SOLIDLY_SYNC), SwapLayout::Sync)
+            .with_decoder(PoolType::Curve, event_topic(CURVE_TOKEN_EXCHANGE), SwapLayout::CurveExchange { signed_ids: true })
+            .with_decoder(PoolType::Curve, event_topic(CURVE_CRYPTO_TOKEN_EXCHANGE), SwapLayout::CurveExchange { signed_ids: false })
+    }
+}
+
+fn topic(log: &LogItem, index: usize) -> Result<H256, SwapEventError> {
+    log.topics.get(index).copied().ok_or(SwapEventError::MissingTopic(index))
+}
+
+// The decoded tokens have the types asked for, so these can't fail
+fn uint(token: &Token) -> U256 {
+    token.clone().into_uint().unwrap()
+}
+
+fn int(token: &Token) -> I256 {
+    I256::from_raw(token.clone().into_int().unwrap())
+}
+
+fn signed(value: U256) -> Result<I256, SwapEventError> {
+    I256::try_from(value).map_err(|_| SwapEventError::InvalidValue(format!("amount {} is out of range", value)))
+}
+
+// A coin index, negative int128 ones end up out of range as well
+fn coin(token: &Token) -> Result<u8, SwapEventError> {
+    let id = match token {
+        Token::Int(id) | Token::Uint(id) => *id,
+        _ => unreachable!("coin indexes are integers"),
+    };
+    if id > U256::from(u8::MAX) {
+        return Err(SwapEventError::InvalidValue(format!("coin index {}", I256::from_raw(id))));
+    }
+    Ok(id.as_u32() as u8)
+}
+
+// The decoder doesn't check that values fit their type
+fn narrow(value: U256, bits: usize, name: &str) -> Result<U256, SwapEventError> {
+    if value.bits() > bits {
+        return Err(SwapEventError::InvalidValue(format!("{} {} doesn't fit in {} bits", name, value, bits)));
+    }
+    Ok(value)
+}
+
+// sqrtPriceX96, liquidity and tick
+fn set_price(swap: &mut SwapEvent, tokens: &[Token]) -> Result<(), SwapEventError> {
+    swap.sqrt_price_x96 = Some(narrow(uint(&tokens[0]), 160, "sqrtPriceX96")?);
+    swap.liquidity = Some(narrow(uint(&tokens[1]), 128, "liquidity")?.as_u128());
+    let tick = int(&tokens[2]);
+    swap.tick = Some(i32::try_from(tick).map_err(|_| SwapEventError::InvalidValue(format!("tick {}", tick)))?);
+    Ok(())
+}
@@ -0,0 +1,238 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use ethers::types::{BigEndianHash, H160, H256, U256};
+use ethers_providers::{Http, Middleware, Provider};
+use serde::{Deserialize, Serialize};
+
+use crate::abi::IERC20;
+use crate::errors::TokenError;
+use crate::price_math::{price_to_sqrt_price_x96, sqrt_price_x96_to_price};
+use crate::types::calculate_balance_slot;
+
+/// Mapping slots tried when looking for the `balanceOf` mapping of a token
+pub const MAX_BALANCE_SLOT: u64 = 20;
+
+/// Whole tokens of each side a simulator is funded with, as a power of ten
+pub const SIMULATOR_FUNDING_EXP: usize = 9;
+
+/// What the bot needs to know about a token.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TokenMetadata {
+    /// The zero address for native ETH
+    pub address: H160,
+    pub symbol: String,
+    pub decimals: u8,
+    /// Slot of the `balanceOf` mapping, to fund the simulator through state
+    /// overrides. `None` if it isn't known.
+    pub balance_slot: Option<u64>,
+}
+
+impl TokenMetadata {
+    pub fn new(address: H160, symbol: &str, decimals: u8) -> Self {
+        Self { address, symbol: symbol.to_string(), decimals, balance_slot: None }
+    }
+
+    pub fn native() -> Self {
+        Self::new(H160::zero(), "ETH", 18)
+    }
+
+    pub fn with_balance_slot(mut self, slot: u64) -> Self {
+        self.balance_slot = Some(slot);
+        self
+    }
+
+    /// The storage slot holding the balance of `holder`.
+    pub fn balance_slot_of(&self, holder: H160) -> Option<H256> {
+        self.balance_slot.map(|slot| calculate_balance_slot(holder, slot))
+    }
+}
+
+/// What the registry reads from the chain, normally the token contracts.
+#[async_trait]
+pub trait TokenSource: Send + Sync {
+    async fn symbol(&self, token: H160) -> Result<String, TokenError>;
+    async fn decimals(&self, token: H160) -> Result<u8, TokenError>;
+    async fn balance_of(&self, token: H160, holder: H160) -> Result<U256, TokenError>;
+    async fn storage_at(&self, token: H160, slot: H256) -> Result<H256, TokenError>;
+}
+
+/// Reads `IERC20` tokens over RPC.
+pub struct RpcTokenSource {
+    client: Arc<Provider<Http>>,
+}
+
+impl RpcTokenSource {
+    pub fn new(client: Arc<Provider<Http>>) -> Self {
+        Self { client }
+    }
+}
+
+fn rpc(e: impl std::fmt::Display) -> TokenError {
+    TokenError::Rpc(e.to_string())
+}
+
+#[async_trait]
+impl TokenSource for RpcTokenSource {
+    async fn symbol(&self, token: H160) -> Result<String, TokenError> {
+        IERC20::new(token, self.client.clone()).symbol().call().await.map_err(rpc)
+    }
+
+    async fn decimals(&self, token: H160) -> Result<u8, TokenError> {
+        IERC20::new(token, self.client.clone()).decimals().call().await.map_err(rpc)
+    }
+
+    async fn balance_of(&self, token: H160, holder: H160) -> Result<U256, TokenError> {
+        IERC20::new(token, self.client.clone()).balance_of(holder).call().await.map_err(rpc)
+    }
+
+    async fn storage_at(&self, token: H160, slot: H256) -> Result<H256, TokenError> {
+        self.client.get_storage_at(token, slot, None).await.map_err(rpc)
+    }
+}
+
+/// Metadata of the tokens the bot has seen, from a static table of well
+/// known tokens or read from the chain once and cached.
+#[derive(Clone, Debug, Default)]
+pub struct TokenRegistry {
+    tokens: HashMap<H160, TokenMetadata>,
+}
+
+impl TokenRegistry {
+    /// A registry with native ETH only.
+    pub fn new() -> Self {
+        Self::default().with_token(TokenMetadata::native())
+    }
+
+    /// A registry with the well known tokens of `chain_id`.
+    pub fn for_chain(chain_id: u64) -> Self {
+        let known: &[(&str, &str, u8, u64)] = match chain_id {
+            1 => &[
+                ("0xC02aaA39b223FE8D0A0e5C4F27eAD9083C756Cc2", "WETH", 18, 3),
+                ("0xA0b86991c6218b36c1d19D4a2e9Eb0cE3606eB48", "USDC", 6, 9),
+            ],
+            10 => &[
+                ("0x4200000000000000000000000000000000000006", "WETH", 18, 3),
+                ("0x4200000000000000000000000000000000000042", "OP", 18, 0),
+                ("0x0b2C639c533813f4Aa9D7837CAf62653d097Ff85", "USDC", 6, 9),
+                ("0x7F5c764cBc14f9669B88837ca1490cCa17c31607", "USDC.e", 6, 0),
+            ],
+            8453 => &[
+                ("0x4200000000000000000000000000000000000006", "WETH", 18, 3),
+                ("0x833589fCD6eDb6E08f4c7C32D4f71b54bdA02913", "USDC", 6, 9),
+            ],
+            _ => &[],
+        };
+
+        known.iter().fold(Self::new(), |registry, (address, symbol, decimals, slot)| {
+            registry.with_token(TokenMetadata::new(address.parse().unwrap(), symbol, *decimals).with_balance_slot(*slot))
+        })
+    }
+
+    pub fn with_token(mut self, token: TokenMetadata) -> Self {
+        self.insert(token);
+        self
+    }
+
+    pub fn insert(&mut self, token: TokenMetadata) {
+        self.tokens.insert(token.address, token);
+    }
+
+    pub fn get(&self, address: H160) -> Option<&TokenMetadata> {
+        self.tokens.get(&address)
+    }
+
+    /// The metadata of `address`, read from `source` the first time. The
+    /// balance slot is found by matching the balance of `holder`, which has
+    /// to hold some of the token, against the storage of the token.
+    pub async fn resolve(
+        &mut self,
+        source: &impl TokenSource,
+        address: H160,
+        holder: H160,
+    ) -> Result<TokenMetadata, TokenError> {
+        if let Some(token) = self.get(address) {
+            return Ok(token.clone());
+        }
+
+        let token = TokenMetadata {
+            address,
+            symbol: source.symbol(address).await?,
+            decimals: source.decimals(address).await?,
+            balance_slot: find_balance_slot(source, address, holder).await?,
+        };
+        self.insert(token.clone());
+
+        Ok(token)
+    }
+}
+
+async fn find_balance_slot(source: &impl TokenSource, token: H160, holder: H160) -> Result<Option<u64>, TokenError> {
+    let balance = source.balance_of(token, holder).await?;
+    if balance.is_zero() {
+        return Ok(None);
+    }
+
+    let mut expected = H256::zero();
+    balance.to_big_endian(expected.as_bytes_mut());
+    for slot in 0..=MAX_BALANCE_SLOT {
+        if source.storage_at(token, calculate_balance_slot(holder, slot)).await? == expected {
+            return Ok(Some(slot));
+        }
+    }
+
+    Ok(None)
+}
+
+/// The two tokens of a launcher's pools, in pool order, and which of them
+/// is the base token.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TokenPair {
+    pub token0: TokenMetadata,
+    pub token1: TokenMetadata,
+    pub base_is_token0: bool,
+}
+
+impl TokenPair {
+    /// Orders the tokens like pools do, by address.
+    pub fn new(base: TokenMetadata, quote: TokenMetadata) -> Self {
+        if base.address < quote.address {
+            Self { token0: base, token1: quote, base_is_token0: true }
+        } else {
+            Self { token0: quote, token1: base, base_is_token0: false }
+        }
+    }
+
+    pub fn base(&self) -> &TokenMetadata {
+        if self.base_is_token0 { &self.token0 } else { &self.token1 }
+    }
+
+    pub fn quote(&self) -> &TokenMetadata {
+        if self.base_is_token0 { &self.token1 } else { &self.token0 }
+    }
+
+    /// Storage overrides funding `simulator` with both tokens, leaving out
+    /// those whose balance slot is unknown.
+    pub fn simulator_balances(&self, simulator: H160) -> Vec<(H160, H256, H256)> {
+        [self.base(), self.quote()]
+            .into_iter()
+            .filter_map(|token| {
+                let balance = U256::exp10(token.decimals as usize + SIMULATOR_FUNDING_EXP);
+                Some((token.address, token.balance_slot_of(simulator)?, H256::from_uint(&balance)))
+            })
+            .collect()
+    }
+
+    /// The price of the base token in the quote token at `sqrt_price_x96`,
+    /// scaled by 1e8.
+    pub fn price(&self, sqrt_price_x96: U256) -> U256 {
+        sqrt_price_x96_to_price(sqrt_price_x96, self.base_is_token0, &self.token0, &self.token1)
+    }
+
+    /// The `sqrt_price_x96` at which the base token is worth `price` quote
+    /// tokens, scaled by 1e8, rounded down.
+    pub fn sqrt_price_x96(&self, price: U256) -> U256 {
+        price_to_sqrt_price_x96(price, self.base_is_token0, &self.token0, &self.token1)
+    }
+}
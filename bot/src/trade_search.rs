@@ -0,0 +1,358 @@
+use ethers::types::{H160, I256, U256};
+use ethers::utils::WEI_IN_ETHER;
+use serde::{Deserialize, Serialize};
+
+use crate::price_math::mul_div;
+use crate::types::TradeInfo;
+
+/// 1/phi in 1e9 fixed point, the fraction golden-section keeps per step
+const INV_PHI: u64 = 618_033_989;
+const INV_PHI_ONE: u64 = 1_000_000_000;
+
+/// How the first delta of the trade ladder is searched for.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SearchAlgorithm {
+    /// Halves the range until the smallest profitable delta is found.
+    /// Assumes larger deltas stay profitable.
+    #[default]
+    Bisection,
+    /// Narrows in on the most profitable delta. Assumes the profit rises
+    /// and then falls with the delta.
+    GoldenSection,
+    /// Steps up from zero, doubling the step until a delta is profitable,
+    /// then bisects the last step. Cheap when trades are profitable early.
+    AdaptiveStep,
+    /// Newton's method on the profit curve, with the slope taken between
+    /// the two ends of the bracket (Illinois regula falsi). Bisects while
+    /// the simulator returns no profit for an end.
+    Newton,
+}
+
+/// Parameters of the trade search, see [find_start_delta].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SearchParams {
+    pub algorithm: SearchAlgorithm,
+    /// Width of the delta range the search stops at
+    pub tolerance: U256,
+    /// Evaluations the search may spend, the ladder comes on top
+    pub max_evaluations: usize,
+    /// Trades in the ladder following the delta found
+    pub regression_count: usize,
+    /// The ladder steps `start_delta / step_count` apart, and at most
+    /// `max_regression_step`
+    pub step_count: u64,
+    pub max_regression_step: U256,
+    /// Gas of each simulated call
+    pub gas_limit: U256,
+}
+
+impl Default for SearchParams {
+    fn default() -> Self {
+        Self {
+            algorithm: SearchAlgorithm::Bisection,
+            tolerance: WEI_IN_ETHER / 1000,
+            max_evaluations: 64,
+            regression_count: 10,
+            step_count: 100,
+            max_regression_step: WEI_IN_ETHER * 3 / 2,
+            gas_limit: U256::from(10_000_000),
+        }
+    }
+}
+
+impl SearchParams {
+    pub fn with_algorithm(mut self, algorithm: SearchAlgorithm) -> Self {
+        self.algorithm = algorithm;
+        self
+    }
+
+    pub fn with_regression(mut self, step_count: u64, regression_count: usize) -> Self {
+        self.step_count = step_count;
+        self.regression_count = regression_count;
+        self
+    }
+
+    pub fn regression_step(&self, max_delta: U256) -> U256 {
+        self.max_regression_step.min(max_delta / self.step_count.max(1))
+    }
+}
+
+/// One trade of a [PriceLadder]. The price and amount come from pricing
+/// its delta, the profit and gas from trading it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Rung {
+    pub delta: U256,
+    pub sqrt_price_x96: U256,
+    pub swap_amount: U256,
+    /// `None` until the rung is traded, or if trading it failed
+    pub profit: Option<I256>,
+    pub gas_used: U256,
+}
+
+impl Rung {
+    pub fn is_tradable(&self, min_profit: I256, min_swap_amount: U256) -> bool {
+        self.profit.is_some_and(|profit| profit > min_profit) && self.swap_amount > min_swap_amount
+    }
+}
+
+/// The trades of one pool and side, `step` apart from the delta the search
+/// found.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PriceLadder {
+    /// The delta of the first rung
+    pub start_delta: U256,
+    pub step: U256,
+    pub rungs: Vec<Rung>,
+}
+
+impl PriceLadder {
+    /// `params.regression_count` rungs from `start_delta` on, stepping by
+    /// the regression step of `max_delta`.
+    pub fn new(params: &SearchParams, max_delta: U256, start_delta: U256) -> Self {
+        let step = params.regression_step(max_delta);
+        let rungs = (0..params.regression_count)
+            .map(|i| Rung { delta: start_delta + step * i, ..Rung::default() })
+            .collect();
+
+        Self { start_delta, step, rungs }
+    }
+
+    /// The tradable rungs, each with its own delta and quote.
+    pub fn trades(
+        &self,
+        launcher_addr: H160,
+        pool_index: usize,
+        sell_base_token: bool,
+        min_profit: I256,
+        min_swap_amount: U256,
+    ) -> Vec<TradeInfo> {
+        self.rungs
+            .iter()
+            .filter(|rung| rung.is_tradable(min_profit, min_swap_amount))
+            .map(|rung| TradeInfo {
+                launcher_addr,
+                pool_index,
+                sell_base_token,
+                start_delta: self.start_delta,
+                delta: rung.delta,
+                sqrt_price_x96: rung.sqrt_price_x96,
+                trade_price: U256::zero(),
+                deviation_bps: U256::zero(),
+                swap_amount: rung.swap_amount,
+                profit: rung.profit.unwrap_or_default().into_raw(),
+                gas_used: rung.gas_used,
+            })
+            .collect()
+    }
+}
+
+/// The delta a search settled on and what it cost.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct SearchReport {
+    /// Zero if no profitable delta was found
+    pub delta: U256,
+    pub evaluations: usize,
+}
+
+/// The trades found for one pool and side.
+#[derive(Clone, Debug, Default)]
+pub struct TradeSearch {
+    pub trades: Vec<TradeInfo>,
+    /// Profit evaluations of the search, without the ladder
+    pub evaluations: usize,
+}
+
+/// Searches `0..max_delta` for the delta the trade ladder starts at with
+/// `params.algorithm`. `profit_at` gives the profit of a delta, `None` if
+/// there is none, e.g. because the trade reverts. Its errors end the search.
+pub fn find_start_delta<E>(
+    params: &SearchParams,
+    min_profit: I256,
+    max_delta: U256,
+    mut profit_at: impl FnMut(U256) -> Result<Option<I256>, E>,
+) -> Result<SearchReport, E> {
+    let mut search = Search { params, min_profit, evaluations: 0, profit_at: &mut profit_at };
+
+    let delta = match params.algorithm {
+        SearchAlgorithm::Bisection => search.bisect(U256::zero(), max_delta, U256::zero())?,
+        SearchAlgorithm::GoldenSection => search.golden_section(max_delta)?,
+        SearchAlgorithm::AdaptiveStep => search.adaptive_step(max_delta)?,
+        SearchAlgorithm::Newton => search.newton(max_delta)?,
+    };
+
+    Ok(SearchReport { delta, evaluations: search.evaluations })
+}
+
+struct Search<'a, F> {
+    params: &'a SearchParams,
+    min_profit: I256,
+    evaluations: usize,
+    profit_at: &'a mut F,
+}
+
+impl<F, E> Search<'_, F>
+where
+    F: FnMut(U256) -> Result<Option<I256>, E>,
+{
+    fn profit(&mut self, delta: U256) -> Result<Option<I256>, E> {
+        self.evaluations += 1;
+        (self.profit_at)(delta)
+    }
+
+    fn is_profitable(&mut self, delta: U256) -> Result<bool, E> {
+        Ok(self.profit(delta)?.is_some_and(|profit| profit > self.min_profit))
+    }
+
+    fn has_budget(&self) -> bool {
+        self.evaluations < self.params.max_evaluations
+    }
+
+    fn is_narrow(&self, low: U256, high: U256) -> bool {
+        high - low <= self.params.tolerance
+    }
+
+    // The smallest profitable delta of `low..high`, or `delta` if there is none
+    fn bisect(&mut self, mut low: U256, mut high: U256, mut delta: U256) -> Result<U256, E> {
+        while !self.is_narrow(low, high) && self.has_budget() {
+            let middle = (low + high) / 2;
+            if self.is_profitable(middle)? {
+                high = middle;
+                delta = middle;
+            } else {
+                low = middle;
+            }
+        }
+
+        Ok(delta)
+    }
+
+    fn golden_section(&mut self, max_delta: U256) -> Result<U256, E> {
+        let inner = |low: U256, high: U256| (high - low) * INV_PHI / INV_PHI_ONE;
+        let (mut low, mut high) = (U256::zero(), max_delta);
+        let (mut left, mut right) = (high - inner(low, high), low + inner(low, high));
+        let (mut left_profit, mut right_profit) = (self.profit(left)?, self.profit(right)?);
+        let mut best = if left_profit >= right_profit { (left, left_profit) } else { (right, right_profit) };
+
+        while !self.is_narrow(low, high) && self.has_budget() {
+            // `None` sorts below any profit
+            if left_profit >= right_profit {
+                (high, right, right_profit) = (right, left, left_profit);
+                left = high - inner(low, high);
+                left_profit = self.profit(left)?;
+                if left_profit > best.1 {
+                    best = (left, left_profit);
+                }
+            } else {
+                (low, left, left_profit) = (left, right, right_profit);
+                right = low + inner(low, high);
+                right_profit = self.profit(right)?;
+                if right_profit > best.1 {
+                    best = (right, right_profit);
+                }
+            }
+        }
+
+        match best.1 {
+            Some(profit) if profit > self.min_profit => Ok(best.0),
+            _ => Ok(U256::zero()),
+        }
+    }
+
+    fn adaptive_step(&mut self, max_delta: U256) -> Result<U256, E> {
+        let mut low = U256::zero();
+        let mut step = self.params.tolerance.max(U256::one());
+
+        while self.has_budget() {
+            let high = (low + step).min(max_delta);
+            if self.is_profitable(high)? {
+                return self.bisect(low, high, high);
+            }
+            if high == max_delta {
+                break;
+            }
+            low = high;
+            step *= 2;
+        }
+
+        Ok(U256::zero())
+    }
+
+    fn newton(&mut self, max_delta: U256) -> Result<U256, E> {
+        // The search moves the profitable end of the bracket down to the
+        // smallest profitable delta, the unprofitable end up to it
+        let (mut low, mut high) = (U256::zero(), max_delta);
+        let tolerance = self.params.tolerance;
+        let excess = |profit: Option<I256>, min_profit: I256| profit.map(|profit| profit - min_profit);
+
+        let mut high_excess = match excess(self.profit(high)?, self.min_profit) {
+            Some(high_excess) if high_excess > I256::zero() => high_excess,
+            _ => return self.bisect(low, high, U256::zero()),
+        };
+        let mut low_excess: Option<I256> = None;
+        // The profitable end before the last, for the slope until the
+        // unprofitable end has a profit
+        let mut previous_high: Option<(U256, I256)> = None;
+        // Which end the last step moved, and whether the step came from the slope
+        let mut last_step: Option<(bool, bool)> = None;
+
+        while !self.is_narrow(low, high) && self.has_budget() {
+            // A step from the slope lands next to the crossing, so the next
+            // one checks a tolerance to the other side of it
+            let from_slope = match last_step {
+                Some((true, true)) => Some(high - tolerance).filter(|delta| *delta > low),
+                Some((false, true)) => Some(low + tolerance).filter(|delta| *delta < high),
+                _ => None,
+            };
+            let slope_step = match low_excess {
+                Some(low_excess) => interpolate(low, high, low_excess, high_excess),
+                None => previous_high.and_then(|(previous, previous_excess)| {
+                    extrapolate(high, high_excess, previous, previous_excess).filter(|delta| *delta > low)
+                }),
+            };
+            let (delta, is_slope_step) = match (from_slope, slope_step) {
+                (Some(delta), _) => (delta, false),
+                (None, Some(delta)) => (delta, true),
+                (None, None) => ((low + high) / 2, false),
+            };
+
+            match excess(self.profit(delta)?, self.min_profit) {
+                Some(delta_excess) if delta_excess > I256::zero() => {
+                    previous_high = Some((high, high_excess));
+                    (high, high_excess) = (delta, delta_excess);
+                    // Illinois: halve the end kept twice so it can't hold the step back
+                    if matches!(last_step, Some((true, _))) {
+                        low_excess = low_excess.map(|low_excess| low_excess / 2);
+                    }
+                    last_step = Some((true, is_slope_step));
+                }
+                delta_excess => {
+                    (low, low_excess) = (delta, delta_excess);
+                    if matches!(last_step, Some((false, _))) {
+                        high_excess = (high_excess / 2).max(I256::one());
+                    }
+                    last_step = Some((false, is_slope_step));
+                }
+            }
+        }
+
+        Ok(high)
+    }
+}
+
+// Where the line between the ends of the bracket crosses the minimum profit
+fn interpolate(low: U256, high: U256, low_excess: I256, high_excess: I256) -> Option<U256> {
+    let (below, above) = (low_excess.unsigned_abs(), high_excess.into_raw());
+    let delta = low + mul_div(high - low, below, below.checked_add(above)?).ok()?;
+    (delta > low && delta < high).then_some(delta)
+}
+
+// Where the line through two profitable deltas, `low` below `high`, crosses
+// the minimum profit below them
+fn extrapolate(low: U256, low_excess: I256, high: U256, high_excess: I256) -> Option<U256> {
+    if high_excess <= low_excess {
+        return None;
+    }
+    let rise = (high_excess - low_excess).into_raw();
+    low.checked_sub(mul_div(high - low, low_excess.into_raw(), rise).ok()?)
+}
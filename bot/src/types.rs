@@ -1,14 +1,11 @@
 use alloy_primitives::TxKind;
-use ethers::abi::decode;
-use ethers::abi::ParamType;
-use ethers::abi::Tokenizable;
 use ethers::types::transaction::eip2718::TypedTransaction;
 use ethers::types::BlockId;
 use ethers::types::Bytes;
+use ethers::types::{Bloom, Withdrawal};
 use ethers::types::I256;
 use ethers::utils::format_ether;
 use ethers::utils::keccak256;
-use ethers::utils::WEI_IN_ETHER;
 use ethers_providers::spoof;
 use grouping_by::GroupingBy;
 use jsonrpsee::core::client::SubscriptionClientT;
@@ -23,12 +20,16 @@ use revm_trace::evm::builder::get_provider;
 use revm_trace::evm::NoOpInspector;
 use revm_trace::revm::bytecode::eof::printer::print;
 use revm_trace::revm::context::result::ExecutionResult;
+use revm_trace::revm::context::ContextTr;
+use revm_trace::revm::database::{AccountState, CacheDB, DatabaseRef};
 use revm_trace::types::AnyNetworkProvider;
 use revm_trace::types::ArcAnyNetworkProvider;
 use revm_trace::types::StateOverride;
+use revm_trace::types::StorageDiff;
 use revm_trace::SharedBackend;
 use revm_trace::SimulationBatch;
 use revm_trace::SimulationTx;
+use revm_trace::TraceEvm;
 use revm_trace::TransactionTrace;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -40,7 +41,7 @@ use ethers::types::H256;
 use ethers::types::U256;
 use ethers::types::Block;
 use tokio::sync::broadcast::Sender;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::pin::Pin;
 use tokio_stream::Stream;
 use tokio_stream::StreamExt;
@@ -52,6 +53,119 @@ use ethers_providers::{Http, Provider, RawCall};
 use tokio::sync::RwLock;
 use std::{str::FromStr, sync::Arc};
 use ethers::abi::AbiDecode;
+use crate::collectors::binance_collector::BinancePrice;
+use crate::collectors::arbitrum_feed::ArbitrumFeedMessage;
+use crate::errors::InvalidPoolType;
+use crate::tokens::TokenPair;
+use crate::cl_quoter::LauncherQuoter;
+use crate::errors::QuoteError;
+use crate::trade_search::{find_start_delta, PriceLadder, SearchParams, SearchReport, TradeSearch};
+
+
+/// A stream of events emitted by a [Collector](Collector).
+pub type CollectorStream<'a, E> = Pin<Box<dyn Stream<Item = E> + Send + 'a>>;
+
+/// Collector trait, which defines a source of events.
+#[async_trait]
+pub trait Collector<E>: Send + Sync {
+    /// Returns the core event stream for the collector.
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, E>>;
+}
+
+/// Strategy trait, which defines the core logic for each opportunity.
+#[async_trait]
+pub trait Strategy<E, A>: Send + Sync {
+    /// Sync the initial state of the strategy if needed, usually by fetching
+    /// onchain data.
+    async fn sync_state(&mut self) -> Result<()>;
+
+    /// Process an event, and return actions if needed.
+    async fn process_event(&mut self, event: E) -> Vec<A>;
+}
+
+/// Executor trait, responsible for executing actions returned by strategies.
+#[async_trait]
+pub trait Executor<A>: Send + Sync {
+    /// Execute an action.
+    async fn execute(&self, action: A) -> Result<()>;
+}
+
+/// CollectorMap is a wrapper around a [Collector](Collector) that maps outgoing
+/// events to a different type.
+pub struct CollectorMap<E, F> {
+    collector: Box<dyn Collector<E>>,
+    f: F,
+}
+
+impl<E, F> CollectorMap<E, F> {
+    pub fn new(collector: Box<dyn Collector<E>>, f: F) -> Self {
+        Self { collector, f }
+    }
+}
+
+#[async_trait]
+impl<E1, E2, F> Collector<E2> for CollectorMap<E1, F>
+where
+    E1: Send + Sync + 'static,
+    E2: Send + Sync + 'static,
+    F: Fn(E1) -> E2 + Send + Sync + Clone + 'static,
+{
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, E2>> {
+        let stream = self.collector.get_event_stream().await?;
+        let f = self.f.clone();
+        let stream = stream.map(f);
+        Ok(Box::pin(stream))
+    }
+}
+
+/// ExecutorMap is a wrapper around an [Executor](Executor) that maps incoming
+/// actions to a different type. Actions the map function rejects are dropped.
+pub struct ExecutorMap<A, F> {
+    executor: Box<dyn Executor<A>>,
+    f: F,
+}
+
+impl<A, F> ExecutorMap<A, F> {
+    pub fn new(executor: Box<dyn Executor<A>>, f: F) -> Self {
+        Self { executor, f }
+    }
+}
+
+#[async_trait]
+impl<A1, A2, F> Executor<A1> for ExecutorMap<A2, F>
+where
+    A1: Send + Sync + 'static,
+    A2: Send + Sync + 'static,
+    F: Fn(A1) -> Option<A2> + Send + Sync + Clone + 'static,
+{
+    async fn execute(&self, action: A1) -> Result<()> {
+        match (self.f)(action) {
+            Some(action) => self.executor.execute(action).await,
+            None => Ok(()),
+        }
+    }
+}
+
+/// Convenience enum containing all the events that can be emitted by collectors.
+#[derive(Debug, Clone)]
+pub enum Event {
+    NewBlock(BlockInfo),
+    FlashBlock(Box<FlashblocksPayloadV1>),
+    ArbitrumFeed(ArbitrumFeedMessage),
+    BinancePrice(BinancePrice),
+    MevShareEvent(mev_share::sse::Event),
+    Timer(u64),
+}
+
+/// Convenience enum containing all the actions that can be emitted by strategies.
+#[derive(Debug, Clone)]
+pub enum Action {
+    /// A transaction for the sequencer / public mempool.
+    SubmitTx(TypedTransaction),
+    /// A transaction for a block builder, with the pools it targets and the
+    /// block number it is valid for.
+    SubmitBundle(TypedTransaction, Vec<H160>, U256),
+}
 
 
 #[derive(Clone, Copy, Debug, Serialize, Deserialize)]
@@ -69,6 +183,26 @@ pub struct TradeInfo {
     pub gas_used: U256,
 }
 
+/// Renders trades as a fixed-width table, one row per trade.
+pub fn format_trade_info_table(trade_info_list: &[TradeInfo]) -> String {
+    let mut table = format!("{:<44} {:>4} {:>4} {:>12} {:>50} {:>12} {:>12} {:>10}\n",
+        "Launcher", "Pool", "Side", "Delta", "SqrtPriceX96", "Swap Amount", "Profit", "Gas");
+
+    for t in trade_info_list {
+        table.push_str(&format!("{:<44} {:>4} {:>4} {:>12.4} {:>50} {:>12.4} {:>12.6} {:>10}\n",
+            format!("{:?}", t.launcher_addr),
+            t.pool_index,
+            if t.sell_base_token { "Bid" } else { "Ask" },
+            format_ether(t.delta).parse::<f64>().unwrap_or_default(),
+            t.sqrt_price_x96,
+            format_ether(t.swap_amount).parse::<f64>().unwrap_or_default(),
+            format_ether(t.profit).parse::<f64>().unwrap_or_default(),
+            t.gas_used));
+    }
+
+    table
+}
+
 /// Represents the modified portions of an execution payload within a flashblock.
 /// This structure contains only the fields that can be updated during block construction,
 /// such as state root, receipts, logs, and new transactions. Other immutable block fields
@@ -76,7 +210,29 @@ pub struct TradeInfo {
 /// the block's construction.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ExecutionPayloadFlashblockDeltaV1 {
-    pub transactions: Vec<Bytes>
+    /// The state root of the block after this flashblock
+    #[serde(default)]
+    pub state_root: H256,
+    /// The receipts root of the block after this flashblock
+    #[serde(default)]
+    pub receipts_root: H256,
+    /// The logs bloom of the block after this flashblock
+    #[serde(default)]
+    pub logs_bloom: Bloom,
+    /// Gas used by the block so far
+    #[serde(default, with = "alloy_serde::quantity")]
+    pub gas_used: u64,
+    /// The hash of the block as of this flashblock
+    #[serde(default)]
+    pub block_hash: H256,
+    /// Transactions added by this flashblock
+    pub transactions: Vec<Bytes>,
+    /// Withdrawals added by this flashblock
+    #[serde(default)]
+    pub withdrawals: Vec<Withdrawal>,
+    /// The withdrawals root of the block after this flashblock
+    #[serde(default)]
+    pub withdrawals_root: H256,
 }
 
 /// Represents the base configuration of an execution payload that remains constant
@@ -85,6 +241,18 @@ pub struct ExecutionPayloadFlashblockDeltaV1 {
 /// block creation and cannot be modified.
 #[derive(Clone, Debug, Default, Deserialize, Serialize)]
 pub struct ExecutionPayloadBaseV1 {
+    /// The parent beacon block root of the block.
+    #[serde(default)]
+    pub parent_beacon_block_root: H256,
+    /// The parent hash of the block.
+    #[serde(default)]
+    pub parent_hash: H256,
+    /// The fee recipient of the block.
+    #[serde(default)]
+    pub fee_recipient: H160,
+    /// The previous randao of the block.
+    #[serde(default)]
+    pub prev_randao: H256,
     #[serde(with = "alloy_serde::quantity")]
     pub block_number: u64,
     /// The gas limit of the block.
@@ -93,6 +261,9 @@ pub struct ExecutionPayloadBaseV1 {
     /// The timestamp of the block.
     #[serde(with = "alloy_serde::quantity")]
     pub timestamp: u64,
+    /// The extra data of the block.
+    #[serde(default)]
+    pub extra_data: Bytes,
     /// The base fee per gas of the block.
     pub base_fee_per_gas: U256,
 }
@@ -106,17 +277,95 @@ pub struct ExecutionPayloadBaseV1 {
 pub struct MetadataV1 {
     #[serde(with = "alloy_serde::quantity")]
     pub block_number: u64,
-    pub receipts: Value
+    /// Balances of the accounts this flashblock changed
+    #[serde(default)]
+    pub new_account_balances: HashMap<H160, U256>,
+    /// Receipts of the transactions this flashblock added, by transaction hash
+    pub receipts: BTreeMap<H256, FlashblockReceipt>,
 }
 
+impl MetadataV1 {
+    /// The hash of the transaction with envelope `raw`, and its receipt.
+    pub fn receipt_of(&self, raw: &[u8]) -> (H256, Option<&FlashblockReceipt>) {
+        let hash = H256::from(keccak256(raw));
+        (hash, self.receipts.get(&hash))
+    }
+}
 
-/// Represents the base configuration of an execution payload that remains constant
-/// throughout block construction. This includes fundamental block properties like
-/// parent hash, block number, and other header fields that are determined at
-/// block creation and cannot be modified.
-#[derive(Clone, Debug, Default, Deserialize, Serialize)]
-pub struct LogReceipts {
-    pub logs: Vec<LogItem>
+/// A receipt of a flashblock, tagged with the type of its transaction.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize, Serialize)]
+pub enum FlashblockReceipt {
+    Legacy(ReceiptV1),
+    Eip2930(ReceiptV1),
+    Eip1559(ReceiptV1),
+    Eip7702(ReceiptV1),
+    Deposit(DepositReceiptV1),
+}
+
+impl FlashblockReceipt {
+    /// The EIP-2718 type of the transaction.
+    pub fn tx_type(&self) -> u8 {
+        match self {
+            FlashblockReceipt::Legacy(_) => 0,
+            FlashblockReceipt::Eip2930(_) => 1,
+            FlashblockReceipt::Eip1559(_) => 2,
+            FlashblockReceipt::Eip7702(_) => 4,
+            FlashblockReceipt::Deposit(_) => 0x7e,
+        }
+    }
+
+    pub fn receipt(&self) -> &ReceiptV1 {
+        match self {
+            FlashblockReceipt::Legacy(receipt)
+            | FlashblockReceipt::Eip2930(receipt)
+            | FlashblockReceipt::Eip1559(receipt)
+            | FlashblockReceipt::Eip7702(receipt) => receipt,
+            FlashblockReceipt::Deposit(deposit) => &deposit.receipt,
+        }
+    }
+
+    /// Whether the transaction succeeded, a reverted one has no logs.
+    pub fn status(&self) -> bool {
+        self.receipt().status
+    }
+
+    pub fn cumulative_gas_used(&self) -> u64 {
+        self.receipt().cumulative_gas_used
+    }
+
+    pub fn logs(&self) -> &[LogItem] {
+        &self.receipt().logs
+    }
+
+    pub fn is_deposit(&self) -> bool {
+        matches!(self, FlashblockReceipt::Deposit(_))
+    }
+}
+
+/// The fields every receipt has.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReceiptV1 {
+    #[serde(with = "alloy_serde::quantity")]
+    pub status: bool,
+    /// Gas used by the block up to and including this transaction
+    #[serde(with = "alloy_serde::quantity")]
+    pub cumulative_gas_used: u64,
+    pub logs: Vec<LogItem>,
+}
+
+/// The receipt of an L1 deposit.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DepositReceiptV1 {
+    #[serde(flatten)]
+    pub receipt: ReceiptV1,
+    /// Nonce of the depositor, set since Regolith
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "alloy_serde::quantity::opt")]
+    pub deposit_nonce: Option<u64>,
+    /// Set since Canyon
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "alloy_serde::quantity::opt")]
+    pub deposit_receipt_version: Option<u64>,
 }
 
 /// A log produced by a transaction.
@@ -140,94 +389,37 @@ pub struct FlashblocksPayloadV1 {
     pub metadata: MetadataV1,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+/// Kinds of pools. The first four are the pool types of the launcher
+/// contract, in its order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum PoolType {
     UniswapV3,
     PancakeV3,
+    /// Aerodrome Slipstream, its concentrated liquidity pools
     Aerodrome,
     UniswapV4,
+    UniswapV2,
+    /// Aerodrome and Velodrome volatile and stable pools
+    AerodromeClassic,
+    Algebra,
+    Curve,
 }
 
-impl From<u8> for PoolType {
-    fn from(value: u8) -> Self {
+/// Converts a pool type of the launcher contract.
+impl TryFrom<u8> for PoolType {
+    type Error = InvalidPoolType;
+
+    fn try_from(value: u8) -> Result<Self, InvalidPoolType> {
         match value {
-            0 => PoolType::UniswapV3,
-            1 => PoolType::PancakeV3,
-            2 => PoolType::Aerodrome,
-            3 => PoolType::UniswapV4,
-            _ => panic!("Invalid pool type: {}", value),
+            0 => Ok(PoolType::UniswapV3),
+            1 => Ok(PoolType::PancakeV3),
+            2 => Ok(PoolType::Aerodrome),
+            3 => Ok(PoolType::UniswapV4),
+            _ => Err(InvalidPoolType(value)),
         }
     }
 }
 
-pub fn decode_price(data_hex: String, pool_type: PoolType) -> U256 {
-    // Strip '0x' prefix and decode hex to bytes
-    let hex_str = data_hex.trim_start_matches("0x");
-    let data_bytes = hex::decode(hex_str).expect("Invalid hex data");
-
-    // Define expected parameter types
-    let param_types = if pool_type == PoolType::PancakeV3 {
-        vec![
-            ParamType::Int(256),  // amount0 (int256)
-            ParamType::Int(256),  // amount1 (int256)
-            ParamType::Uint(160), // sqrtPriceX96 (uint160)
-            ParamType::Uint(128), // liquidity (uint128)
-            ParamType::Int(24),   // tick (int24)
-            ParamType::Uint(128), // protocolFee1 (uint128)
-            ParamType::Uint(128), // protocolFee2 (uint128)
-        ]
-    } else if pool_type == PoolType::UniswapV4 {
-        vec![
-            ParamType::Int(128),  // amount0 (int128)
-            ParamType::Int(128),  // amount1 (int128)
-            ParamType::Uint(160), // sqrtPriceX96 (uint160)
-            ParamType::Uint(128), // liquidity (uint128)
-            ParamType::Int(24),   // tick (int24)
-            ParamType::Uint(24), // fee (uint24)
-        ]
-    } else {
-        vec![
-            ParamType::Int(256),  // amount0 (int256)
-            ParamType::Int(256),  // amount1 (int256)
-            ParamType::Uint(160), // sqrtPriceX96 (uint160)
-            ParamType::Uint(128), // liquidity (uint128)
-            ParamType::Int(24),   // tick (int24)
-        ]
-    };
-
-    // Decode into tokens
-    let tokens = decode(&param_types, &data_bytes).expect("ABI decoding failed");
-
-    U256::from_token(tokens[2].clone()).unwrap()
-
-    // sqrt_price_x96_to_price(U256::from_token(tokens[2].clone()).unwrap())
-}
-
-
-pub fn sqrt_price_x96_to_price(sqrt_price_x96: U256, zero_for_one: bool, decimal0: u8, decimal1: u8) -> U256 {
-    let q96 = U256::from(2).pow(96.into());
-    let x = U256::from(10).pow(((decimal0 - decimal1 + 8) / 2).into());
-    let price = sqrt_price_x96 * x / q96 * sqrt_price_x96 * x / q96;
-
-    if price == U256::from(0) {
-        return price;
-    }
-
-    if zero_for_one {
-        price
-    } else {
-        U256::from(10).pow(40.into()) / price
-    }
-}
-
-
-pub fn price_to_sqrt_price_x96(price: U256) -> U256 {
-    let q96 = U256::from(2).pow(96.into());
-    let x = U256::from(10).pow(10.into());
-    let sqrt_price_x96 = (price * q96 * q96 / x / x).integer_sqrt();
-    sqrt_price_x96
-}
-
 fn H256_to_U256(h256: H256) -> alloy_primitives::Uint<256, 4> {
     alloy_primitives::Uint::from_be_bytes(h256.0.into())
 }        
@@ -261,252 +453,505 @@ pub fn to_state_override(storage_changes: &Vec<(H160, H256, H256)>) -> StateOver
         balances: HashMap::new(),
     };
 
+    // Later changes of a slot are applied over earlier ones
     for (addr, slot, value) in storage_changes {
-        state_override.storages.insert(addr.0.into(), vec![(H256_to_U256(slot.clone()), H256_to_U256(value.clone()))]);
+        state_override.storages.entry(addr.0.into()).or_default().push((H256_to_U256(slot.clone()), H256_to_U256(value.clone())));
     }
     state_override
 }
 
+/// The state pending transactions left behind: the storage slots they
+/// changed, with their final values in the order they were first changed, and
+/// the balances of the accounts they touched. Nonces aren't kept, overrides
+/// can't set them and simulations read their sender's nonce from the block.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct PendingState {
+    pub storage: Vec<(H160, H256, H256)>,
+    pub balances: HashMap<H160, U256>,
+}
 
-pub async fn simulate_tx_with_revm(
+impl PendingState {
+    pub fn is_empty(&self) -> bool {
+        self.storage.is_empty() && self.balances.is_empty()
+    }
+
+    /// Sets `value` in `slot`, over any earlier change of it.
+    pub fn store(&mut self, address: H160, slot: H256, value: H256) {
+        match self.storage.iter_mut().find(|(a, s, _)| *a == address && *s == slot) {
+            Some(change) => change.2 = value,
+            None => self.storage.push((address, slot, value)),
+        }
+    }
+
+    pub fn to_state_override(&self) -> StateOverride {
+        let mut state_override = to_state_override(&self.storage);
+        state_override.balances = self.balances.iter()
+            .map(|(address, balance)| (address.0.into(), alloy_primitives::U256::from_limbs(balance.0)))
+            .collect();
+        state_override
+    }
+}
+
+/// A pending transaction to replay. A deposit first mints `mint` wei to its
+/// sender, which the call alone doesn't do.
+#[derive(Clone, Debug)]
+pub struct PendingCall {
+    pub tx: SimulationTx,
+    pub mint: U256,
+}
+
+impl From<SimulationTx> for PendingCall {
+    fn from(tx: SimulationTx) -> Self {
+        Self { tx, mint: U256::zero() }
+    }
+}
+
+/// Replays `transactions` in order on top of the backend's block with `state`
+/// applied, and returns `state` with what they changed. Fails if any of them
+/// can't be executed, the state after it would not be the pending one.
+pub async fn replay_transactions(
     shared_backend: SharedBackend,
     provider: ArcAnyNetworkProvider,
+    state: &PendingState,
+    transactions: Vec<PendingCall>,
+) -> Result<PendingState> {
+    let mut shared_evm = create_evm_from_shared_backend(shared_backend, &provider, NoOpInspector).await?;
+
+    // Overrides only apply at the start of a batch, so every minting deposit
+    // starts a batch of its own with the minted ETH credited to its sender
+    let mut batches: Vec<Vec<PendingCall>> = vec![];
+    for call in transactions {
+        match batches.last_mut() {
+            Some(batch) if call.mint.is_zero() => batch.push(call),
+            _ => batches.push(vec![call]),
+        }
+    }
+
+    let mut state = state.clone();
+    let mut replayed = 0;
+    for batch in batches {
+        let first = &batch[0];
+        if !first.mint.is_zero() {
+            let sender = H160::from(first.tx.caller.into_array());
+            let balance = match state.balances.get(&sender) {
+                Some(balance) => *balance,
+                None => {
+                    let account = shared_evm.ctx.db().basic_ref(first.tx.caller)
+                        .map_err(|e| anyhow!("Pending transaction {} can't be replayed: {}", replayed, e))?;
+                    U256(account.map(|account| account.balance).unwrap_or_default().into_limbs())
+                }
+            };
+            state.balances.insert(sender, balance + first.mint);
+        }
+
+        let count = batch.len();
+        let batch = SimulationBatch {
+            transactions: batch.into_iter().map(|call| call.tx).collect(),
+            is_stateful: true,
+            overrides: Some(state.to_state_override()),
+        };
+
+        for (i, trace) in shared_evm.trace_transactions(batch).into_iter().enumerate() {
+            let (_, storage_diff, _) = trace.map_err(|e| anyhow!("Pending transaction {} can't be replayed: {}", replayed + i, e))?;
+            for (address, slot, value) in to_storage_changes(storage_diff) {
+                state.store(address, slot, value);
+            }
+        }
+
+        // The batch leaves its writes in the cache
+        for (address, account) in shared_evm.ctx.db().cache.accounts.iter() {
+            if matches!(account.account_state, AccountState::Touched | AccountState::StorageCleared) {
+                state.balances.insert(H160::from(address.into_array()), U256(account.info.balance.into_limbs()));
+            }
+        }
+
+        replayed += count;
+    }
+
+    Ok(state)
+}
+
+
+/// Selector of `Error(string)`, what `revert("...")` and `require` revert with
+pub const ERROR_SELECTOR: [u8; 4] = [0x08, 0xc3, 0x79, 0xa0];
+/// Selector of `Panic(uint256)`, what failed asserts and overflows revert with
+pub const PANIC_SELECTOR: [u8; 4] = [0x4e, 0x48, 0x7b, 0x71];
+
+/// Why a simulated call reverted, decoded from its revert data.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RevertReason {
+    /// `revert("...")` or a failed `require`
+    Message(String),
+    /// A panic code, e.g. 0x11 for an overflow
+    Panic(U256),
+    /// A custom error, by selector, with its ABI encoded arguments
+    Custom { selector: [u8; 4], data: Bytes },
+    Empty,
+    /// Revert data that is none of the above
+    Unknown(Bytes),
+}
+
+impl RevertReason {
+    pub fn decode(data: &[u8]) -> Self {
+        if data.is_empty() {
+            return Self::Empty;
+        }
+        if data.len() < 4 {
+            return Self::Unknown(data.to_vec().into());
+        }
+
+        let (selector, arguments) = (<[u8; 4]>::try_from(&data[..4]).unwrap(), &data[4..]);
+        let decoded = match selector {
+            ERROR_SELECTOR => String::decode(arguments).ok().map(Self::Message),
+            PANIC_SELECTOR => U256::decode(arguments).ok().map(Self::Panic),
+            _ => Some(Self::Custom { selector, data: arguments.to_vec().into() }),
+        };
+        decoded.unwrap_or_else(|| Self::Unknown(data.to_vec().into()))
+    }
+}
+
+impl std::fmt::Display for RevertReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Message(message) => write!(f, "{}", message),
+            Self::Panic(code) => write!(f, "panic {:#x}", code),
+            Self::Custom { selector, data } => write!(f, "custom error 0x{} {}", hex::encode(selector), data),
+            Self::Empty => write!(f, "no reason"),
+            Self::Unknown(data) => write!(f, "{}", data),
+        }
+    }
+}
+
+/// What a simulated call did.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SimulationOutcome {
+    Success {
+        output: Bytes,
+        gas_used: u64,
+        logs: Vec<LogItem>,
+        /// The storage slots the call changed, with their new values
+        state_diff: Vec<(H160, H256, H256)>,
+    },
+    Revert { reason: RevertReason, gas_used: u64 },
+    /// Out of gas, invalid opcode and the like
+    Halt { reason: String, gas_used: u64 },
+    /// The EVM couldn't run the call, e.g. the RPC node failed to serve state
+    BackendError(String),
+}
+
+impl SimulationOutcome {
+    /// The output of a successful call decoded as `T`.
+    pub fn decode<T: AbiDecode>(&self) -> Option<T> {
+        match self {
+            Self::Success { output, .. } => T::decode(output).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn is_backend_error(&self) -> bool {
+        matches!(self, Self::BackendError(_))
+    }
+}
+
+impl std::fmt::Display for SimulationOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Success { gas_used, .. } => write!(f, "succeeded, {} gas", gas_used),
+            Self::Revert { reason, gas_used } => write!(f, "reverted: {}, {} gas", reason, gas_used),
+            Self::Halt { reason, gas_used } => write!(f, "halted: {}, {} gas", reason, gas_used),
+            Self::BackendError(e) => write!(f, "backend error: {}", e),
+        }
+    }
+}
+
+// The slots a trace changed, with their new values
+fn to_storage_changes(storage_diff: StorageDiff) -> Vec<(H160, H256, H256)> {
+    storage_diff.into_values().flatten().map(|access| (
+        H160::from(access.address.into_array()),
+        H256::from(access.slot.to_be_bytes::<32>()),
+        H256::from(access.new_value.to_be_bytes::<32>()),
+    )).collect()
+}
+
+fn to_log_item(log: &alloy_primitives::Log) -> LogItem {
+    LogItem {
+        address: H160::from(log.address.into_array()),
+        topics: log.topics().iter().map(|topic| H256::from(topic.0)).collect(),
+        data: log.data.data.to_vec().into(),
+    }
+}
+
+/// An EVM on the state of one block, built once and reused for every
+/// simulation of a task. The shared backend keeps the accounts and storage
+/// fetched so far, and every batch starts again from the block state with
+/// the overrides applied, so nothing leaks from one batch to the next.
+pub struct SimulationSession {
+    evm: TraceEvm<CacheDB<SharedBackend>, NoOpInspector>,
     from_addr: H160,
-    txs: Vec<TypedTransaction>,
-    is_stateful: bool,
     state_override: StateOverride,
-) -> Vec<Option<Vec<u8>>> {
-    let mut shared_evm = 
-        create_evm_from_shared_backend(
-            shared_backend.clone(),
-            &provider,
-            NoOpInspector::default(),
-        ).await.unwrap();
-
-    let mut batch = SimulationBatch {
-        transactions: vec![],
-        is_stateful: is_stateful,
-        overrides: Some(state_override),
-    };
+}
+
+impl SimulationSession {
+    pub async fn new(
+        shared_backend: SharedBackend,
+        provider: ArcAnyNetworkProvider,
+        from_addr: H160,
+        state_override: StateOverride,
+    ) -> Result<Self> {
+        let evm = create_evm_from_shared_backend(shared_backend, &provider, NoOpInspector).await?;
+        Ok(Self { evm, from_addr, state_override })
+    }
 
-    for tx in txs {
-        let simulation_tx = SimulationTx {
-            caller: from_addr.0.into(),
+    /// Runs `txs` in order, each seeing the changes of the ones before it
+    /// if `is_stateful`.
+    pub fn run(&mut self, txs: Vec<TypedTransaction>, is_stateful: bool) -> Vec<SimulationOutcome> {
+        let transactions = txs.into_iter().map(|tx| SimulationTx {
+            caller: self.from_addr.0.into(),
             transact_to: TxKind::Call(tx.to_addr().unwrap().0.into()),
             value: alloy_primitives::Uint::from(0),
             data: tx.data().unwrap().0.clone().into(),
-        };
-
-        batch.transactions.push(simulation_tx); 
-    }
-
-    // println!("Batch: {:?}", batch);
+        }).collect();
 
-    let mut results = vec![];
+        let batch = SimulationBatch {
+            transactions,
+            is_stateful,
+            overrides: Some(self.state_override.clone()),
+        };
 
-    for trace in shared_evm.trace_transactions(batch) {
-        match trace {
-            Ok(trace) => {
-                match trace.0 {
-                    ExecutionResult::Success { reason, gas_used, gas_refunded, logs, output } => {
-                        let bytes = output.clone().into_data().as_ref().to_vec();
-                        results.push(Some(bytes));
-                        continue;
-                    }
-                    ExecutionResult::Revert { gas_used, output } => {
-                        println!("Revert: {:?}, {:?}", output, gas_used);
-                    }
-                    ExecutionResult::Halt { reason, gas_used } => {
-                        println!("Halt: {:?}, {:?}", reason, gas_used);
-                    }
-                }
+        self.evm.trace_transactions(batch).into_iter().map(|trace| match trace {
+            Ok((ExecutionResult::Success { output, gas_used, logs, .. }, storage_diff, _)) => SimulationOutcome::Success {
+                output: output.into_data().to_vec().into(),
+                gas_used,
+                logs: logs.iter().map(to_log_item).collect(),
+                state_diff: to_storage_changes(storage_diff),
+            },
+            Ok((ExecutionResult::Revert { gas_used, output }, _, _)) => {
+                SimulationOutcome::Revert { reason: RevertReason::decode(&output), gas_used }
             }
-            Err(e) => {
-                println!("Error: {:?}", e);
+            Ok((ExecutionResult::Halt { reason, gas_used }, _, _)) => {
+                SimulationOutcome::Halt { reason: format!("{:?}", reason), gas_used }
             }
-        }
+            Err(e) => SimulationOutcome::BackendError(e.to_string()),
+        }).collect()
+    }
 
-        results.push(None);
+    /// Runs each of `txs` on the block state alone.
+    pub fn call_batch(&mut self, txs: Vec<TypedTransaction>) -> Vec<SimulationOutcome> {
+        self.run(txs, false)
     }
 
-    results
+    pub fn call(&mut self, tx: TypedTransaction) -> SimulationOutcome {
+        self.call_batch(vec![tx]).pop().unwrap()
+    }
 }
 
+/// Runs `txs` in an EVM of their own, see [SimulationSession::run]. Tasks
+/// simulating more than once should keep a session instead.
+pub async fn simulate_tx_with_revm(
+    shared_backend: SharedBackend,
+    provider: ArcAnyNetworkProvider,
+    from_addr: H160,
+    txs: Vec<TypedTransaction>,
+    is_stateful: bool,
+    state_override: StateOverride,
+) -> Result<Vec<SimulationOutcome>> {
+    let mut session = SimulationSession::new(shared_backend, provider, from_addr, state_override).await?;
+    Ok(session.run(txs, is_stateful))
+}
+
+
+/// The trades of each pool and side.
+pub type TradeMap = HashMap<(usize, bool), Vec<TradeInfo>>;
 
+/// The trades of each launcher, pool and side.
+pub type LauncherTradeMap = HashMap<(H160, usize, bool), Vec<TradeInfo>>;
+
+/// What the trades of one pool and side are searched with, see
+/// [find_best_trade_list].
+#[derive(Clone, Debug, Default)]
+pub struct TradeSearchContext {
+    pub from_addr: H160,
+    pub launcher_addr: H160,
+    pub simulator_addr: H160,
+    pub min_profit: U256,
+    pub min_swap_amount: U256,
+    pub tokens: TokenPair,
+    /// Funds the simulator on top of the pending changes, if any
+    pub state_override: StateOverride,
+    pub pool_index: usize,
+    pub sell_base_token: bool,
+    /// Largest delta searched
+    pub max_delta: U256,
+    pub params: SearchParams,
+}
+
+/// Searches the trades of one pool and side with the simulator, starting
+/// from the delta `params` finds below `max_delta`.
 pub async fn find_best_trade_list(
     shared_backend: SharedBackend,
     provider: ArcAnyNetworkProvider,
     rpc_client: Arc<Provider<Http>>,
-    from_addr: H160,
-    launcher_addr: H160,
-    simulator_addr: H160,
-    min_profit: U256,
-    min_swap_amount: U256,
-    storage_changes: Vec<(H160, H256, H256)>,
-    pool_index: usize,
-    sell_base_token: bool,
-    start_delta: U256,
-) -> Vec<TradeInfo> {
-    let mut max_delta = start_delta;
-    let mut min_delta = U256::from(0);
-    let mut delta= U256::from(0);
-    let state_override = to_state_override(&storage_changes);
+    search: TradeSearchContext,
+) -> TradeSearch {
+    let TradeSearchContext {
+        from_addr, launcher_addr, simulator_addr, min_profit, min_swap_amount, tokens, state_override,
+        pool_index, sell_base_token, max_delta, params,
+    } = search;
+    let min_profit_signed = I256::from_raw(min_profit);
     let simulator = MSLauncher::new(simulator_addr, rpc_client.clone());
+    let mut session = match SimulationSession::new(
+        shared_backend, provider, from_addr, state_override).await {
+        Ok(session) => session,
+        Err(e) => {
+            println!("Failed to create the EVM: {:?}", e);
+            return TradeSearch::default();
+        }
+    };
 
     // First step: find the optimal delta from which swap is profitable
-    while (max_delta - min_delta) * 1000 > WEI_IN_ETHER {
-        let new_delta = (max_delta + min_delta) / 2;
-
+    let search = find_start_delta(&params, min_profit_signed, max_delta, |new_delta| {
         let mut tx = simulator.simulate_trade(
                     launcher_addr,
                     U256::from(pool_index),
                     sell_base_token,
-                    I256::from(new_delta.as_u128()),
+                    I256::from_raw(new_delta),
                     min_swap_amount,
                 ).tx;
 
-        tx.set_gas(U256::from(10000000));
-
-        let result = &simulate_tx_with_revm(
-            shared_backend.clone(),
-            provider.clone(),
-            from_addr,
-            vec![tx],   
-            false,
-            state_override.clone(),
-        ).await[0];
-        if let Some(result) = result {
-            let (new_sqrt_price_x96, new_profit, new_gas_used): (U256, U256, U256) = 
-                        <(U256, U256, U256)>::decode(result.as_slice()).expect("decode failed");
-
-            if new_profit > min_profit {
-                max_delta = new_delta;
-                delta = new_delta;
-
-                if pool_index == 0 && sell_base_token {
-                    println!("Step 1: Price: {:?}, Profit: {:?}, Delta: {:?}",
-                        sqrt_price_x96_to_price(new_sqrt_price_x96, true, 18, 6),
-                        &format_ether(new_profit),
-                        &format_ether(delta));
-                }
-                    
-                continue;
-            }
-        }
+        tx.set_gas(params.gas_limit);
 
-        min_delta = new_delta;
-    }
+        // Reverts are unprofitable trades, backend errors say nothing
+        let outcome = session.call(tx);
+        if outcome.is_backend_error() {
+            return Err(outcome);
+        }
 
-    // Second step: starting from swap amount, find the next profitable delta, swap amount, and sqrt_price_x96
-    let regression_count = 10;
-    let regression_step = (WEI_IN_ETHER * U256::from(3) / U256::from(2)).min(start_delta / 100);
-    let mut txs = vec![];
-    let mut delta_list = vec![];
-    let mut sqrt_price_x96_list = vec![U256::from(0); regression_count];
-    let mut swap_amount_list = vec![U256::from(0); regression_count];
-    let mut profit_list = vec![U256::from(0); regression_count];
-    let mut gas_used_list = vec![U256::from(0); regression_count];
+        let result = outcome.decode::<(U256, I256, U256)>();
+        if let Some((new_sqrt_price_x96, new_profit, _)) = result {
+            if new_profit > min_profit_signed && pool_index == 0 && sell_base_token {
+                println!("Step 1: Price: {:?}, Profit: {:?}, Delta: {:?}",
+                    tokens.price(new_sqrt_price_x96),
+                    &format_ether(new_profit.into_raw()),
+                    &format_ether(new_delta));
+            }
+        }
 
-    for i in 0..regression_count {
-        let tx = simulator.simulate_price_and_amount(
-            launcher_addr,
-            U256::from(pool_index),
-            sell_base_token,
-            I256::from(delta.as_u128())).tx;
+        Ok(result.map(|(_, new_profit, _)| new_profit))
+    });
 
-        delta_list.push(delta);
-        txs.push(tx);
+    let SearchReport { delta: start_delta, evaluations } = match search {
+        Ok(report) => report,
+        Err(outcome) => {
+            println!("Giving up on pool {}: {}", pool_index, outcome);
+            return TradeSearch::default();
+        }
+    };
 
-        delta = delta + regression_step;
+    // Second step: the price and swap amount of every rung of the ladder from there on
+    let mut ladder = PriceLadder::new(&params, max_delta, start_delta);
+    let txs = ladder.rungs.iter().map(|rung| simulator.simulate_price_and_amount(
+        launcher_addr,
+        U256::from(pool_index),
+        sell_base_token,
+        I256::from_raw(rung.delta)).tx).collect();
+
+    let results = session.call_batch(txs);
+    if let Some(outcome) = results.iter().find(|outcome| outcome.is_backend_error()) {
+        println!("Giving up on pool {}: {}", pool_index, outcome);
+        return TradeSearch { trades: vec![], evaluations };
     }
 
-    let results = simulate_tx_with_revm(
-        shared_backend.clone(),
-        provider.clone(),
-        from_addr,
-        txs,   
-        false,
-        state_override.clone(),
-    ).await;
-
     let mut txs2 = vec![];
     let mut tx2_indices = vec![];
 
-    for i in 0..regression_count {
-        let mut sqrt_price_x96 = U256::from(0);
-        let mut swap_amount = U256::from(0);
-
-        match results[i].clone() {
-            Some(result) => {
-                (sqrt_price_x96, swap_amount) = 
-                    <(U256, U256)>::decode(result).expect("decode failed");
+    for (i, (rung, outcome)) in ladder.rungs.iter_mut().zip(results.iter()).enumerate() {
+        match outcome.decode::<(U256, U256)>() {
+            Some((sqrt_price_x96, swap_amount)) => {
+                rung.sqrt_price_x96 = sqrt_price_x96;
+                rung.swap_amount = swap_amount;
 
-                let tx = simulator.simulate_trade(
+                let mut tx = simulator.simulate_trade(
                         launcher_addr,
                         U256::from(pool_index),
                         sell_base_token,
-                        I256::from(delta.as_u128()),
+                        I256::from_raw(rung.delta),
                         swap_amount).tx;
+                tx.set_gas(params.gas_limit);
 
                 txs2.push(tx);
                 tx2_indices.push(i);
             }
             None => {
-                println!("No result for index: {}", i);
+                println!("No result for index {}: {}", i, outcome);
             }
         }
-        
-        sqrt_price_x96_list[i] = sqrt_price_x96;
-        swap_amount_list[i] = swap_amount;
     }
 
-    // Third step
-    let results2 = simulate_tx_with_revm(
-        shared_backend.clone(),
-        provider.clone(),
-        from_addr,
-        txs2,   
-        false,
-        state_override.clone(),
-    ).await;
+    // Third step: trade every priced rung at its own delta
+    let results2 = session.call_batch(txs2);
 
-
-    for (result, i) in results2.iter().zip(tx2_indices.iter()) {
-        if let Some(result) = result {
-            let (sqrt_price_x96, profit, gas_used): (U256, U256, U256) = 
-                    <(U256, U256, U256)>::decode(result).expect("decode failed");
-
-            profit_list[*i] = profit;
-            gas_used_list[*i] = gas_used;
+    for (outcome, i) in results2.iter().zip(tx2_indices.iter()) {
+        match outcome.decode::<(U256, I256, U256)>() {
+            Some((_sqrt_price_x96, profit, gas_used)) => {
+                ladder.rungs[*i].profit = Some(profit);
+                ladder.rungs[*i].gas_used = gas_used;
+            }
+            None => println!("No profit for index {}: {}", i, outcome),
         }
     }
 
-    let mut trade_info_list = vec![];
-
     // Last step
-    for i in 0..regression_count {
-        // let gas_price = profit_list[i] / gas_used_list[i];
-
-        if profit_list[i] > min_profit && swap_amount_list[i] > min_swap_amount {
-            let trade_info = TradeInfo {
-                launcher_addr: launcher_addr,
-                pool_index: pool_index,
-                sell_base_token: sell_base_token,
-                start_delta: delta_list[i],
-                delta: delta_list[i],
-                sqrt_price_x96: sqrt_price_x96_list[i],
-                trade_price: U256::from(0),
-                deviation_bps: U256::from(0),
-                swap_amount: swap_amount_list[i],
-                profit: profit_list[i],
-                gas_used: gas_used_list[i],
-            };
+    let trade_info_list = ladder.trades(launcher_addr, pool_index, sell_base_token, min_profit_signed, min_swap_amount);
+    TradeSearch { trades: trade_info_list, evaluations }
+}
 
-            trade_info_list.push(trade_info);
-        }
+/// [find_best_trade_list] with the trades quoted by `quoter` instead of the
+/// simulator. The quotes don't include gas, `gas_used` is left at zero.
+pub fn find_best_trade_list_native(quoter: &LauncherQuoter, search: &TradeSearchContext) -> Result<TradeSearch, QuoteError> {
+    let TradeSearchContext { launcher_addr, min_profit, min_swap_amount, pool_index, sell_base_token, max_delta, ref params, .. } = *search;
+    let min_profit = I256::from_raw(min_profit);
+
+    // First step: find the optimal delta from which swap is profitable
+    let SearchReport { delta: start_delta, evaluations } = find_start_delta(params, min_profit, max_delta, |delta| {
+        quoter.quote(pool_index, sell_base_token, delta).map(|quote| Some(quote.profit))
+    })?;
+
+    // Second step: the trades from there on
+    let mut ladder = PriceLadder::new(params, max_delta, start_delta);
+    for rung in ladder.rungs.iter_mut() {
+        let quote = quoter.quote(pool_index, sell_base_token, rung.delta)?;
+        rung.sqrt_price_x96 = quote.sqrt_price_x96;
+        rung.swap_amount = quote.swap_amount;
+        rung.profit = Some(quote.profit);
     }
-    
-    trade_info_list
+
+    let trade_info_list = ladder.trades(launcher_addr, pool_index, sell_base_token, min_profit, min_swap_amount);
+    Ok(TradeSearch { trades: trade_info_list, evaluations })
+}
+
+/// Runs `trade_info_list` through `simulateTrade` of the simulator in
+/// `session`, the reference the native quotes are checked against.
+/// Successful calls return the sqrt price, profit and gas used of the trade.
+pub fn verify_trade_list(
+    session: &mut SimulationSession,
+    rpc_client: Arc<Provider<Http>>,
+    simulator_addr: H160,
+    trade_info_list: &[TradeInfo],
+    gas_limit: U256,
+) -> Vec<SimulationOutcome> {
+    let simulator = MSLauncher::new(simulator_addr, rpc_client);
+    let txs = trade_info_list.iter().map(|t| {
+        let mut tx = simulator.simulate_trade(
+            t.launcher_addr,
+            U256::from(t.pool_index),
+            t.sell_base_token,
+            I256::from_raw(t.delta),
+            t.swap_amount).tx;
+        tx.set_gas(gas_limit);
+        tx
+    }).collect();
+
+    session.call_batch(txs)
 }
 
 
@@ -567,7 +1012,7 @@ pub async fn broadcast_trade(subscribers: Arc<RwLock<Vec<SubscriptionSink>>>, tr
     }
 }
 
-pub async fn start_trade_collector(trade_server_url: String) -> Arc<RwLock<HashMap<(usize, bool), Vec<TradeInfo>>>> {
+pub async fn start_trade_collector(trade_server_url: String) -> Arc<RwLock<TradeMap>> {
     let trade_info_map = Arc::new(RwLock::new(HashMap::new()));
     let trade_info_map_clone = trade_info_map.clone();
 
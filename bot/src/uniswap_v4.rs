@@ -0,0 +1,124 @@
+use ethers::abi::{decode, encode, ParamType, Token};
+use ethers::types::{H160, H256, I256};
+use ethers::utils::keccak256;
+use serde::{Deserialize, Serialize};
+
+use crate::errors::SwapEventError;
+use crate::swap_events::event_topic;
+use crate::types::LogItem;
+
+/// Emitted by the PoolManager when a pool is created, with its key
+pub const INITIALIZE: &str = "Initialize(bytes32,address,address,uint24,int24,address,uint160,int24)";
+
+/// Set in the fee of pools whose hook sets the fee of every swap
+pub const DYNAMIC_FEE_FLAG: u32 = 0x800000;
+
+// Permissions a hook has over swaps, from the low bits of its address
+pub const BEFORE_SWAP_FLAG: u16 = 1 << 7;
+pub const AFTER_SWAP_FLAG: u16 = 1 << 6;
+pub const BEFORE_SWAP_RETURNS_DELTA_FLAG: u16 = 1 << 3;
+pub const AFTER_SWAP_RETURNS_DELTA_FLAG: u16 = 1 << 2;
+
+const ALL_HOOK_FLAGS: u16 = (1 << 14) - 1;
+
+/// Identifies a pool of the Uniswap V4 PoolManager, its id is the hash of it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PoolKey {
+    /// The zero address for ETH
+    pub currency0: H160,
+    pub currency1: H160,
+    /// In hundredths of a bip, or [DYNAMIC_FEE_FLAG]
+    pub fee: u32,
+    pub tick_spacing: i32,
+    pub hooks: H160,
+}
+
+impl PoolKey {
+    /// The key of the pool of `token_a` and `token_b`, in either order.
+    pub fn new(token_a: H160, token_b: H160, fee: u32, tick_spacing: i32, hooks: H160) -> Self {
+        let (currency0, currency1) = if token_a < token_b { (token_a, token_b) } else { (token_b, token_a) };
+        Self { currency0, currency1, fee, tick_spacing, hooks }
+    }
+
+    /// The pool id, `keccak256(abi.encode(key))`.
+    pub fn id(&self) -> H256 {
+        H256::from(keccak256(encode(&[
+            Token::Address(self.currency0),
+            Token::Address(self.currency1),
+            Token::Uint(self.fee.into()),
+            Token::Int(I256::from(self.tick_spacing).into_raw()),
+            Token::Address(self.hooks),
+        ])))
+    }
+
+    pub fn is_dynamic_fee(&self) -> bool {
+        self.fee == DYNAMIC_FEE_FLAG
+    }
+
+    /// The permissions of the hook, see the `*_FLAG` constants.
+    pub fn hook_flags(&self) -> u16 {
+        u16::from_be_bytes([self.hooks[18], self.hooks[19]]) & ALL_HOOK_FLAGS
+    }
+
+    /// Whether the hook runs on swaps, so a swap may not price like the
+    /// pool's liquidity says.
+    pub fn hooks_swaps(&self) -> bool {
+        self.hook_flags() & (BEFORE_SWAP_FLAG | AFTER_SWAP_FLAG) != 0
+    }
+
+    /// Decodes the key of an `Initialize` log, checking it hashes to the
+    /// pool id of the log.
+    pub fn from_initialize(log: &LogItem) -> Result<Self, SwapEventError> {
+        if log.topics.first() != Some(&event_topic(INITIALIZE)) {
+            return Err(SwapEventError::InvalidValue("not an Initialize event".to_string()));
+        }
+        if log.topics.len() < 4 {
+            return Err(SwapEventError::MissingTopic(log.topics.len()));
+        }
+
+        let tokens = decode(&[
+            ParamType::Uint(24),  // fee
+            ParamType::Int(24),   // tickSpacing
+            ParamType::Address,   // hooks
+            ParamType::Uint(160), // sqrtPriceX96
+            ParamType::Int(24),   // tick
+        ], &log.data)?;
+
+        // int24 words are sign extended, their low 32 bits are the i32
+        let tick_spacing = tokens[1].clone().into_int().unwrap();
+        let key = PoolKey {
+            currency0: H160::from(log.topics[2]),
+            currency1: H160::from(log.topics[3]),
+            fee: tokens[0].clone().into_uint().unwrap().low_u32(),
+            tick_spacing: tick_spacing.low_u32() as i32,
+            hooks: tokens[2].clone().into_address().unwrap(),
+        };
+
+        if key.id() != log.topics[1] {
+            return Err(SwapEventError::InvalidValue(format!("key of pool {:?} hashes to {:?}", log.topics[1], key.id())));
+        }
+
+        Ok(key)
+    }
+}
+
+/// A pool of the Uniswap V4 PoolManager. Its swaps are emitted by `manager`
+/// with `id` as first indexed topic.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct V4Pool {
+    pub manager: H160,
+    pub id: H256,
+    /// `None` until the key hashing to `id` is known
+    pub key: Option<PoolKey>,
+}
+
+impl V4Pool {
+    pub fn new(manager: H160, id: H256) -> Self {
+        Self { manager, id, key: None }
+    }
+
+    /// Whether `log` was emitted by this pool.
+    pub fn emitted(&self, log: &LogItem) -> bool {
+        log.address == self.manager && log.topics.get(1) == Some(&self.id)
+    }
+}
@@ -0,0 +1,239 @@
+mod support;
+
+use std::time::Duration;
+
+use alloy_primitives::{Address, TxKind};
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Bytes, Eip1559TransactionRequest, TransactionRequest, H160, U256};
+use ms_bot::collectors::arbitrum_feed::{parse_l2_message, ArbitrumCodec, ArbitrumTransaction, ARB1_CHAIN_ID, ARB1_GENESIS_BLOCK};
+use ms_bot::collectors::arbitrum_feed_collector::{start_arbitrum_feed_collector, ArbitrumFeedCollector};
+use ms_bot::collectors::feed_client::{ClientOptions, FeedCodec};
+use ms_bot::collectors::feed_clients::FeedEndpoint;
+use ms_bot::collectors::reconnect_policy::ReconnectPolicy;
+use ms_bot::errors::RelayError;
+use ms_bot::types::Collector;
+use serde_json::json;
+use support::mock_flashblocks::{MockFlashblocksServer, Step};
+use tokio::time::timeout;
+
+const SEQUENCER: &str = "0xa4b000000000000000000073657175656e636572";
+const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+fn wallet() -> LocalWallet {
+    KEY.parse::<LocalWallet>().unwrap().with_chain_id(ARB1_CHAIN_ID)
+}
+
+// A signed EIP-1559 transfer, as the sequencer receives it
+fn signed_tx(nonce: u64) -> Bytes {
+    let tx: TypedTransaction = Eip1559TransactionRequest::new()
+        .to(H160::repeat_byte(0x11))
+        .value(1_000)
+        .nonce(nonce)
+        .gas(21_000)
+        .max_fee_per_gas(100_000_000)
+        .max_priority_fee_per_gas(0)
+        .chain_id(ARB1_CHAIN_ID)
+        .into();
+    let signature = wallet().sign_transaction_sync(&tx).unwrap();
+    tx.rlp_signed(&signature)
+}
+
+fn legacy_tx() -> Bytes {
+    let tx: TypedTransaction = TransactionRequest::new()
+        .to(H160::repeat_byte(0x22))
+        .nonce(7)
+        .gas(50_000)
+        .gas_price(100_000_000)
+        .chain_id(ARB1_CHAIN_ID)
+        .into();
+    let signature = wallet().sign_transaction_sync(&tx).unwrap();
+    tx.rlp_signed(&signature)
+}
+
+fn signed_message(tx: &[u8]) -> Vec<u8> {
+    [&[4u8][..], tx].concat()
+}
+
+fn batch(segments: &[Vec<u8>]) -> Vec<u8> {
+    let mut message = vec![3u8];
+    for segment in segments {
+        message.extend_from_slice(&(segment.len() as u64).to_be_bytes());
+        message.extend_from_slice(segment);
+    }
+    message
+}
+
+fn word(value: u64) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    U256::from(value).to_big_endian(&mut word);
+    word
+}
+
+fn broadcast(messages: &[(u64, u8, &[u8])]) -> String {
+    let messages: Vec<_> = messages.iter().map(|(sequence_number, kind, l2_msg)| json!({
+        "sequenceNumber": sequence_number,
+        "message": {
+            "message": {
+                "header": {
+                    "kind": kind,
+                    "sender": SEQUENCER,
+                    "blockNumber": 20_000_000,
+                    "timestamp": 1_720_000_000,
+                    "requestId": null,
+                    "baseFeeL1": null,
+                },
+                "l2Msg": STANDARD.encode(l2_msg),
+            },
+            "delayedMessagesRead": 1_700_000,
+        },
+        "signature": null,
+    })).collect();
+
+    json!({ "version": 1, "messages": messages }).to_string()
+}
+
+#[test]
+fn decodes_batches_of_signed_transactions() {
+    let message = batch(&[signed_message(&signed_tx(0)), signed_message(&legacy_tx()), signed_message(&signed_tx(1))]);
+    let frame = broadcast(&[(100, 3, &message)]);
+
+    let messages = ArbitrumCodec::arb1().decode(frame.as_bytes()).unwrap();
+    assert_eq!(messages.len(), 1);
+    assert_eq!(messages[0].sequence_number, 100);
+    assert_eq!(messages[0].block_number, ARB1_GENESIS_BLOCK + 100);
+    assert_eq!(messages[0].header.sender, SEQUENCER.parse().unwrap());
+    assert_eq!(messages[0].header.timestamp, 1_720_000_000);
+
+    let txs: Vec<_> = messages[0].transactions.iter().map(|tx| match tx {
+        ArbitrumTransaction::Signed(tx) => tx,
+        other => panic!("expected a signed transaction, got {:?}", other),
+    }).collect();
+    assert_eq!(txs.len(), 3);
+    assert_eq!(txs[0].transaction_type, Some(2.into()));
+    assert_eq!(txs[0].nonce, 0.into());
+    assert_eq!(txs[0].value, 1_000.into());
+    assert_eq!(txs[0].to, Some(H160::repeat_byte(0x11)));
+    assert_eq!(txs[1].transaction_type, None);
+    assert_eq!(txs[1].nonce, 7.into());
+    assert_eq!(txs[2].nonce, 1.into());
+    for tx in txs {
+        assert_eq!(tx.from, wallet().address());
+    }
+}
+
+#[test]
+fn decodes_unsigned_transactions_and_heartbeats() {
+    let sender = H160::repeat_byte(0x33);
+    let mut unsigned = vec![0u8];
+    unsigned.extend_from_slice(&word(30_000));
+    unsigned.extend_from_slice(&word(200_000_000));
+    unsigned.extend_from_slice(&word(5));
+    unsigned.extend_from_slice(&[0u8; 32]);
+    unsigned.extend_from_slice(&word(42));
+    unsigned.extend_from_slice(&[0xde, 0xad]);
+
+    let txs = parse_l2_message(&unsigned, sender).unwrap();
+    match &txs[..] {
+        [ArbitrumTransaction::Unsigned(tx)] => {
+            assert_eq!(tx.from, sender);
+            assert_eq!(tx.to, None);
+            assert_eq!(tx.nonce, Some(5.into()));
+            assert_eq!(tx.gas_limit, 30_000.into());
+            assert_eq!(tx.max_fee_per_gas, 200_000_000.into());
+            assert_eq!(tx.value, 42.into());
+            assert_eq!(tx.data, Bytes::from(vec![0xde, 0xad]));
+        }
+        other => panic!("expected one unsigned transaction, got {:?}", other),
+    }
+
+    assert!(parse_l2_message(&[6], sender).unwrap().is_empty());
+    assert!(matches!(parse_l2_message(&[], sender), Err(RelayError::InvalidL2Message(_))));
+    assert!(matches!(parse_l2_message(&[2, 0], sender), Err(RelayError::InvalidL2Message(_))));
+}
+
+#[test]
+fn transactions_replay_as_simulation_calls() {
+    let sender = H160::repeat_byte(0x33);
+    let mut contract_tx = vec![1u8];
+    contract_tx.extend_from_slice(&word(30_000));
+    contract_tx.extend_from_slice(&word(200_000_000));
+    contract_tx.extend_from_slice(&[[0u8; 12].as_slice(), &[0x44; 20]].concat());
+    contract_tx.extend_from_slice(&word(42));
+    contract_tx.extend_from_slice(&[0xde, 0xad]);
+    let message = batch(&[signed_message(&signed_tx(0)), contract_tx]);
+
+    let txs = parse_l2_message(&message, sender).unwrap();
+    let calls: Vec<_> = txs.iter().map(|tx| tx.simulation_tx().unwrap()).collect();
+    assert_eq!(calls[0].caller, Address::from(wallet().address().0));
+    assert_eq!(calls[0].transact_to, TxKind::Call(Address::repeat_byte(0x11)));
+    assert_eq!(calls[0].value, alloy_primitives::U256::from(1_000));
+    assert_eq!(calls[1].caller, Address::from(sender.0));
+    assert_eq!(calls[1].transact_to, TxKind::Call(Address::repeat_byte(0x44)));
+    assert_eq!(calls[1].value, alloy_primitives::U256::from(42));
+    assert_eq!(calls[1].data.to_vec(), vec![0xde, 0xad]);
+
+    assert!(ArbitrumTransaction::Unsupported(vec![0x03].into()).simulation_tx().is_none());
+}
+
+#[test]
+fn keeps_messages_that_are_not_l2_messages() {
+    // A delayed inbox deposit carries no L2 message to decode
+    let frame = broadcast(&[(7, 12, &[1, 2, 3]), (8, 3, &[6])]);
+
+    let messages = ArbitrumCodec::new(0).decode(frame.as_bytes()).unwrap();
+    assert_eq!(messages.iter().map(|m| m.block_number).collect::<Vec<_>>(), vec![7, 8]);
+    assert_eq!(messages[0].header.kind, 12);
+    assert_eq!(messages[0].l2_msg, Bytes::from(vec![1, 2, 3]));
+    assert!(messages.iter().all(|m| m.transactions.is_empty()));
+
+    // Frames that only confirm sequence numbers carry no messages
+    let confirmation = r#"{"version":1,"confirmedSequenceNumberMessage":{"sequenceNumber":8}}"#;
+    assert!(ArbitrumCodec::arb1().decode(confirmation.as_bytes()).unwrap().is_empty());
+}
+
+#[test]
+fn rejects_malformed_frames() {
+    let codec = ArbitrumCodec::arb1();
+    assert!(matches!(codec.decode(b"not json"), Err(RelayError::InvalidPayload(_))));
+
+    let truncated = signed_tx(0);
+    let frame = broadcast(&[(1, 3, &signed_message(&truncated[..truncated.len() - 4]))]);
+    assert!(matches!(codec.decode(frame.as_bytes()), Err(RelayError::InvalidL2Message(_))));
+}
+
+#[tokio::test]
+async fn arbitrum_feed_is_carried_by_the_feed_pool() {
+    let first = broadcast(&[(100, 3, &signed_message(&signed_tx(0)))]);
+    let second = broadcast(&[(101, 3, &batch(&[signed_message(&signed_tx(1))])), (102, 3, &[6])]);
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Text(first.clone()),
+        Step::Text(first),
+        Step::Garbage(b"{}}".to_vec()),
+        Step::Text(second),
+    ]])
+    .await;
+
+    let mut receiver = start_arbitrum_feed_collector(
+        vec![FeedEndpoint::new(&server.url, 0)], ARB1_CHAIN_ID, ArbitrumCodec::arb1(),
+        ReconnectPolicy::default(), ClientOptions::default()).await.unwrap();
+
+    let mut sequence_numbers = Vec::new();
+    for _ in 0..3 {
+        let message = timeout(Duration::from_secs(5), receiver.recv()).await.unwrap().unwrap();
+        sequence_numbers.push(message.sequence_number);
+    }
+
+    // The repeated message is merged away and the bad frame skipped
+    assert_eq!(sequence_numbers, vec![100, 101, 102]);
+}
+
+#[tokio::test]
+async fn arbitrum_collector_reports_a_feed_it_cant_start() {
+    let started = start_arbitrum_feed_collector(
+        vec![], ARB1_CHAIN_ID, ArbitrumCodec::arb1(), ReconnectPolicy::default(), ClientOptions::default()).await;
+    assert!(matches!(started, Err(RelayError::Msg(_))));
+    assert!(ArbitrumFeedCollector::new(vec![], ARB1_CHAIN_ID).get_event_stream().await.is_err());
+}
@@ -0,0 +1,199 @@
+use std::collections::HashMap;
+
+use async_trait::async_trait;
+use ethers::types::{H160, I256, U256};
+use ethers::utils::WEI_IN_ETHER;
+use ms_bot::cl_quoter::*;
+use ms_bot::errors::QuoteError;
+use ms_bot::price_math::*;
+use ms_bot::trade_search::SearchParams;
+use ms_bot::types::{find_best_trade_list_native, TradeSearchContext};
+use proptest::prelude::*;
+
+const ONE: u128 = 1_000_000_000_000_000_000;
+
+fn u256(value: &str) -> U256 {
+    U256::from_dec_str(value).unwrap()
+}
+
+fn state(tick: i32, liquidity: u128, fee: u32, tick_spacing: i32) -> ClPoolState {
+    ClPoolState {
+        sqrt_price_x96: get_sqrt_ratio_at_tick(tick).unwrap(),
+        tick,
+        liquidity,
+        fee,
+        protocol_fee: 0,
+        tick_spacing,
+    }
+}
+
+// Two positions around tick 0 of 1 ETH of liquidity each, in [-600, 600]
+// and [-1200, 1200]
+fn two_positions() -> ClPoolSnapshot {
+    ClPoolSnapshot::new(state(0, 2 * ONE, 3000, 60), (-1, 0))
+        .with_tick(-1200, ONE as i128)
+        .with_tick(-600, ONE as i128)
+        .with_tick(600, -(ONE as i128))
+        .with_tick(1200, -(ONE as i128))
+}
+
+// A pool with the same liquidity at every price
+fn flat_pool(tick: i32, liquidity: u128) -> ClPoolSnapshot {
+    ClPoolSnapshot::new(state(tick, liquidity, 500, 10), (-50, 50))
+}
+
+struct MockPool {
+    state: ClPoolState,
+    bitmap: HashMap<i16, U256>,
+    ticks: HashMap<i32, i128>,
+}
+
+#[async_trait]
+impl ClPoolSource for MockPool {
+    async fn state(&self) -> Result<ClPoolState, QuoteError> {
+        Ok(self.state)
+    }
+
+    async fn tick_bitmap(&self, word: i16) -> Result<U256, QuoteError> {
+        Ok(self.bitmap.get(&word).copied().unwrap_or_default())
+    }
+
+    async fn liquidity_net(&self, tick: i32) -> Result<i128, QuoteError> {
+        self.ticks.get(&tick).copied().ok_or_else(|| QuoteError::Rpc(format!("tick {} is not initialized", tick)))
+    }
+}
+
+#[test]
+fn computes_swap_steps_like_uniswap() {
+    // The SwapMath vectors of Uniswap V3, from price 1 towards 1.01 and 10
+    let price = Q96;
+    let target = u256("79623317895830914510639640423");
+    let liquidity = 2 * ONE;
+    let amount = I256::from(ONE);
+
+    let capped = SwapStep {
+        sqrt_price_next: target,
+        amount_in: U256::from(9975124224178055u64),
+        amount_out: U256::from(9925619580021728u64),
+        fee_amount: U256::from(5988667735148u64),
+    };
+    assert_eq!(compute_swap_step(price, target, liquidity, amount, 600), Ok(capped));
+    assert_eq!(compute_swap_step(price, target, liquidity, -amount, 600), Ok(capped));
+
+    let spent = compute_swap_step(price, u256("250541448375047931186413801569"), liquidity, amount, 600).unwrap();
+    assert_eq!(spent.amount_in, U256::from(999400000000000000u64));
+    assert_eq!(spent.amount_out, U256::from(666399946655997866u64));
+    assert_eq!(spent.fee_amount, U256::from(600000000000000u64));
+    assert_eq!(spent.sqrt_price_next, u256("118818475322642227089037862318"));
+}
+
+#[test]
+fn crosses_initialized_ticks() {
+    let pool = two_positions();
+    let limit = get_sqrt_ratio_at_tick(-900).unwrap();
+    let quote = pool.swap(true, I256::from(100 * ONE), limit).unwrap();
+
+    // Stops at the limit, with only the wider position left
+    assert_eq!((quote.sqrt_price_x96, quote.tick, quote.liquidity), (limit, -900, ONE));
+
+    // Two steps, 2 ETH of liquidity down to tick -600 and 1 ETH below
+    let at_600 = get_sqrt_ratio_at_tick(-600).unwrap();
+    let steps = [(Q96, at_600, 2 * ONE), (at_600, limit, ONE)];
+    let with_fee = |amount: U256| amount + mul_div_rounding_up(amount, U256::from(3000), U256::from(997000)).unwrap();
+    let amount_in = steps.iter().map(|(from, to, liquidity)| with_fee(get_amount0_delta(*to, *from, *liquidity, true).unwrap()));
+    let amount_out = steps.iter().map(|(from, to, liquidity)| get_amount1_delta(*to, *from, *liquidity, false).unwrap());
+    assert_eq!(quote.amount_in(true), amount_in.fold(U256::zero(), |a, b| a + b));
+    assert_eq!(quote.amount_out(true), amount_out.fold(U256::zero(), |a, b| a + b));
+    assert!(quote.amount0 > I256::zero() && quote.amount1 < I256::zero());
+
+    // Within a range, the price moves like the input says
+    let quote = pool.swap_exact_in(false, U256::from(ONE / 1000)).unwrap();
+    let amount_less_fee = U256::from(ONE / 1000 * 997 / 1000);
+    assert_eq!(quote.sqrt_price_x96, get_next_sqrt_price_from_input(Q96, 2 * ONE, amount_less_fee, false).unwrap());
+    assert_eq!(quote.amount_in(false), U256::from(ONE / 1000));
+}
+
+#[test]
+fn refuses_to_quote_past_the_loaded_ticks() {
+    let pool = two_positions();
+    assert_eq!(pool.swap_exact_in(true, U256::from(1000 * ONE)), Err(QuoteError::TicksNotLoaded(-2)));
+    assert_eq!(pool.swap_exact_in(false, U256::from(1000 * ONE)), Err(QuoteError::TicksNotLoaded(1)));
+
+    let limit = get_sqrt_ratio_at_tick(60).unwrap();
+    assert_eq!(pool.swap(true, I256::from(ONE), limit), Err(QuoteError::InvalidPriceLimit(limit)));
+}
+
+#[test]
+fn charges_the_v4_protocol_fee_per_direction() {
+    let state = ClPoolState { fee: 3000, protocol_fee: 1000 | (500 << 12), ..ClPoolState::default() };
+    assert_eq!(state.swap_fee(true), 1000 + 3000 - 3);
+    assert_eq!(state.swap_fee(false), 500 + 3000 - 1);
+    assert_eq!(ClPoolState { fee: 500, ..ClPoolState::default() }.swap_fee(true), 500);
+}
+
+#[tokio::test]
+async fn loads_snapshots_from_the_tick_bitmap() {
+    // Ticks -1200 and -600 are in word -1, 600 and 1200 in word 0
+    let pool = MockPool {
+        state: state(0, 2 * ONE, 3000, 60),
+        bitmap: HashMap::from([(-1, (U256::one() << 236) | (U256::one() << 246)), (0, (U256::one() << 10) | (U256::one() << 20))]),
+        ticks: two_positions().ticks.into_iter().collect(),
+    };
+
+    let snapshot = ClPoolSnapshot::load(&pool, 1).await.unwrap();
+    assert_eq!(snapshot.words, (-1, 1));
+    assert_eq!(snapshot.ticks, two_positions().ticks);
+    assert_eq!(snapshot.state, pool.state);
+
+    let quote = two_positions().swap_exact_in(true, U256::from(ONE / 100)).unwrap();
+    assert_eq!(snapshot.swap_exact_in(true, U256::from(ONE / 100)), Ok(quote));
+}
+
+#[test]
+fn quotes_launcher_trades_natively() {
+    // Base is token0 and sells 2% higher in the pool than in the ms pool
+    let quoter = LauncherQuoter {
+        pools: vec![Some(flat_pool(200, 10_000 * ONE)), None],
+        base_is_token0: vec![true, true],
+        ms_pool: flat_pool(0, 10_000 * ONE),
+        ms_base_is_token0: true,
+    };
+
+    let sell = quoter.quote(0, true, U256::from(ONE)).unwrap();
+    assert!(sell.profit > I256::zero());
+    assert!(sell.sqrt_price_x96 < get_sqrt_ratio_at_tick(200).unwrap());
+    assert!(quoter.quote(0, false, U256::from(ONE)).unwrap().profit < I256::zero());
+    assert!(matches!(quoter.quote(1, true, U256::from(ONE)), Err(QuoteError::UnsupportedPool { index: 1, .. })));
+
+    let launcher = H160::repeat_byte(0x01);
+    let start_delta = WEI_IN_ETHER * 100;
+    let params = SearchParams::default();
+    let search = TradeSearchContext { launcher_addr: launcher, sell_base_token: true, max_delta: start_delta, params, ..Default::default() };
+    let trades = find_best_trade_list_native(&quoter, &search).unwrap().trades;
+    assert_eq!(trades.len(), 10);
+    for pair in trades.windows(2) {
+        // Selling more base lowers the price further
+        assert!(pair[1].delta > pair[0].delta && pair[1].sqrt_price_x96 < pair[0].sqrt_price_x96);
+    }
+    assert!(trades.iter().all(|t| t.start_delta == trades[0].delta));
+    let expected = quoter.quote(0, true, trades[3].delta).unwrap();
+    assert_eq!((trades[3].swap_amount, trades[3].profit), (expected.swap_amount, expected.profit.into_raw()));
+
+    let search = TradeSearchContext { sell_base_token: false, ..search };
+    assert!(find_best_trade_list_native(&quoter, &search).unwrap().trades.is_empty());
+}
+
+proptest! {
+    #[test]
+    fn exact_output_never_costs_more_than_exact_input(amount in 1u128..100 * ONE, zero_for_one: bool) {
+        let pool = two_positions();
+        let limit = get_sqrt_ratio_at_tick(if zero_for_one { -1500 } else { 1500 }).unwrap();
+        let exact_in = pool.swap(zero_for_one, I256::from(amount), limit).unwrap();
+        let amount_out = exact_in.amount_out(zero_for_one);
+        prop_assume!(!amount_out.is_zero());
+
+        let exact_out = pool.swap(zero_for_one, -I256::from_raw(amount_out), limit).unwrap();
+        prop_assert_eq!(exact_out.amount_out(zero_for_one), amount_out);
+        prop_assert!(exact_out.amount_in(zero_for_one) <= exact_in.amount_in(zero_for_one));
+    }
+}
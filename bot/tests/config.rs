@@ -0,0 +1,239 @@
+use ms_bot::collectors::arbitrum_feed::ARB1_GENESIS_BLOCK;
+use ms_bot::config::{apply_env_overrides, Config, Platform};
+use ms_bot::errors::ConfigError;
+use toml::Table;
+
+const COMMON: &str = r#"
+[common]
+chain_id = 42161
+rpc_url = "http://localhost:8545"
+ws_url = "ws://localhost:8546"
+from_addr = "0x8856c1E1b30C0CBa94032F8C97c5aFC5cf3B104D"
+keystore = "./keystore"
+gas_limit = 7000000
+"#;
+
+const BASE: &str = r#"
+[base]
+to_addr = "0x8dfb6fcB65830EbAC54D92f1aD170aF01B0AC10f"
+simulator_addr = "0xdcA45FAC69E4D82FeA917E3338B0dC1545C6fA46"
+flashblocks_url = "wss://mainnet.flashblocks.base.org/ws"
+trade_server_url = "127.0.0.1:8829"
+sequencer_url = "https://mainnet-sequencer.base.org"
+step_count = 50
+regression_count = 7
+min_profit = 1
+min_swap_amount = 15000
+"#;
+
+const ARBITRUM: &str = r#"
+[arbitrum]
+to_addr = "0x8dfb6fcB65830EbAC54D92f1aD170aF01B0AC10f"
+simulator_addr = "0xdcA45FAC69E4D82FeA917E3338B0dC1545C6fA46"
+feed_url = "wss://arb1.arbitrum.io/feed"
+trade_server_url = "127.0.0.1:8829"
+step_count = 50
+regression_count = 7
+min_profit = 1
+min_swap_amount = 15000
+"#;
+
+const OP: &str = r#"
+[op]
+sequencer_url = "https://mainnet-sequencer.optimism.io"
+
+[[op.launchers]]
+name = "WETH_USDC"
+launcher_addr = "0xBa9e959f472eE197Ac1518a99E7435dF0ECefd30"
+simulator_addr = "0xdea14e1cE824878F3f71a72Ee0F415B9bddB0F17"
+base_token_addr = "0x4200000000000000000000000000000000000006"
+min_profit = 1
+min_swap_amount = 15000
+
+[[op.launchers]]
+name = "WETH_OP"
+launcher_addr = "0xA011FE071308218c6A064f1fDeaC3Db82Ee5f540"
+simulator_addr = "0xEB30899D937e0825e00744eAec82fC966a189205"
+base_token_addr = "0x4200000000000000000000000000000000000006"
+min_profit = 1
+min_swap_amount = 15000
+"#;
+
+fn table(text: &str) -> Table {
+    toml::from_str(text).unwrap()
+}
+
+fn invalid(root: Table, platform: Platform) -> Vec<String> {
+    match Config::from_table(root, platform) {
+        Err(ConfigError::Invalid(issues)) => issues,
+        other => panic!("expected an invalid config, got {:?}", other),
+    }
+}
+
+#[test]
+fn reads_the_arbitrum_section() {
+    let config = Config::from_table(table(&format!("{COMMON}{ARBITRUM}")), Platform::Arbitrum).unwrap();
+    assert!(config.base.is_none());
+    let arbitrum = config.arbitrum.unwrap();
+    assert_eq!(arbitrum.feed_url, "wss://arb1.arbitrum.io/feed");
+    assert_eq!(arbitrum.genesis_block, ARB1_GENESIS_BLOCK);
+    assert!(!arbitrum.simulate_pending);
+
+    // It only simulates, so it has no execution settings
+    let arbitrum = format!("{ARBITRUM}genesis_block = 0\nexecution_mode = true\n");
+    let issues = invalid(table(&format!("{COMMON}{arbitrum}")), Platform::Arbitrum);
+    assert_eq!(issues, vec!["unknown key `arbitrum.execution_mode`"]);
+
+    // The feed is no longer a part of the base section
+    let nested = "[base.arbitrum]\nfeed_url = \"wss://arb1.arbitrum.io/feed\"\n";
+    let issues = invalid(table(&format!("{COMMON}{BASE}{nested}")), Platform::Base);
+    assert_eq!(issues, vec!["unknown key `base.arbitrum`"]);
+
+    let issues = invalid(table(&format!("{COMMON}{BASE}")), Platform::Arbitrum);
+    assert_eq!(issues, vec!["missing key `arbitrum`"]);
+}
+
+#[test]
+fn loads_the_shipped_configs() {
+    for platform in [Platform::Base, Platform::Arbitrum, Platform::Op, Platform::Mainnet] {
+        let config = Config::load(&platform.default_config_path(), platform).unwrap();
+        match platform {
+            Platform::Base => assert!(config.base.is_some()),
+            Platform::Arbitrum => assert_eq!(config.arbitrum.unwrap().genesis_block, ARB1_GENESIS_BLOCK),
+            Platform::Op => assert_eq!(config.op.unwrap().launchers.len(), 3),
+            Platform::Mainnet => assert!(config.mainnet.is_some()),
+        }
+    }
+}
+
+#[test]
+fn reports_every_unknown_and_missing_key_at_once() {
+    let base = BASE.replace("min_profit = 1\n", "retries = 3\n");
+    let extra = r#"
+[base.reconnect]
+jitter = 0.1
+max_retries = 3
+
+[[base.flashblocks_endpoints]]
+url = "wss://a.example.org/ws"
+priority = 1
+
+[[base.flashblocks_endpoints]]
+url = "wss://b.example.org/ws"
+
+[metrics]
+port = 9000
+"#;
+    let issues = invalid(table(&format!("{COMMON}{base}{extra}")), Platform::Op);
+    assert_eq!(issues, vec![
+        "unknown key `metrics`",
+        "missing key `op`",
+        "unknown key `base.retries`",
+        "missing key `base.min_profit`",
+        "unknown key `base.reconnect.max_retries`",
+        "missing key `base.flashblocks_endpoints[1].priority`",
+    ]);
+}
+
+#[test]
+fn reports_invalid_values() {
+    let extra = "[base.reconnect]\njitter = 2.0\n[base.search]\nmax_evaluations = 0\n";
+    let base = BASE.replace("step_count = 50", "step_count = 0");
+    let issues = invalid(table(&format!("{COMMON}{base}{extra}")), Platform::Base);
+    assert_eq!(issues, vec![
+        "`base.reconnect.jitter` must be between 0 and 1",
+        "`base.search.max_evaluations` must be positive",
+        "`base.step_count` must be positive",
+    ]);
+}
+
+#[test]
+fn locates_values_that_dont_deserialize() {
+    // The path of a bad value is found from the span of the error
+    let base = BASE.replace("min_profit = 1", "min_profit = \"one\"");
+    let issues = invalid(table(&format!("{COMMON}{base}")), Platform::Base);
+    assert_eq!(issues.len(), 1);
+    assert!(issues[0].starts_with("invalid value at `base.min_profit`:"), "{}", issues[0]);
+
+    let search = "[base.search]\nalgorithm = \"simplex\"\n";
+    let issues = invalid(table(&format!("{COMMON}{BASE}{search}")), Platform::Base);
+    assert!(issues[0].starts_with("invalid value at `base.search.algorithm`:"), "{}", issues[0]);
+
+    // Tables of an array are numbered
+    let op = replace_last(OP, "min_profit = 1", "min_profit = -1");
+    let issues = invalid(table(&format!("{COMMON}{op}")), Platform::Op);
+    assert!(issues[0].starts_with("invalid value at `op.launchers[1].min_profit`:"), "{}", issues[0]);
+}
+
+fn replace_last(text: &str, from: &str, to: &str) -> String {
+    let at = text.rfind(from).unwrap();
+    format!("{}{}{}", &text[..at], to, &text[at + from.len()..])
+}
+
+#[test]
+fn overrides_keys_from_the_environment() {
+    let mut root = table(&format!("{COMMON}{BASE}{OP}"));
+    let vars = [
+        ("MS_BOT__COMMON__RPC_URL", "http://node:8545"),
+        ("MS_BOT__COMMON__TEST_MODE", "true"),
+        ("MS_BOT__BASE__MIN_PROFIT", "5"),
+        ("MS_BOT__BASE__RECONNECT__JITTER", "0.5"),
+        ("MS_BOT__OP__LAUNCHERS__1__MIN_PROFIT", "7"),
+        // Strings stay strings even when they read as another TOML scalar
+        ("MS_BOT__COMMON__KEYSTORE", "2024"),
+        ("MS_BOT__BASE__TRADE_SERVER_URL", "true"),
+        ("OTHER__COMMON__GAS_LIMIT", "1"),
+    ];
+    let issues = apply_env_overrides(&mut root, vars.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+    assert!(issues.is_empty(), "{issues:?}");
+
+    let config = Config::from_table(root.clone(), Platform::Base).unwrap();
+    assert_eq!(config.common.rpc_url, "http://node:8545");
+    assert!(config.common.test_mode);
+    assert_eq!(config.common.gas_limit, 7000000);
+    assert_eq!(config.common.keystore, "2024");
+    let base = config.base.unwrap();
+    assert_eq!(base.trade_server_url, "true");
+    assert_eq!(base.min_profit, 5);
+    assert_eq!(base.reconnect.jitter, 0.5);
+    let launchers = config.op.unwrap().launchers;
+    assert_eq!(launchers.iter().map(|l| l.min_profit).collect::<Vec<_>>(), vec![1, 7]);
+
+    // Overrides that don't point at a value are reported instead of dropped
+    let vars = [
+        ("MS_BOT__OP__LAUNCHERS__2__MIN_PROFIT", "9"),
+        ("MS_BOT__BASE__STEP_COUNT__MAX", "9"),
+    ];
+    let issues = apply_env_overrides(&mut root, vars.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+    assert_eq!(issues, vec![
+        "`MS_BOT__OP__LAUNCHERS__2__MIN_PROFIT` can't be applied: no entry `2` in the array at `op.launchers`",
+        "`MS_BOT__BASE__STEP_COUNT__MAX` can't be applied: `base.step_count` isn't a table",
+    ]);
+
+    // Overrides go through the same validation as the file
+    apply_env_overrides(&mut root, [("MS_BOT__BASE__STEP_COUNT".to_string(), "none".to_string())].into_iter());
+    let issues = invalid(root, Platform::Base);
+    assert!(issues[0].starts_with("invalid value at `base.step_count`:"), "{}", issues[0]);
+}
+
+#[test]
+fn replays_only_drive_the_pending_simulation() {
+    let base = |extra: &str| {
+        let config = Config::from_table(table(&format!("{COMMON}{BASE}{extra}")), Platform::Base).unwrap();
+        config.base.unwrap()
+    };
+
+    base("simulation_mode = true\nsimulate_pending = true\n").check_replay().unwrap();
+
+    for (extra, expected) in [
+        ("execution_mode = true\nsimulation_mode = true\nsimulate_pending = true\n", "`base.execution_mode` must be off to replay"),
+        // Without pending simulation nothing would consume the flashblocks
+        ("simulation_mode = true\n", "`base.simulation_mode` and `base.simulate_pending` must be on to replay"),
+        ("", "`base.simulation_mode` and `base.simulate_pending` must be on to replay"),
+    ] {
+        match base(extra).check_replay() {
+            Err(ConfigError::Invalid(issues)) => assert_eq!(issues, vec![expected]),
+            other => panic!("expected the replay to be refused, got {:?}", other),
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use ms_bot::engine::Engine;
+use ms_bot::types::{Collector, CollectorStream, Executor, Strategy};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio::time::timeout;
+
+// Emits a fixed list of events, then ends
+struct ListCollector(Vec<u64>);
+
+#[async_trait]
+impl Collector<u64> for ListCollector {
+    async fn get_event_stream<'a>(&'a self) -> Result<CollectorStream<'a, u64>> {
+        Ok(Box::pin(tokio_stream::iter(self.0.clone())))
+    }
+}
+
+// Acts on even events only, with the running sum of the events seen so far
+#[derive(Default)]
+struct SumStrategy {
+    synced: bool,
+    sum: u64,
+}
+
+#[async_trait]
+impl Strategy<u64, String> for SumStrategy {
+    async fn sync_state(&mut self) -> Result<()> {
+        self.synced = true;
+        Ok(())
+    }
+
+    async fn process_event(&mut self, event: u64) -> Vec<String> {
+        assert!(self.synced, "events are processed after the state is synced");
+        self.sum += event;
+        if event.is_multiple_of(2) {
+            vec![format!("sum {}", self.sum)]
+        } else {
+            vec![]
+        }
+    }
+}
+
+struct ChannelExecutor(UnboundedSender<String>);
+
+#[async_trait]
+impl Executor<String> for ChannelExecutor {
+    async fn execute(&self, action: String) -> Result<()> {
+        self.0.send(action)?;
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn engine_delivers_the_actions_of_collected_events() {
+    let (sender, mut receiver) = unbounded_channel();
+
+    let mut engine: Engine<u64, String> = Engine::default();
+    engine.add_collector(Box::new(ListCollector(vec![1, 2, 3, 4])));
+    engine.add_strategy(Box::new(SumStrategy::default()));
+    engine.add_executor(Box::new(ChannelExecutor(sender)));
+    let _set = engine.run().await.unwrap();
+
+    let mut actions = vec![];
+    for _ in 0..2 {
+        let action = timeout(Duration::from_secs(5), receiver.recv()).await
+            .expect("the action is delivered")
+            .unwrap();
+        actions.push(action);
+    }
+    assert_eq!(actions, vec!["sum 3", "sum 10"]);
+}
+
+#[tokio::test]
+async fn engine_fails_when_a_strategy_cant_sync() {
+    struct BrokenStrategy;
+
+    #[async_trait]
+    impl Strategy<u64, String> for BrokenStrategy {
+        async fn sync_state(&mut self) -> Result<()> {
+            anyhow::bail!("no state")
+        }
+
+        async fn process_event(&mut self, _: u64) -> Vec<String> {
+            vec![]
+        }
+    }
+
+    let mut engine: Engine<u64, String> = Engine::default();
+    engine.add_strategy(Box::new(BrokenStrategy));
+    assert_eq!(engine.run().await.unwrap_err().to_string(), "no state");
+}
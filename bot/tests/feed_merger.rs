@@ -0,0 +1,49 @@
+use std::time::{Duration, Instant};
+
+use ms_bot::collectors::feed_merger::{ConnectionStats, FeedMerger};
+use ms_bot::types::FlashblocksPayloadV1;
+
+fn flashblock(block_number: u64, index: u64) -> FlashblocksPayloadV1 {
+    let mut flashblock = FlashblocksPayloadV1 { index, ..Default::default() };
+    flashblock.metadata.block_number = block_number;
+    flashblock
+}
+
+#[test]
+fn first_copy_wins_and_later_copies_record_lag() {
+    let merger = FeedMerger::new();
+    let start = Instant::now();
+
+    assert!(merger.observe_at(1, &flashblock(100, 0), start));
+    assert!(!merger.observe_at(0, &flashblock(100, 0), start + Duration::from_millis(3)));
+    assert!(merger.observe_at(0, &flashblock(100, 1), start + Duration::from_millis(200)));
+    assert!(!merger.observe_at(1, &flashblock(100, 1), start + Duration::from_millis(201)));
+
+    assert_eq!(merger.stats(), vec![
+        (0, ConnectionStats { wins: 1, duplicates: 1, total_lag_us: 3_000 }),
+        (1, ConnectionStats { wins: 1, duplicates: 1, total_lag_us: 1_000 }),
+    ]);
+    assert_eq!(merger.stats()[0].1.average_lag_us(), 3_000);
+}
+
+#[test]
+fn same_index_in_another_block_is_not_a_duplicate() {
+    let merger = FeedMerger::new();
+
+    assert!(merger.observe(0, &flashblock(100, 3)));
+    assert!(merger.observe(0, &flashblock(101, 3)));
+    assert!(!merger.observe(1, &flashblock(100, 3)));
+}
+
+#[test]
+fn flashblocks_older_than_the_window_are_dropped() {
+    let merger = FeedMerger::new();
+
+    assert!(merger.observe(0, &flashblock(100, 0)));
+    assert!(merger.observe(0, &flashblock(110, 0)));
+
+    // Block 100 is forgotten, a late copy must not be passed on again
+    assert!(!merger.observe(1, &flashblock(100, 0)));
+    assert!(!merger.observe(1, &flashblock(100, 5)));
+    assert!(merger.observe(1, &flashblock(109, 0)));
+}
@@ -0,0 +1,336 @@
+mod support;
+
+use alloy_primitives::TxKind;
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Bytes, Eip1559TransactionRequest, H160, H256, U256};
+use ethers::utils::keccak256;
+use ethers::utils::rlp::RlpStream;
+use ms_bot::errors::FlashblockError;
+use ms_bot::flashblock_accumulator::{FlashblockAccumulator, PendingTransaction, Progress, DEPOSIT_TX_TYPE};
+use ms_bot::types::{FlashblockReceipt, FlashblocksPayloadV1, MetadataV1};
+use serde_json::json;
+use support::mock_flashblocks::fixture_flashblocks;
+
+const BLOCK: u64 = 0x1f4a2c0;
+
+fn pool() -> H160 {
+    "0xd0b53d9277642d899df5c87a3966a349a798f224".parse().unwrap()
+}
+
+fn swap_topic() -> H256 {
+    "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67".parse().unwrap()
+}
+
+const KEY: &str = "4c0883a69102937d6231471b5dbb6204fe5129617082792ae468d01a3f362318";
+
+fn hash(raw: &[u8]) -> H256 {
+    H256::from(keccak256(raw))
+}
+
+// The fixtures moved to `block_number`
+fn block(block_number: u64) -> Vec<FlashblocksPayloadV1> {
+    let mut flashblocks = fixture_flashblocks();
+    for flashblock in flashblocks.iter_mut() {
+        flashblock.metadata.block_number = block_number;
+        if let Some(base) = flashblock.base.as_mut() {
+            base.block_number = block_number;
+        }
+    }
+    flashblocks
+}
+
+#[test]
+fn rebuilds_the_pending_block() {
+    let mut flashblocks = fixture_flashblocks();
+    flashblocks[2].diff.state_root = H256::repeat_byte(1);
+    flashblocks[2].diff.gas_used = 0x2dc6c0;
+    flashblocks[1].metadata.new_account_balances.insert(H160::repeat_byte(2), U256::from(5));
+
+    let mut accumulator = FlashblockAccumulator::new();
+    assert_eq!(accumulator.block_number(), None);
+
+    let progress: Vec<_> = flashblocks.into_iter().map(|fb| accumulator.apply(fb).unwrap()).collect();
+    assert_eq!(progress, vec![
+        Progress::Applied { index: 0, new_block: true },
+        Progress::Applied { index: 1, new_block: false },
+        Progress::Applied { index: 2, new_block: false },
+    ]);
+
+    assert_eq!(accumulator.block_number(), Some(BLOCK));
+    assert_eq!(accumulator.flashblock_index(), Some(2));
+    assert_eq!(accumulator.base().unwrap().timestamp, 0x68a0c3e0);
+
+    let txs = accumulator.transactions();
+    assert_eq!(txs.len(), 2);
+    assert_eq!((txs[0].index, txs[0].flashblock_index, txs[0].gas_used), (0, 0, 0xb71b));
+    assert_eq!((txs[1].index, txs[1].flashblock_index, txs[1].gas_used), (1, 1, 0x2dc6c0 - 0xb71b));
+    assert!(txs[0].receipt.is_deposit());
+    assert_eq!(txs[1].receipt.tx_type(), 2);
+    assert!(txs.iter().all(|tx| tx.receipt.status()));
+    assert!(accumulator.transaction(txs[1].hash).is_some());
+
+    assert_eq!(accumulator.cumulative_gas_used(), 0x2dc6c0);
+    assert_eq!(accumulator.gas_used(), 0x2dc6c0);
+    assert_eq!(accumulator.state_root(), H256::repeat_byte(1));
+    assert_eq!(accumulator.balance(H160::repeat_byte(2)), Some(U256::from(5)));
+
+    let swaps: Vec<_> = accumulator.logs_by_address(pool()).collect();
+    assert_eq!(swaps.len(), 1);
+    assert_eq!((swaps[0].log_index, swaps[0].transaction.index), (0, 1));
+    assert_eq!(accumulator.logs_by_topic(swap_topic()).count(), 1);
+    assert_eq!(accumulator.logs_by_address(H160::zero()).count(), 0);
+}
+
+#[test]
+fn waits_for_missing_flashblocks() {
+    let flashblocks = fixture_flashblocks();
+    let mut accumulator = FlashblockAccumulator::new();
+
+    assert_eq!(accumulator.apply(flashblocks[2].clone()).unwrap(), Progress::Queued);
+    assert_eq!(accumulator.apply(flashblocks[1].clone()).unwrap(), Progress::Queued);
+    assert_eq!(accumulator.transactions().len(), 0);
+
+    assert_eq!(accumulator.apply(flashblocks[0].clone()).unwrap(), Progress::Applied { index: 2, new_block: true });
+    assert_eq!(accumulator.transactions().len(), 2);
+
+    // Copies and older blocks change nothing
+    assert_eq!(accumulator.apply(flashblocks[1].clone()).unwrap(), Progress::Ignored);
+    assert_eq!(accumulator.apply(block(BLOCK - 1)[0].clone()).unwrap(), Progress::Ignored);
+    assert_eq!(accumulator.transactions().len(), 2);
+}
+
+#[test]
+fn starts_over_on_the_next_block() {
+    let mut accumulator = FlashblockAccumulator::new();
+    for flashblock in fixture_flashblocks() {
+        accumulator.apply(flashblock).unwrap();
+    }
+
+    let next = block(BLOCK + 1);
+    assert_eq!(accumulator.apply(next[0].clone()).unwrap(), Progress::Applied { index: 0, new_block: true });
+    assert_eq!(accumulator.block_number(), Some(BLOCK + 1));
+    assert_eq!(accumulator.transactions().len(), 1);
+    assert_eq!(accumulator.logs().count(), 0);
+}
+
+#[test]
+fn numbers_logs_across_flashblocks() {
+    let mut flashblocks = fixture_flashblocks();
+    // Flashblock 2 adds a second swap in the same pool
+    let mut receipts = flashblocks[1].metadata.receipts.clone();
+    let (_, receipt) = receipts.pop_first().unwrap();
+    let mut receipt = receipt.receipt().clone();
+    receipt.cumulative_gas_used = 0x2f0000;
+    flashblocks[2].metadata.receipts.insert(hash(&[2]), FlashblockReceipt::Eip1559(receipt));
+    flashblocks[2].diff.transactions.push(Bytes::from(vec![2]));
+
+    let mut accumulator = FlashblockAccumulator::new();
+    for flashblock in flashblocks {
+        accumulator.apply(flashblock).unwrap();
+    }
+
+    let logs: Vec<_> = accumulator.logs_by_topic(swap_topic())
+        .map(|pending| (pending.log_index, pending.transaction.hash))
+        .collect();
+    assert_eq!(logs.len(), 2);
+    assert_eq!(logs[0].0, 0);
+    assert_eq!(logs[1], (1, hash(&[2])));
+}
+
+#[test]
+fn matches_receipts_by_transaction_hash() {
+    let mut flashblocks = fixture_flashblocks();
+    let (_, swap) = flashblocks[1].metadata.receipts.pop_first().unwrap();
+    let mut failed = swap.receipt().clone();
+    failed.status = false;
+    failed.logs.clear();
+    failed.cumulative_gas_used = 0x2e0000;
+
+    // The receipts sort the other way round from the transactions
+    let (first, second) = (vec![0x02, 0x02], vec![0x02, 0x01]);
+    assert!(hash(&first) > hash(&second));
+    flashblocks[1].diff.transactions = vec![first.clone().into(), second.clone().into()];
+    flashblocks[1].metadata.receipts.insert(hash(&first), swap);
+    flashblocks[1].metadata.receipts.insert(hash(&second), FlashblockReceipt::Eip1559(failed));
+
+    let mut accumulator = FlashblockAccumulator::new();
+    for flashblock in flashblocks {
+        accumulator.apply(flashblock).unwrap();
+    }
+
+    let txs = accumulator.transactions();
+    assert_eq!((txs[1].hash, txs[2].hash), (hash(&first), hash(&second)));
+    assert!(txs[1].receipt.status() && !txs[2].receipt.status());
+    assert_eq!((txs[1].gas_used, txs[2].gas_used), (0x2dc6c0 - 0xb71b, 0x2e0000 - 0x2dc6c0));
+    assert_eq!(accumulator.logs_by_address(pool()).next().unwrap().transaction.hash, hash(&first));
+}
+
+#[test]
+fn rejects_transactions_without_a_receipt() {
+    let mut flashblocks = fixture_flashblocks();
+    flashblocks[1].diff.transactions = vec![Bytes::from(vec![0x02, 0x03])];
+
+    let mut accumulator = FlashblockAccumulator::new();
+    accumulator.apply(flashblocks[0].clone()).unwrap();
+    assert!(matches!(
+        accumulator.apply(flashblocks[1].clone()),
+        Err(FlashblockError::MissingReceipt { block_number: BLOCK, index: 1, hash: missing }) if missing == hash(&[0x02, 0x03])
+    ));
+    assert!(accumulator.transactions().is_empty());
+}
+
+#[test]
+fn drops_a_block_that_doesnt_add_up() {
+    let mut flashblocks = fixture_flashblocks();
+    flashblocks[1].diff.transactions.push(Bytes::from(vec![1]));
+
+    let mut accumulator = FlashblockAccumulator::new();
+    accumulator.apply(flashblocks[0].clone()).unwrap();
+    assert!(matches!(
+        accumulator.apply(flashblocks[1].clone()),
+        Err(FlashblockError::ReceiptCount { block_number: BLOCK, index: 1, transactions: 2, receipts: 1 })
+    ));
+
+    // Nothing of the block is exposed and the rest of it is ignored
+    assert_eq!(accumulator.block_number(), None);
+    assert!(accumulator.transactions().is_empty());
+    assert_eq!(accumulator.apply(flashblocks[2].clone()).unwrap(), Progress::Ignored);
+
+    assert_eq!(accumulator.apply(block(BLOCK + 1)[0].clone()).unwrap(), Progress::Applied { index: 0, new_block: true });
+}
+
+#[test]
+fn parses_typed_receipts() {
+    let (deposit, legacy) = (vec![DEPOSIT_TX_TYPE], vec![0xf8]);
+    let metadata: MetadataV1 = serde_json::from_value(json!({
+        "block_number": 10,
+        "receipts": {
+            format!("{:?}", hash(&deposit)): {
+                "Deposit": { "status": "0x1", "cumulativeGasUsed": "0xb71b", "logs": [], "depositNonce": "0x5", "depositReceiptVersion": "0x1" }
+            },
+            format!("{:?}", hash(&legacy)): {
+                "Legacy": { "status": "0x0", "cumulativeGasUsed": "0x1b71b", "logs": [] }
+            }
+        }
+    })).unwrap();
+
+    let receipts = [metadata.receipt_of(&deposit), metadata.receipt_of(&legacy)];
+    assert_eq!((receipts[0].0, receipts[1].0), (hash(&deposit), hash(&legacy)));
+    match receipts[0].1.unwrap() {
+        FlashblockReceipt::Deposit(deposit) => {
+            assert_eq!(deposit.deposit_nonce, Some(5));
+            assert_eq!(deposit.deposit_receipt_version, Some(1));
+            assert_eq!(deposit.receipt.cumulative_gas_used, 0xb71b);
+        }
+        other => panic!("expected a deposit receipt, got {:?}", other),
+    }
+    assert_eq!(receipts[1].1.unwrap().tx_type(), 0);
+    assert!(!receipts[1].1.unwrap().status());
+    assert_eq!(metadata.receipt_of(&[0x02]).1, None);
+}
+
+#[test]
+fn rejects_malformed_receipts() {
+    let receipts = [
+        json!({ "Eip1559": { "status": "yes", "cumulativeGasUsed": "0x1", "logs": [] } }),
+        json!({ "Eip1559": { "status": "0x1", "logs": [] } }),
+        json!({ "Eip4844": { "status": "0x1", "cumulativeGasUsed": "0x1", "logs": [] } }),
+        json!({ "Eip1559": { "status": "0x1", "cumulativeGasUsed": "0x1", "logs": [{ "address": "0x01" }] } }),
+    ];
+
+    for receipt in receipts {
+        let metadata = json!({ "block_number": 10, "receipts": { format!("{:?}", H256::zero()): receipt.clone() } });
+        assert!(serde_json::from_value::<MetadataV1>(metadata).is_err(), "{} should not parse", receipt);
+    }
+}
+
+#[test]
+fn rejects_flashblocks_that_dont_add_up() {
+    let mut flashblocks = fixture_flashblocks();
+    flashblocks[1].diff.transactions.clear();
+
+    let mut accumulator = FlashblockAccumulator::new();
+    accumulator.apply(flashblocks[0].clone()).unwrap();
+    assert!(matches!(
+        accumulator.apply(flashblocks[1].clone()),
+        Err(FlashblockError::ReceiptCount { transactions: 0, receipts: 1, .. })
+    ));
+
+    let mut no_base = block(BLOCK + 1);
+    no_base[0].base = None;
+    assert!(matches!(accumulator.apply(no_base[0].clone()), Err(FlashblockError::MissingBase(_))));
+}
+
+// The swap of the fixtures with `raw` as its envelope
+fn pending_transaction(raw: Vec<u8>) -> PendingTransaction {
+    let mut accumulator = FlashblockAccumulator::new();
+    let mut flashblocks = fixture_flashblocks();
+    let (_, receipt) = flashblocks[1].metadata.receipts.pop_first().unwrap();
+    flashblocks[1].metadata.receipts.insert(hash(&raw), receipt);
+    flashblocks[1].diff.transactions = vec![raw.into()];
+    for flashblock in flashblocks {
+        accumulator.apply(flashblock).unwrap();
+    }
+    accumulator.transactions()[1].clone()
+}
+
+#[test]
+fn replays_signed_transactions() {
+    let wallet = KEY.parse::<LocalWallet>().unwrap().with_chain_id(8453u64);
+    let tx: TypedTransaction = Eip1559TransactionRequest::new()
+        .to(pool())
+        .value(1_000)
+        .data(vec![0x12, 0x34])
+        .nonce(3)
+        .gas(200_000)
+        .max_fee_per_gas(10_000_000)
+        .max_priority_fee_per_gas(0)
+        .chain_id(8453u64)
+        .into();
+    let signature = wallet.sign_transaction_sync(&tx).unwrap();
+
+    let pending_call = pending_transaction(tx.rlp_signed(&signature).to_vec()).pending_call().unwrap();
+    assert_eq!(pending_call.mint, U256::zero());
+    let simulation_tx = pending_call.tx;
+    assert_eq!(simulation_tx.caller, alloy_primitives::Address::from(wallet.address().0));
+    assert_eq!(simulation_tx.transact_to, TxKind::Call(pool().0.into()));
+    assert_eq!(simulation_tx.value, alloy_primitives::U256::from(1_000));
+    assert_eq!(simulation_tx.data.to_vec(), vec![0x12, 0x34]);
+}
+
+#[test]
+fn replays_deposits_with_their_mint() {
+    let from = H160::repeat_byte(0xde);
+    let to = H160::repeat_byte(0x42);
+
+    let mut rlp = RlpStream::new_list(8);
+    rlp.append(&H256::repeat_byte(1));
+    rlp.append(&from);
+    rlp.append(&to);
+    rlp.append(&U256::from(5_000));
+    rlp.append(&U256::from(700));
+    rlp.append(&1_000_000u64);
+    rlp.append(&false);
+    rlp.append(&vec![0xabu8]);
+    let raw = [&[DEPOSIT_TX_TYPE][..], &rlp.out()].concat();
+
+    let pending_call = pending_transaction(raw).pending_call().unwrap();
+    assert_eq!(pending_call.mint, U256::from(5_000));
+    let simulation_tx = pending_call.tx;
+    assert_eq!(simulation_tx.caller, alloy_primitives::Address::from(from.0));
+    assert_eq!(simulation_tx.transact_to, TxKind::Call(to.0.into()));
+    assert_eq!(simulation_tx.value, alloy_primitives::U256::from(700));
+    assert_eq!(simulation_tx.data.to_vec(), vec![0xab]);
+}
+
+#[test]
+fn rejects_transactions_it_cant_replay() {
+    for raw in [vec![], vec![0x03, 0x01], vec![0x02, 0xf8, 0xb1], vec![DEPOSIT_TX_TYPE, 0xc0]] {
+        let tx = pending_transaction(raw.clone());
+        assert!(
+            matches!(tx.pending_call(), Err(FlashblockError::InvalidTransaction(hash, _)) if hash == tx.hash),
+            "{:?} should not replay", raw
+        );
+    }
+}
@@ -0,0 +1,531 @@
+mod support;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use ms_bot::collectors::chain_check::{ChainCheck, ChainHead};
+use ms_bot::collectors::feed_client::{ClientOptions, Heartbeat, RelayClient};
+use ms_bot::collectors::feed_clients::{FeedEndpoint, RelayClients};
+use ms_bot::collectors::feed_recorder::{read_recording, FeedRecorder, RecordedFrame, MAX_FRAME_SIZE};
+use ms_bot::collectors::flash_block_collector::{start_flash_block_collector, FlashBlockCollector};
+use ms_bot::collectors::frame_quarantine::{DecodeErrorCounts, FrameQuarantine};
+use ms_bot::collectors::reconnect_policy::ReconnectPolicy;
+use ms_bot::collectors::replay_collector::replay_recording;
+use ms_bot::errors::{ConnectionUpdate, RelayError};
+use ms_bot::types::{Collector, FlashblocksPayloadV1};
+use support::mock_flashblocks::{fixture_flashblocks, MockFlashblocksServer, Step};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver};
+use tokio::time::timeout;
+use url::Url;
+
+const CHAIN_ID: u64 = 8453;
+
+async fn recv<T>(receiver: &mut UnboundedReceiver<T>) -> Option<T> {
+    timeout(Duration::from_secs(5), receiver.recv()).await.expect("timed out waiting for the feed")
+}
+
+// A chain head that answers with fixed values
+struct FixedHead {
+    chain_id: u64,
+    block_number: u64,
+}
+
+#[async_trait::async_trait]
+impl ChainHead for FixedHead {
+    async fn chain_id(&self) -> Result<u64, RelayError> {
+        Ok(self.chain_id)
+    }
+
+    async fn block_number(&self) -> Result<u64, RelayError> {
+        Ok(self.block_number)
+    }
+}
+
+// The fixtures with the first flashblock stamped with the current time
+fn fresh_flashblocks() -> Vec<FlashblocksPayloadV1> {
+    let mut flashblocks = fixture_flashblocks();
+    let now = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs();
+    flashblocks[0].base.as_mut().unwrap().timestamp = now;
+    flashblocks
+}
+
+fn chain_check(chain_id: u64, block_number: u64) -> Arc<ChainCheck> {
+    Arc::new(ChainCheck::new(CHAIN_ID, FixedHead { chain_id, block_number }))
+}
+
+fn json(flashblock: &FlashblocksPayloadV1) -> serde_json::Value {
+    serde_json::to_value(flashblock).unwrap()
+}
+
+#[tokio::test]
+async fn collector_delivers_fixture_frames_in_order() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+
+    let mut receiver = start_flash_block_collector(vec![FeedEndpoint::new(&server.url, 0)], CHAIN_ID, ReconnectPolicy::default(), ClientOptions::default()).await.unwrap();
+
+    for expected in &fixtures {
+        let flashblock = recv(&mut receiver).await.unwrap();
+        assert_eq!(json(&flashblock), json(expected));
+    }
+}
+
+#[tokio::test]
+async fn collector_reports_a_feed_it_cant_start() {
+    assert!(matches!(
+        start_flash_block_collector(vec![], CHAIN_ID, ReconnectPolicy::default(), ClientOptions::default()).await,
+        Err(RelayError::Msg(_))
+    ));
+    assert!(FlashBlockCollector::new(vec![], CHAIN_ID).get_event_stream().await.is_err());
+}
+
+#[tokio::test]
+async fn reader_merges_redundant_connections_without_duplicates() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![
+        fixtures.iter().cloned().map(Step::Flashblock).collect(),
+        // The second connection lags behind the first one
+        fixtures.iter().cloned().flat_map(|f| [Step::Sleep(Duration::from_millis(20)), Step::Flashblock(f)]).collect(),
+    ]).await;
+
+    let two_connections = ReconnectPolicy { min_connections: 2, max_connections: 2, ..Default::default() };
+    let (sender, mut receiver) = unbounded_channel();
+    let clients = RelayClients::new(&[FeedEndpoint::new(&server.url, 0)], CHAIN_ID, two_connections, sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+
+    assert!(timeout(Duration::from_millis(300), receiver.recv()).await.is_err());
+    assert_eq!(server.connections(), 2);
+}
+
+#[tokio::test]
+async fn close_frame_is_reported_as_closed() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Flashblock(fixtures[0].clone()),
+        Step::Close,
+        Step::Flashblock(fixtures[1].clone()),
+    ]]).await;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, mut update_receiver) = unbounded_channel();
+    let client = RelayClient::new(Url::parse(&server.url).unwrap(), 0, sender, update_sender, ClientOptions::default()).await.unwrap();
+
+    client.run().await.unwrap();
+
+    assert_eq!(recv(&mut receiver).await.unwrap().index, 0);
+    assert!(receiver.recv().await.is_none());
+    assert!(matches!(update_receiver.try_recv(), Ok(ConnectionUpdate::Closed(0))));
+    assert!(update_receiver.try_recv().is_err());
+}
+
+#[tokio::test]
+async fn disconnect_is_reported_as_stopped_sending_frames() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Flashblock(fixtures[0].clone()),
+        Step::Disconnect,
+    ]]).await;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, mut update_receiver) = unbounded_channel();
+    let client = RelayClient::new(Url::parse(&server.url).unwrap(), 7, sender, update_sender, ClientOptions::default()).await.unwrap();
+
+    client.spawn().await.unwrap();
+
+    assert_eq!(recv(&mut receiver).await.unwrap().index, 0);
+    assert!(matches!(recv(&mut update_receiver).await, Some(ConnectionUpdate::StoppedSendingFrames(7))));
+}
+
+#[tokio::test]
+async fn bad_frames_are_quarantined_and_the_client_keeps_reading() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Flashblock(fixtures[0].clone()),
+        Step::Garbage(vec![0xff; 64]),
+        Step::Flashblock(fixtures[1].clone()),
+        Step::MalformedJson("{\"index\": 1, \"diff\":".to_string()),
+        Step::Garbage(support::mock_flashblocks::compress(&[0xc3, 0x28])),
+        Step::Flashblock(fixtures[2].clone()),
+        Step::Close,
+    ]]).await;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, mut update_receiver) = unbounded_channel();
+    let quarantine = Arc::new(FrameQuarantine::new(2));
+    let client = RelayClient::new(
+        Url::parse(&server.url).unwrap(), 3, sender, update_sender,
+        ClientOptions { quarantine: Some(quarantine.clone()), ..Default::default() },
+    ).await.unwrap();
+
+    client.run().await.unwrap();
+
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+    assert!(matches!(update_receiver.try_recv(), Ok(ConnectionUpdate::Closed(3))));
+
+    assert_eq!(quarantine.counts(), DecodeErrorCounts { decompress: 1, utf8: 1, payload: 1 });
+
+    // Only the two most recent bad frames are kept
+    let frames = quarantine.frames();
+    assert_eq!(frames.len(), 2);
+    assert!(frames.iter().all(|f| f.client_id == 3));
+    assert!(frames[0].error.starts_with("Frame is not a valid feed message"));
+    assert!(frames[1].error.starts_with("Frame is not valid utf8"));
+}
+
+#[tokio::test]
+async fn recorded_frames_replay_in_order() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Flashblock(fixtures[0].clone()),
+        Step::Flashblock(fixtures[1].clone()),
+        Step::Flashblock(fixtures[2].clone()),
+        Step::Close,
+    ]]).await;
+
+    let path = std::env::temp_dir().join(format!("ms_bot_recording_{}.bin", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let _ = std::fs::remove_file(&path);
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, _update_receiver) = unbounded_channel();
+    let recorder = FeedRecorder::open(&path).unwrap();
+    let options = ClientOptions { recorder: Some(recorder.clone()), ..Default::default() };
+    let client = RelayClient::new(Url::parse(&server.url).unwrap(), 0, sender, update_sender, options).await.unwrap();
+    client.run().await.unwrap();
+    while receiver.recv().await.is_some() {}
+
+    recorder.flush().await.unwrap();
+
+    let (sender, mut receiver) = unbounded_channel();
+    let count = replay_recording(&path, 0.0, sender).await.unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(count, fixtures.len());
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+}
+
+#[tokio::test]
+async fn recorder_reports_a_failed_write_and_keeps_recording() {
+    let path = std::env::temp_dir().join(format!("ms_bot_failed_recording_{}.bin", std::process::id()));
+    let path = path.to_str().unwrap().to_string();
+    let _ = std::fs::remove_file(&path);
+
+    let recorder = FeedRecorder::open(&path).unwrap();
+    recorder.record(0, b"first");
+    recorder.record(1, &vec![0; MAX_FRAME_SIZE + 1]);
+    recorder.record(2, b"second");
+    assert_eq!(recorder.flush().await.unwrap_err().kind(), std::io::ErrorKind::InvalidInput);
+
+    // The error is reported once, the frames after it are still written
+    recorder.record(3, b"third");
+    recorder.flush().await.unwrap();
+
+    let frames = read_recording(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let frames: Vec<_> = frames.into_iter().map(|f| (f.client_id, f.data)).collect();
+    assert_eq!(frames, vec![(0, b"first".to_vec()), (2, b"second".to_vec()), (3, b"third".to_vec())]);
+}
+
+#[test]
+fn recordings_with_oversized_frames_are_rejected() {
+    let frame = RecordedFrame { received_at_us: 1, client_id: 2, data: vec![0xab; 10] };
+    let mut bytes = vec![];
+    frame.write_to(&mut bytes).unwrap();
+    assert_eq!(RecordedFrame::read_from(&mut &bytes[..]).unwrap(), Some(frame));
+
+    // A frame cut short by a crash ends the recording
+    assert_eq!(RecordedFrame::read_from(&mut &bytes[..bytes.len() - 1]).unwrap(), None);
+
+    // A corrupt length isn't allocated
+    bytes[12..16].copy_from_slice(&(MAX_FRAME_SIZE as u32 + 1).to_le_bytes());
+    let e = RecordedFrame::read_from(&mut &bytes[..]).unwrap_err();
+    assert_eq!(e.kind(), std::io::ErrorKind::InvalidData);
+
+    let oversized = RecordedFrame { received_at_us: 1, client_id: 2, data: vec![0; MAX_FRAME_SIZE + 1] };
+    assert!(oversized.write_to(&mut vec![]).is_err());
+}
+
+#[tokio::test]
+async fn silent_connection_is_reported_as_stalled() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Flashblock(fixtures[0].clone()),
+        Step::Sleep(Duration::from_secs(30)),
+    ]]).await;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, mut update_receiver) = unbounded_channel();
+    let heartbeat = Heartbeat { ping_interval: Duration::from_millis(50), stall_timeout: Duration::from_millis(300) };
+    let client = RelayClient::new(
+        Url::parse(&server.url).unwrap(), 4, sender, update_sender,
+        ClientOptions { heartbeat, ..Default::default() },
+    ).await.unwrap();
+
+    timeout(Duration::from_secs(5), client.run()).await.unwrap().unwrap();
+
+    assert_eq!(recv(&mut receiver).await.unwrap().index, 0);
+    assert!(matches!(recv(&mut update_receiver).await, Some(ConnectionUpdate::Stalled(4))));
+}
+
+#[tokio::test]
+async fn client_answers_server_pings() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![vec![
+        Step::Ping(b"are you there".to_vec()),
+        Step::Flashblock(fixtures[0].clone()),
+    ]]).await;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, _update_receiver) = unbounded_channel();
+    let client = RelayClient::new(Url::parse(&server.url).unwrap(), 0, sender, update_sender, ClientOptions::default()).await.unwrap();
+    let handle = client.spawn();
+
+    assert_eq!(recv(&mut receiver).await.unwrap().index, 0);
+    timeout(Duration::from_secs(5), async {
+        while server.pongs() == 0 {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }).await.expect("no pong received");
+
+    handle.abort();
+}
+
+#[tokio::test]
+async fn reader_replaces_a_dropped_connection_after_the_backoff() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![
+        vec![Step::Flashblock(fixtures[0].clone()), Step::Disconnect],
+        fixtures.iter().cloned().map(Step::Flashblock).collect(),
+    ]).await;
+
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(50),
+        max_backoff: Duration::from_secs(60),
+        min_connections: 1,
+        max_connections: 1,
+        ..Default::default()
+    };
+    let (sender, mut receiver) = unbounded_channel();
+    let clients = RelayClients::new(&[FeedEndpoint::new(&server.url, 0)], CHAIN_ID, policy, sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    // The replacement repeats flashblock 0, which the merger drops
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+    assert_eq!(server.connections(), 2);
+}
+
+#[tokio::test]
+async fn reader_replaces_a_closed_connection_after_the_backoff() {
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::start(vec![
+        vec![Step::Flashblock(fixtures[0].clone()), Step::Close],
+        fixtures.iter().cloned().map(Step::Flashblock).collect(),
+    ]).await;
+
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(300),
+        max_backoff: Duration::from_secs(60),
+        multiplier: 1.0,
+        jitter: 0.0,
+        min_connections: 1,
+        max_connections: 1,
+    };
+    let (sender, mut receiver) = unbounded_channel();
+    let clients = RelayClients::new(&[FeedEndpoint::new(&server.url, 0)], CHAIN_ID, policy, sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    assert_eq!(recv(&mut receiver).await.unwrap().index, 0);
+    let closed = tokio::time::Instant::now();
+    for expected in &fixtures[1..] {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+    assert!(closed.elapsed() >= policy.initial_backoff, "replaced after {:?}", closed.elapsed());
+    assert_eq!(server.connections(), 2);
+}
+
+#[tokio::test]
+async fn reader_grows_the_pool_up_to_the_budget() {
+    let server = MockFlashblocksServer::start(vec![vec![]]).await;
+
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(20),
+        max_backoff: Duration::from_millis(20),
+        min_connections: 1,
+        max_connections: 3,
+        ..Default::default()
+    };
+    let (sender, _receiver) = unbounded_channel();
+    let clients = RelayClients::new(&[FeedEndpoint::new(&server.url, 0)], CHAIN_ID, policy, sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    assert_eq!(server.connections(), 3);
+}
+
+#[tokio::test]
+async fn unreachable_primary_fails_over_at_startup() {
+    let fixtures = fixture_flashblocks();
+    let backup = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+
+    // Nothing listens on the primary once its listener is dropped
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let primary = format!("ws://{}", listener.local_addr().unwrap());
+    drop(listener);
+
+    let endpoints = [FeedEndpoint::new(primary, 0), FeedEndpoint::new(&backup.url, 1)];
+    let (sender, mut receiver) = unbounded_channel();
+    let clients = RelayClients::new(&endpoints, CHAIN_ID, ReconnectPolicy::default(), sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+}
+
+#[tokio::test]
+async fn degraded_primary_fails_over_to_the_backup() {
+    let fixtures = fixture_flashblocks();
+    // The primary accepts connections but drops every one after a flashblock
+    let primary = MockFlashblocksServer::start(vec![vec![Step::Flashblock(fixtures[0].clone()), Step::Disconnect]]).await;
+    let backup = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_secs(60),
+        multiplier: 1.0,
+        jitter: 0.0,
+        min_connections: 1,
+        max_connections: 1,
+    };
+    let endpoints = [FeedEndpoint::new(&primary.url, 0), FeedEndpoint::new(&backup.url, 1)];
+    let (sender, mut receiver) = unbounded_channel();
+    let clients = RelayClients::new(&endpoints, CHAIN_ID, policy, sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+    assert!(primary.connections() >= 3);
+    assert_eq!(backup.connections(), 1);
+}
+
+#[tokio::test]
+async fn endpoints_with_the_same_priority_share_the_connections() {
+    let first = MockFlashblocksServer::start(vec![vec![]]).await;
+    let second = MockFlashblocksServer::start(vec![vec![]]).await;
+    let standby = MockFlashblocksServer::start(vec![vec![]]).await;
+
+    let policy = ReconnectPolicy {
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+        min_connections: 1,
+        max_connections: 2,
+        ..Default::default()
+    };
+    let endpoints = [
+        FeedEndpoint::new(&first.url, 0),
+        FeedEndpoint::new(&second.url, 0),
+        FeedEndpoint::new(&standby.url, 1),
+    ];
+    let (sender, _receiver) = unbounded_channel();
+    let clients = RelayClients::new(&endpoints, CHAIN_ID, policy, sender, ClientOptions::default()).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    tokio::time::sleep(Duration::from_millis(800)).await;
+    assert_eq!((first.connections(), second.connections(), standby.connections()), (2, 2, 0));
+}
+
+#[tokio::test]
+async fn feed_matching_the_chain_head_is_accepted() {
+    let fixtures = fresh_flashblocks();
+    let server = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+    let head = fixtures[0].metadata.block_number - 2;
+
+    let (sender, mut receiver) = unbounded_channel();
+    let (update_sender, _update_receiver) = unbounded_channel();
+    let options = ClientOptions { chain_check: Some(chain_check(CHAIN_ID, head)), ..Default::default() };
+    let client = RelayClient::new(Url::parse(&server.url).unwrap(), 0, sender, update_sender, options).await.unwrap();
+    client.spawn();
+
+    // The flashblock read to verify the chain is delivered too
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+}
+
+#[tokio::test]
+async fn feed_of_another_chain_is_rejected() {
+    let fixtures = fresh_flashblocks();
+    let server = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+    // The feed is far ahead of what the RPC reports for our chain
+    let head = fixtures[0].metadata.block_number / 4;
+
+    let (sender, _receiver) = unbounded_channel();
+    let (update_sender, _update_receiver) = unbounded_channel();
+    let options = ClientOptions { chain_check: Some(chain_check(CHAIN_ID, head)), ..Default::default() };
+    let result = RelayClient::new(Url::parse(&server.url).unwrap(), 0, sender, update_sender, options).await;
+
+    assert!(matches!(result, Err(RelayError::InvalidChainId)));
+}
+
+#[tokio::test]
+async fn stale_feed_is_rejected() {
+    // The fixtures carry a timestamp from long ago
+    let fixtures = fixture_flashblocks();
+    let server = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+
+    let (sender, _receiver) = unbounded_channel();
+    let (update_sender, _update_receiver) = unbounded_channel();
+    let options = ClientOptions {
+        chain_check: Some(chain_check(CHAIN_ID, fixtures[0].metadata.block_number)),
+        ..Default::default()
+    };
+    let result = RelayClient::new(Url::parse(&server.url).unwrap(), 0, sender, update_sender, options).await;
+
+    assert!(matches!(result, Err(RelayError::InvalidChainId)));
+}
+
+#[tokio::test]
+async fn rpc_on_another_chain_is_rejected() {
+    let server = MockFlashblocksServer::with_flashblocks(fresh_flashblocks()).await;
+
+    let (sender, _receiver) = unbounded_channel();
+    let options = ClientOptions { chain_check: Some(chain_check(10, 0)), ..Default::default() };
+    let result = RelayClients::new(&[FeedEndpoint::new(&server.url, 0)], CHAIN_ID, ReconnectPolicy::default(), sender, options).await;
+
+    assert!(matches!(result, Err(RelayError::InvalidChainId)));
+}
+
+#[tokio::test]
+async fn endpoint_of_another_chain_is_failed_over() {
+    let fixtures = fresh_flashblocks();
+    let mut other_chain = fixtures.clone();
+    for flashblock in other_chain.iter_mut() {
+        flashblock.metadata.block_number *= 4;
+    }
+    let wrong = MockFlashblocksServer::with_flashblocks(other_chain).await;
+    let right = MockFlashblocksServer::with_flashblocks(fixtures.clone()).await;
+
+    let endpoints = [FeedEndpoint::new(&wrong.url, 0), FeedEndpoint::new(&right.url, 1)];
+    let options = ClientOptions {
+        chain_check: Some(chain_check(CHAIN_ID, fixtures[0].metadata.block_number)),
+        ..Default::default()
+    };
+    let (sender, mut receiver) = unbounded_channel();
+    let clients = RelayClients::new(&endpoints, CHAIN_ID, ReconnectPolicy::default(), sender, options).await.unwrap();
+    tokio::spawn(RelayClients::start_reader(clients));
+
+    for expected in &fixtures {
+        assert_eq!(json(&recv(&mut receiver).await.unwrap()), json(expected));
+    }
+}
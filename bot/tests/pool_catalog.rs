@@ -0,0 +1,260 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use ethers::types::H160;
+use ms_bot::errors::{InvalidPoolType, PoolCatalogError};
+use ms_bot::pool_catalog::*;
+use ms_bot::types::PoolType;
+use ms_bot::uniswap_v4::{PoolKey, V4Pool};
+
+fn usdc() -> H160 {
+    H160::repeat_byte(0x83)
+}
+
+fn pool(byte: u8) -> H160 {
+    H160::repeat_byte(byte)
+}
+
+fn v4_key() -> PoolKey {
+    PoolKey { currency0: H160::zero(), currency1: usdc(), fee: 500, tick_spacing: 10, hooks: H160::zero() }
+}
+
+// A launcher with a V3 pool, an Aerodrome pool and the WETH/USDC V4 pool
+struct MockSource {
+    launcher: H160,
+    pools: Vec<(H160, u8)>,
+    tokens: HashMap<H160, (H160, H160)>,
+    no_code: Vec<H160>,
+    v4_pool: LauncherV4Pool,
+    // RPC failures before pool_count answers
+    failures: AtomicU32,
+    calls: AtomicU32,
+    decimals_calls: AtomicU32,
+}
+
+impl MockSource {
+    fn new() -> Self {
+        let manager = pool(0x44);
+        Self {
+            launcher: pool(0x01),
+            pools: vec![(pool(0xa0), 0), (pool(0xa2), 2), (H160::zero(), 3)],
+            tokens: HashMap::from([(pool(0xa0), (OP_STACK_WETH, usdc())), (pool(0xa2), (OP_STACK_WETH, usdc()))]),
+            no_code: vec![],
+            v4_pool: LauncherV4Pool { manager, id: v4_key().id(), fee: 500, tick_spacing: 10, hooks: H160::zero() },
+            failures: AtomicU32::new(0),
+            calls: AtomicU32::new(0),
+            decimals_calls: AtomicU32::new(0),
+        }
+    }
+}
+
+#[async_trait]
+impl PoolSource for MockSource {
+    fn launcher(&self) -> H160 {
+        self.launcher
+    }
+
+    async fn pool_count(&self) -> Result<usize, PoolCatalogError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        if self.failures.load(Ordering::SeqCst) > 0 {
+            self.failures.fetch_sub(1, Ordering::SeqCst);
+            return Err(PoolCatalogError::Rpc("connection reset".to_string()));
+        }
+        Ok(self.pools.len())
+    }
+
+    async fn pool_info(&self, index: usize) -> Result<(H160, u8), PoolCatalogError> {
+        Ok(self.pools[index])
+    }
+
+    async fn launcher_tokens(&self) -> Result<(H160, H160), PoolCatalogError> {
+        Ok((OP_STACK_WETH, usdc()))
+    }
+
+    async fn launcher_v4_pool(&self) -> Result<LauncherV4Pool, PoolCatalogError> {
+        Ok(self.v4_pool)
+    }
+
+    async fn has_code(&self, address: H160) -> Result<bool, PoolCatalogError> {
+        Ok(!self.no_code.contains(&address))
+    }
+
+    async fn pool_tokens(&self, pool: H160) -> Result<(H160, H160), PoolCatalogError> {
+        self.tokens.get(&pool).copied().ok_or_else(|| PoolCatalogError::Rpc("execution reverted".to_string()))
+    }
+
+    async fn decimals(&self, token: H160) -> Result<u8, PoolCatalogError> {
+        self.decimals_calls.fetch_add(1, Ordering::SeqCst);
+        Ok(if token == usdc() { 6 } else { 18 })
+    }
+}
+
+fn retry() -> RetryPolicy {
+    RetryPolicy { attempts: 3, initial_delay: Duration::from_millis(1) }
+}
+
+fn cache_path(name: &str) -> String {
+    let path = std::env::temp_dir().join(format!("ms_bot_{}_{}.json", name, std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    path.to_str().unwrap().to_string()
+}
+
+#[test]
+fn converts_launcher_pool_types() {
+    assert_eq!(PoolType::try_from(0).unwrap(), PoolType::UniswapV3);
+    assert_eq!(PoolType::try_from(3).unwrap(), PoolType::UniswapV4);
+    assert!(matches!(PoolType::try_from(4), Err(InvalidPoolType(4))));
+}
+
+#[test]
+fn finds_the_key_of_the_launcher_v4_pool() {
+    let v4_pool = MockSource::new().v4_pool;
+    // The pool trades native ETH for the WETH of the launcher
+    assert_eq!(v4_pool.key(usdc(), OP_STACK_WETH), Some(v4_key()));
+
+    let weth_key = PoolKey { currency0: OP_STACK_WETH, currency1: usdc(), ..v4_key() };
+    let weth_pool = LauncherV4Pool { id: weth_key.id(), ..v4_pool };
+    assert_eq!(weth_pool.key(OP_STACK_WETH, usdc()), Some(weth_key));
+
+    // Nothing the launcher is set up for hashes to the id
+    assert_eq!(LauncherV4Pool { tick_spacing: 60, ..v4_pool }.key(OP_STACK_WETH, usdc()), None);
+    assert_eq!(v4_pool.key(OP_STACK_WETH, pool(0x99)), None);
+}
+
+#[tokio::test]
+async fn loads_the_pools_of_the_launcher() {
+    let source = MockSource::new();
+    let catalog = PoolCatalog::fetch(8453, &source, retry()).await.unwrap();
+
+    assert_eq!((catalog.chain_id, catalog.launcher), (8453, source.launcher));
+    let types: Vec<_> = catalog.pools.iter().map(|pool| pool.pool_type).collect();
+    assert_eq!(types, vec![PoolType::UniswapV3, PoolType::Aerodrome, PoolType::UniswapV4]);
+
+    let v3 = &catalog.pools[0];
+    assert_eq!((v3.index, v3.address), (0, pool(0xa0)));
+    assert_eq!(v3.token0, TokenInfo { address: OP_STACK_WETH, decimals: 18 });
+    assert_eq!(v3.token1, TokenInfo { address: usdc(), decimals: 6 });
+    assert_eq!(v3.v4_pool, None);
+
+    // The V4 pool logs from the PoolManager and trades native ETH
+    let v4 = &catalog.pools[2];
+    assert_eq!(v4.address, source.v4_pool.manager);
+    assert_eq!(v4.token0, TokenInfo { address: H160::zero(), decimals: 18 });
+    assert_eq!(v4.v4_pool.unwrap().key, Some(v4_key()));
+
+    catalog.validate(&source, retry()).await.unwrap();
+}
+
+#[tokio::test]
+async fn retries_failed_rpc_calls() {
+    let source = MockSource::new();
+    source.failures.store(2, Ordering::SeqCst);
+    assert!(PoolCatalog::fetch(8453, &source, retry()).await.is_ok());
+    assert_eq!(source.calls.load(Ordering::SeqCst), 3);
+
+    source.failures.store(3, Ordering::SeqCst);
+    assert!(matches!(PoolCatalog::fetch(8453, &source, retry()).await, Err(PoolCatalogError::Rpc(_))));
+}
+
+#[tokio::test]
+async fn rejects_pools_it_cant_trust() {
+    // A failed call no longer registers the zero address as a pool
+    let mut source = MockSource::new();
+    source.pools[1] = (pool(0xa2), 9);
+    assert!(matches!(
+        PoolCatalog::fetch(8453, &source, retry()).await,
+        Err(PoolCatalogError::PoolType { index: 1, source: InvalidPoolType(9) })
+    ));
+
+    let mut source = MockSource::new();
+    source.no_code.push(pool(0xa2));
+    let catalog = PoolCatalog::fetch(8453, &source, retry()).await.unwrap();
+    assert!(matches!(catalog.validate(&source, retry()).await, Err(PoolCatalogError::NoCode { index: 1, .. })));
+
+    let mut source = MockSource::new();
+    source.tokens.insert(pool(0xa2), (OP_STACK_WETH, pool(0x99)));
+    let catalog = PoolCatalog::fetch(8453, &source, retry()).await.unwrap();
+    assert!(matches!(catalog.validate(&source, retry()).await, Err(PoolCatalogError::TokenMismatch { index: 1, .. })));
+
+    let mut source = MockSource::new();
+    source.v4_pool.hooks = pool(0x77);
+    assert!(matches!(PoolCatalog::fetch(8453, &source, retry()).await, Err(PoolCatalogError::V4Pool { index: 2, .. })));
+
+    // A cached V4 pool is checked against its own key
+    let source = MockSource::new();
+    let catalog = PoolCatalog::fetch(8453, &source, retry()).await.unwrap();
+    let mut tampered = catalog.clone();
+    let v4_pool = tampered.pools[2].v4_pool.as_mut().unwrap();
+    v4_pool.key = Some(PoolKey { currency1: pool(0x99), ..v4_key() });
+    assert!(matches!(tampered.validate(&source, retry()).await, Err(PoolCatalogError::V4Pool { index: 2, .. })));
+
+    let mut keyless = catalog.clone();
+    keyless.pools[2].v4_pool.as_mut().unwrap().key = None;
+    assert!(matches!(keyless.validate(&source, retry()).await, Err(PoolCatalogError::V4Pool { index: 2, .. })));
+
+    let mut other_tokens = catalog;
+    let key = PoolKey { currency1: pool(0x99), ..v4_key() };
+    other_tokens.pools[2].v4_pool = Some(V4Pool { key: Some(key), ..V4Pool::new(source.v4_pool.manager, key.id()) });
+    let source = MockSource { v4_pool: LauncherV4Pool { id: key.id(), ..source.v4_pool }, ..MockSource::new() };
+    assert!(matches!(other_tokens.validate(&source, retry()).await, Err(PoolCatalogError::TokenMismatch { index: 2, .. })));
+}
+
+#[tokio::test]
+async fn caches_the_catalog_to_disk() {
+    let path = cache_path("catalog");
+    let source = MockSource::new();
+    let catalog = PoolCatalog::load(Some(&path), 8453, &source, retry()).await.unwrap();
+    assert_eq!(PoolCatalog::read(&path).unwrap(), catalog);
+
+    // The cache is used without loading the tokens again, but still checked
+    let mut cached = MockSource::new();
+    assert_eq!(PoolCatalog::load(Some(&path), 8453, &cached, retry()).await.unwrap(), catalog);
+    assert_eq!(cached.decimals_calls.load(Ordering::SeqCst), 0);
+
+    cached.tokens.clear();
+    assert!(PoolCatalog::load(Some(&path), 8453, &cached, retry()).await.is_err());
+
+    // Another chain or launcher loads again
+    let other = MockSource { launcher: pool(0x02), ..MockSource::new() };
+    assert_eq!(PoolCatalog::load(Some(&path), 8453, &other, retry()).await.unwrap().launcher, pool(0x02));
+    assert_eq!(PoolCatalog::load(Some(&path), 10, &source, retry()).await.unwrap().chain_id, 10);
+
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn reloads_a_cache_the_launcher_moved_on_from() {
+    let path = cache_path("stale");
+    let catalog = PoolCatalog::load(Some(&path), 8453, &MockSource::new(), retry()).await.unwrap();
+
+    let mut added = MockSource::new();
+    added.pools.push((pool(0xa3), 0));
+    added.tokens.insert(pool(0xa3), (OP_STACK_WETH, usdc()));
+    assert!(matches!(catalog.validate(&added, retry()).await, Err(PoolCatalogError::Stale(_))));
+    let reloaded = PoolCatalog::load(Some(&path), 8453, &added, retry()).await.unwrap();
+    assert_eq!(reloaded.pools.len(), 4);
+    assert_eq!(PoolCatalog::read(&path).unwrap(), reloaded);
+
+    let mut moved = MockSource::new();
+    moved.pools[0] = (pool(0xa3), 0);
+    moved.tokens.insert(pool(0xa3), (OP_STACK_WETH, usdc()));
+    assert!(matches!(catalog.validate(&moved, retry()).await, Err(PoolCatalogError::Stale(_))));
+    assert_eq!(PoolCatalog::load(Some(&path), 8453, &moved, retry()).await.unwrap().pools[0].address, pool(0xa3));
+
+    let mut retyped = MockSource::new();
+    retyped.pools[0].1 = 1;
+    assert!(matches!(catalog.validate(&retyped, retry()).await, Err(PoolCatalogError::Stale(_))));
+    assert_eq!(PoolCatalog::load(Some(&path), 8453, &retyped, retry()).await.unwrap().pools[0].pool_type, PoolType::PancakeV3);
+
+    // The launcher is set up for another V4 pool
+    let key = PoolKey { fee: 3000, tick_spacing: 60, ..v4_key() };
+    let mut rekeyed = MockSource::new();
+    rekeyed.v4_pool = LauncherV4Pool { id: key.id(), fee: 3000, tick_spacing: 60, ..rekeyed.v4_pool };
+    assert!(matches!(catalog.validate(&rekeyed, retry()).await, Err(PoolCatalogError::Stale(_))));
+    let reloaded = PoolCatalog::load(Some(&path), 8453, &rekeyed, retry()).await.unwrap();
+    assert_eq!(reloaded.pools[2].v4_pool.unwrap().key, Some(key));
+
+    std::fs::remove_file(&path).unwrap();
+}
@@ -0,0 +1,163 @@
+use ethers::types::U256;
+use ms_bot::errors::MathError;
+use ms_bot::price_math::*;
+use ms_bot::tokens::TokenMetadata;
+use proptest::prelude::*;
+
+fn token(decimals: u8) -> TokenMetadata {
+    TokenMetadata { decimals, ..TokenMetadata::default() }
+}
+
+fn to_f64(value: U256) -> f64 {
+    value.0.iter().rev().fold(0.0, |acc, limb| acc * 2f64.powi(64) + *limb as f64)
+}
+
+fn u256() -> impl Strategy<Value = U256> {
+    any::<[u64; 4]>().prop_map(U256)
+}
+
+fn sqrt_price() -> impl Strategy<Value = U256> {
+    (MIN_TICK..MAX_TICK, any::<u64>()).prop_map(|(tick, offset)| {
+        let low = get_sqrt_ratio_at_tick(tick).unwrap();
+        let high = get_sqrt_ratio_at_tick(tick + 1).unwrap();
+        low + U256::from(offset) % (high - low)
+    })
+}
+
+#[test]
+fn matches_tick_math_reference_vectors() {
+    let vectors = [
+        (MIN_TICK, "4295128739"),
+        (MIN_TICK + 1, "4295343490"),
+        (0, "79228162514264337593543950336"),
+        (MAX_TICK - 1, "1461373636630004318706518188784493106690254656249"),
+        (MAX_TICK, "1461446703485210103287273052203988822378723970342"),
+    ];
+    for (tick, sqrt_price) in vectors {
+        assert_eq!(get_sqrt_ratio_at_tick(tick).unwrap(), U256::from_dec_str(sqrt_price).unwrap(), "tick {}", tick);
+    }
+
+    assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK).unwrap(), MIN_SQRT_RATIO);
+    assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK).unwrap(), MAX_SQRT_RATIO);
+    assert_eq!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO).unwrap(), MIN_TICK);
+    assert_eq!(get_tick_at_sqrt_ratio(MAX_SQRT_RATIO - 1).unwrap(), MAX_TICK - 1);
+
+    assert_eq!(get_sqrt_ratio_at_tick(MAX_TICK + 1), Err(MathError::TickOutOfRange(MAX_TICK + 1)));
+    assert_eq!(get_sqrt_ratio_at_tick(MIN_TICK - 1), Err(MathError::TickOutOfRange(MIN_TICK - 1)));
+    assert!(get_tick_at_sqrt_ratio(MIN_SQRT_RATIO - 1).is_err());
+    assert!(get_tick_at_sqrt_ratio(MAX_SQRT_RATIO).is_err());
+}
+
+#[test]
+fn every_tick_bit_is_sqrt_1_0001() {
+    for bit in 0..20 {
+        for tick in [1 << bit, -(1 << bit)] {
+            let expected = (tick as f64 * 0.0001f64.ln_1p() / 2.0).exp() * 2f64.powi(96);
+            let actual = to_f64(get_sqrt_ratio_at_tick(tick).unwrap());
+            assert!((actual / expected - 1.0).abs() < 1e-12, "tick {}: {} != {}", tick, actual, expected);
+        }
+    }
+}
+
+#[test]
+fn mul_div_handles_512_bit_products() {
+    assert_eq!(mul_div(U256::MAX, U256::MAX, U256::MAX), Ok(U256::MAX));
+    assert_eq!(mul_div(U256::MAX, U256::from(2), U256::from(4)), Ok(U256::MAX / 2));
+    assert_eq!(mul_div(U256::MAX, U256::from(2), U256::one()), Err(MathError::Overflow));
+    assert_eq!(mul_div(U256::one(), U256::one(), U256::zero()), Err(MathError::DivisionByZero));
+
+    assert_eq!(mul_div_rounding_up(U256::from(7), U256::from(3), U256::from(2)), Ok(U256::from(11)));
+    assert_eq!(mul_div_rounding_up(U256::MAX, U256::MAX, U256::MAX), Ok(U256::MAX));
+    assert_eq!(mul_div_rounding_up(U256::MAX, U256::from(3), U256::from(3) - 1), Err(MathError::Overflow));
+}
+
+#[test]
+fn prices_with_more_decimals_in_token1() {
+    // 1 token0 with 6 decimals for 4 token1 with 18
+    let sqrt_price = U256::from(2_000_000) << 96;
+    assert_eq!(sqrt_price_x96_to_price(sqrt_price, true, &token(6), &token(18)), U256::from(4_0000_0000u64));
+    assert_eq!(sqrt_price_x96_to_price(sqrt_price, false, &token(6), &token(18)), U256::from(2500_0000u64));
+    assert_eq!(price_to_sqrt_price_x96(U256::from(4_0000_0000u64), true, &token(6), &token(18)), sqrt_price);
+    assert_eq!(price_to_sqrt_price_x96(U256::from(2500_0000u64), false, &token(6), &token(18)), sqrt_price);
+
+    // Prices past 256 bits saturate, and past 1e-8 round to zero
+    assert_eq!(sqrt_price_x96_to_price(MAX_SQRT_RATIO, true, &token(255), &token(0)), U256::MAX);
+    assert_eq!(sqrt_price_x96_to_price(MIN_SQRT_RATIO, true, &token(0), &token(255)), U256::zero());
+    assert_eq!(price_to_sqrt_price_x96(U256::zero(), false, &token(18), &token(6)), U256::MAX);
+}
+
+proptest! {
+    #[test]
+    fn mul_div_matches_256_bit_math(a: u128, b: u128, denominator in 1..u128::MAX) {
+        let product = U256::from(a) * U256::from(b);
+        let denominator = U256::from(denominator);
+        prop_assert_eq!(mul_div(U256::from(a), U256::from(b), denominator), Ok(product / denominator));
+
+        let rounded_up = (product + denominator - 1) / denominator;
+        prop_assert_eq!(mul_div_rounding_up(U256::from(a), U256::from(b), denominator), Ok(rounded_up));
+    }
+
+    #[test]
+    fn mul_div_cancels_out(a in u256(), b in u256()) {
+        prop_assume!(!b.is_zero());
+        prop_assert_eq!(mul_div(a, b, b), Ok(a));
+        prop_assert_eq!(mul_div_rounding_up(a, b, b), Ok(a));
+    }
+
+    #[test]
+    fn ticks_round_trip(tick in MIN_TICK..MAX_TICK) {
+        let sqrt_price = get_sqrt_ratio_at_tick(tick).unwrap();
+        prop_assert!(sqrt_price < get_sqrt_ratio_at_tick(tick + 1).unwrap());
+        prop_assert_eq!(get_tick_at_sqrt_ratio(sqrt_price), Ok(tick));
+    }
+
+    #[test]
+    fn sqrt_prices_fall_between_their_tick_and_the_next(sqrt_price in sqrt_price()) {
+        let tick = get_tick_at_sqrt_ratio(sqrt_price).unwrap();
+        prop_assert!(get_sqrt_ratio_at_tick(tick).unwrap() <= sqrt_price);
+        prop_assert!(get_sqrt_ratio_at_tick(tick + 1).unwrap() > sqrt_price);
+    }
+
+    #[test]
+    fn inverted_sqrt_prices_multiply_to_one(sqrt_price in sqrt_price()) {
+        let q192 = U256::one() << 192;
+        let inverse = invert_sqrt_price_x96(sqrt_price).unwrap();
+        prop_assert!(inverse * sqrt_price <= q192);
+        prop_assert!((inverse + 1) * sqrt_price > q192);
+    }
+
+    #[test]
+    fn prices_round_trip_through_sqrt_prices(
+        sqrt_price in sqrt_price(),
+        zero_for_one: bool,
+        decimals0 in 0u8..=30,
+        decimals1 in 0u8..=30,
+    ) {
+        let (token0, token1) = (token(decimals0), token(decimals1));
+        let price = sqrt_price_x96_to_price(sqrt_price, zero_for_one, &token0, &token1);
+        prop_assume!(!price.is_zero() && price != U256::MAX);
+
+        // The sqrt price found is rounded down, the price lies between its
+        // price and the price of the next one
+        let found = price_to_sqrt_price_x96(price, zero_for_one, &token0, &token1);
+        let at_found = sqrt_price_x96_to_price(found, zero_for_one, &token0, &token1);
+        let after_found = sqrt_price_x96_to_price(found + 1, zero_for_one, &token0, &token1);
+        if zero_for_one {
+            prop_assert!(found <= sqrt_price);
+            prop_assert!(at_found <= price && price <= after_found);
+        } else {
+            prop_assert!(found >= sqrt_price);
+            prop_assert!(at_found >= price && price >= after_found);
+        }
+    }
+
+    #[test]
+    fn prices_match_floating_point(sqrt_price in sqrt_price(), decimals0 in 0u8..=24, decimals1 in 0u8..=24) {
+        let raw_price = (to_f64(sqrt_price) / 2f64.powi(96)).powi(2);
+        let expected = raw_price * 10f64.powi(decimals0 as i32 - decimals1 as i32 + 8);
+        prop_assume!(expected > 1e12 && expected < 1e70);
+
+        let actual = to_f64(sqrt_price_x96_to_price(sqrt_price, true, &token(decimals0), &token(decimals1)));
+        prop_assert!((actual / expected - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,46 @@
+use std::time::Duration;
+
+use ms_bot::collectors::reconnect_policy::ReconnectPolicy;
+
+fn policy() -> ReconnectPolicy {
+    ReconnectPolicy {
+        initial_backoff: Duration::from_millis(100),
+        max_backoff: Duration::from_secs(2),
+        multiplier: 2.0,
+        jitter: 0.5,
+        min_connections: 2,
+        max_connections: 4,
+    }
+}
+
+#[test]
+fn backoff_doubles_until_the_cap() {
+    let policy = policy();
+    let delays: Vec<_> = (0..7).map(|failures| policy.backoff_with_jitter(failures, 0.0)).collect();
+
+    assert_eq!(delays, [100, 200, 400, 800, 1600, 2000, 2000].map(Duration::from_millis));
+    assert_eq!(policy.backoff_with_jitter(u32::MAX, 0.0), Duration::from_secs(2));
+}
+
+#[test]
+fn jitter_scales_the_delay_but_never_past_the_cap() {
+    let policy = policy();
+
+    assert_eq!(policy.backoff_with_jitter(1, -0.5), Duration::from_millis(100));
+    assert_eq!(policy.backoff_with_jitter(1, 0.5), Duration::from_millis(300));
+    assert_eq!(policy.backoff_with_jitter(4, 0.5), Duration::from_secs(2));
+
+    for failures in 0..10 {
+        let delay = policy.backoff(failures);
+        let plain = policy.backoff_with_jitter(failures, 0.0);
+        assert!(delay >= plain.mul_f64(0.5) && delay <= policy.max_backoff);
+    }
+}
+
+#[test]
+fn pool_grows_slowly_once_the_minimum_is_met() {
+    let policy = ReconnectPolicy { jitter: 0.0, ..policy() };
+
+    assert_eq!(policy.growth_delay(1), Duration::from_millis(100));
+    assert_eq!(policy.growth_delay(2), Duration::from_secs(2));
+}
@@ -0,0 +1,207 @@
+mod support;
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use alloy_primitives::TxKind;
+use ethers::abi::AbiEncode;
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Bytes, Eip1559TransactionRequest, H160, H256, I256, U256};
+use ethers::utils::WEI_IN_ETHER;
+use ms_bot::types::*;
+use revm_trace::{create_shared_backend, evm::builder::get_provider, types::StateOverride, SimulationTx};
+use support::mock_rpc::{MockAccount, MockRpcServer, BLOCK_NUMBER};
+
+// Stores its first calldata word in slot 0
+const STORE: [u8; 7] = [0x60, 0x00, 0x35, 0x60, 0x00, 0x55, 0x00];
+// Returns slot 0 and stores its first calldata word in it
+const SWAP: [u8; 17] = [0x60, 0x00, 0x54, 0x60, 0x00, 0x52, 0x60, 0x00, 0x35, 0x60, 0x00, 0x55, 0x60, 0x20, 0x60, 0x00, 0xf3];
+
+fn with_selector(selector: [u8; 4], arguments: Vec<u8>) -> Vec<u8> {
+    [selector.to_vec(), arguments].concat()
+}
+
+#[test]
+fn decodes_revert_reasons() {
+    let message = with_selector(ERROR_SELECTOR, "STF".to_string().encode());
+    assert_eq!(RevertReason::decode(&message), RevertReason::Message("STF".to_string()));
+
+    let overflow = with_selector(PANIC_SELECTOR, U256::from(0x11).encode());
+    assert_eq!(RevertReason::decode(&overflow), RevertReason::Panic(U256::from(0x11)));
+    assert_eq!(RevertReason::decode(&overflow).to_string(), "panic 0x11");
+
+    // A custom error keeps its arguments for the caller to decode
+    let custom = with_selector([0xde, 0xad, 0xbe, 0xef], U256::one().encode());
+    let reason = RevertReason::decode(&custom);
+    assert_eq!(reason, RevertReason::Custom { selector: [0xde, 0xad, 0xbe, 0xef], data: U256::one().encode().into() });
+    assert!(reason.to_string().starts_with("custom error 0xdeadbeef"));
+
+    assert_eq!(RevertReason::decode(&[]), RevertReason::Empty);
+    assert_eq!(RevertReason::decode(&[1, 2]), RevertReason::Unknown(Bytes::from(vec![1, 2])));
+
+    // A known selector with arguments that don't decode
+    let broken = with_selector(ERROR_SELECTOR, vec![0xff; 3]);
+    assert_eq!(RevertReason::decode(&broken), RevertReason::Unknown(broken.clone().into()));
+}
+
+#[test]
+fn decodes_only_successful_outputs() {
+    // A losing trade decodes to a negative profit
+    let output = (U256::from(7), I256::from(-5), U256::from(21_000)).encode();
+    let success = SimulationOutcome::Success { output: output.into(), gas_used: 90_000, logs: vec![], state_diff: vec![] };
+    assert_eq!(success.decode::<(U256, I256, U256)>(), Some((U256::from(7), I256::from(-5), U256::from(21_000))));
+    assert_eq!(success.decode::<(U256, I256, U256, U256)>(), None);
+    assert_eq!(success.to_string(), "succeeded, 90000 gas");
+
+    let revert = SimulationOutcome::Revert { reason: RevertReason::Message("STF".to_string()), gas_used: 30_000 };
+    assert_eq!(revert.decode::<(U256, I256, U256)>(), None);
+    assert_eq!(revert.to_string(), "reverted: STF, 30000 gas");
+
+    let backend = SimulationOutcome::BackendError("connection refused".to_string());
+    assert!(backend.is_backend_error() && !revert.is_backend_error());
+    assert_eq!(backend.to_string(), "backend error: connection refused");
+}
+
+fn call(caller: H160, to: H160, value: u64, word: u64) -> PendingCall {
+    SimulationTx {
+        caller: caller.0.into(),
+        transact_to: TxKind::Call(to.0.into()),
+        value: alloy_primitives::U256::from(value),
+        data: U256::from(word).encode().into(),
+    }.into()
+}
+
+fn deposit(from: H160, to: H160, mint: u64, value: u64, word: u64) -> PendingCall {
+    PendingCall { mint: U256::from(mint), ..call(from, to, value, word) }
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_storage_and_balances() {
+    let (sender, contract) = (H160::repeat_byte(0xa1), H160::repeat_byte(0xc0));
+    let server = MockRpcServer::start(HashMap::from([
+        (sender, MockAccount::with_balance(WEI_IN_ETHER)),
+        (contract, MockAccount::with_code(STORE.to_vec())),
+    ])).await;
+    let shared_backend = create_shared_backend(&server.url, Some(BLOCK_NUMBER)).await.unwrap();
+    let provider = Arc::new(get_provider(&server.url).await.unwrap());
+
+    let state = replay_transactions(shared_backend.clone(), provider.clone(), &PendingState::default(), vec![
+        call(sender, contract, 1_000, 7),
+        call(sender, contract, 0, 9),
+    ]).await.unwrap();
+
+    // The second write of the slot wins
+    assert_eq!(state.storage, vec![(contract, H256::zero(), H256::from_low_u64_be(9))]);
+    assert_eq!(state.balances[&sender], WEI_IN_ETHER - 1_000);
+    assert_eq!(state.balances[&contract], U256::from(1_000));
+
+    let state_override = state.to_state_override();
+    assert_eq!(state_override.balances[&alloy_primitives::Address::from(sender.0)], alloy_primitives::U256::from_limbs((WEI_IN_ETHER - 1_000).0));
+    assert_eq!(state_override.storages[&alloy_primitives::Address::from(contract.0)], vec![(alloy_primitives::U256::ZERO, alloy_primitives::U256::from(9))]);
+
+    // An empty account can't pay the value, and the state after it would be wrong
+    let broke = H160::repeat_byte(0xb0);
+    let error = replay_transactions(shared_backend, provider, &PendingState::default(), vec![
+        call(sender, contract, 0, 1),
+        call(broke, contract, 1, 2),
+    ]).await.unwrap_err();
+    assert!(error.to_string().starts_with("Pending transaction 1 can't be replayed"), "{}", error);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_on_top_of_an_earlier_replay() {
+    let (sender, contract, other) = (H160::repeat_byte(0xa1), H160::repeat_byte(0xc0), H160::repeat_byte(0xc1));
+    let server = MockRpcServer::start(HashMap::from([
+        (sender, MockAccount::with_balance(WEI_IN_ETHER)),
+        (contract, MockAccount::with_code(STORE.to_vec())),
+        (other, MockAccount::with_code(STORE.to_vec())),
+    ])).await;
+    let shared_backend = create_shared_backend(&server.url, Some(BLOCK_NUMBER)).await.unwrap();
+    let provider = Arc::new(get_provider(&server.url).await.unwrap());
+
+    let first = vec![call(sender, contract, 1_000, 7)];
+    let second = vec![call(sender, other, 2_000, 8), call(sender, contract, 0, 9)];
+    let all = replay_transactions(shared_backend.clone(), provider.clone(), &PendingState::default(), [first.clone(), second.clone()].concat()).await.unwrap();
+
+    // Replaying the second batch alone over the first gives the same state
+    let state = replay_transactions(shared_backend.clone(), provider.clone(), &PendingState::default(), first).await.unwrap();
+    assert!(!state.is_empty());
+    let state = replay_transactions(shared_backend, provider, &state, second).await.unwrap();
+    assert_eq!(state, all);
+    assert_eq!(state.balances[&sender], WEI_IN_ETHER - 3_000);
+    assert_eq!(state.storage, vec![
+        (contract, H256::zero(), H256::from_low_u64_be(9)),
+        (other, H256::zero(), H256::from_low_u64_be(8)),
+    ]);
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn replays_deposits_after_minting_their_eth() {
+    let (sender, depositor, contract) = (H160::repeat_byte(0xa1), H160::repeat_byte(0xd0), H160::repeat_byte(0xc0));
+    let server = MockRpcServer::start(HashMap::from([
+        (sender, MockAccount::with_balance(WEI_IN_ETHER)),
+        (depositor, MockAccount::with_balance(U256::from(50))),
+        (contract, MockAccount::with_code(STORE.to_vec())),
+    ])).await;
+    let shared_backend = create_shared_backend(&server.url, Some(BLOCK_NUMBER)).await.unwrap();
+    let provider = Arc::new(get_provider(&server.url).await.unwrap());
+
+    // The depositor can only pay the value with the minted ETH, the second
+    // deposit mints on top of what the first one left
+    let state = replay_transactions(shared_backend.clone(), provider.clone(), &PendingState::default(), vec![
+        call(sender, contract, 1_000, 7),
+        deposit(depositor, contract, 5_000, 700, 8),
+        call(sender, contract, 0, 9),
+        deposit(depositor, contract, 1_000, 5_000, 10),
+    ]).await.unwrap();
+
+    assert_eq!(state.storage, vec![(contract, H256::zero(), H256::from_low_u64_be(10))]);
+    assert_eq!(state.balances[&depositor], U256::from(50 + 5_000 - 700 + 1_000 - 5_000));
+    assert_eq!(state.balances[&contract], U256::from(1_000 + 700 + 5_000));
+    assert_eq!(state.balances[&sender], WEI_IN_ETHER - 1_000);
+
+    // Without the mint the deposit can't pay its value
+    let error = replay_transactions(shared_backend, provider, &PendingState::default(), vec![
+        deposit(depositor, contract, 0, 700, 8),
+    ]).await.unwrap_err();
+    assert!(error.to_string().starts_with("Pending transaction 0 can't be replayed"), "{}", error);
+}
+
+fn swap(contract: H160, word: u64) -> TypedTransaction {
+    Eip1559TransactionRequest::new().to(contract).data(U256::from(word).encode()).into()
+}
+
+fn returned(outcomes: &[SimulationOutcome]) -> Vec<Option<U256>> {
+    outcomes.iter().map(|outcome| outcome.decode::<U256>()).collect()
+}
+
+#[tokio::test(flavor = "multi_thread")]
+async fn reuses_a_session_from_the_block_state() {
+    let contract = H160::repeat_byte(0xc0);
+    let server = MockRpcServer::start(HashMap::from([(contract, MockAccount::with_code(SWAP.to_vec()))])).await;
+    let shared_backend = create_shared_backend(&server.url, Some(BLOCK_NUMBER)).await.unwrap();
+    let provider = Arc::new(get_provider(&server.url).await.unwrap());
+
+    // Slot 0 starts at 5 in every run
+    let state_override = StateOverride {
+        storages: HashMap::from([(contract.0.into(), vec![(alloy_primitives::U256::ZERO, alloy_primitives::U256::from(5))])]),
+        balances: HashMap::new(),
+    };
+    let mut session = SimulationSession::new(shared_backend.clone(), provider.clone(), H160::repeat_byte(0xa1), state_override.clone()).await.unwrap();
+
+    let outcomes = session.run(vec![swap(contract, 7), swap(contract, 8)], true);
+    assert_eq!(returned(&outcomes), vec![Some(U256::from(5)), Some(U256::from(7))]);
+    match &outcomes[0] {
+        SimulationOutcome::Success { state_diff, .. } => assert_eq!(state_diff, &vec![(contract, H256::zero(), H256::from_low_u64_be(7))]),
+        other => panic!("expected a success, got {}", other),
+    }
+
+    // Nothing of the first run is left
+    let outcomes = session.run(vec![swap(contract, 9)], true);
+    assert_eq!(returned(&outcomes), vec![Some(U256::from(5))]);
+    assert_eq!(returned(&session.call_batch(vec![swap(contract, 1), swap(contract, 2)])), vec![Some(U256::from(5)); 2]);
+
+    // The EVM can't be built without a node
+    let offline = Arc::new(get_provider("http://127.0.0.1:1").await.unwrap());
+    assert!(simulate_tx_with_revm(shared_backend, offline, H160::zero(), vec![swap(contract, 1)], false, state_override).await.is_err());
+}
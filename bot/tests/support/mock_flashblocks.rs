@@ -0,0 +1,148 @@
+use std::io::Write;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::SinkExt;
+use ms_bot::types::FlashblocksPayloadV1;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_tungstenite::accept_async;
+use tungstenite::protocol::CloseFrame;
+use tungstenite::protocol::frame::coding::CloseCode;
+use tungstenite::Message;
+
+const FIXTURES: &str = include_str!("../fixtures/flashblocks.json");
+
+/// The flashblocks in `tests/fixtures/flashblocks.json`, in feed order.
+pub fn fixture_flashblocks() -> Vec<FlashblocksPayloadV1> {
+    serde_json::from_str(FIXTURES).expect("fixtures are valid flashblocks")
+}
+
+/// Compresses `bytes` the way the flashblocks feed does.
+pub fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut writer = brotli::CompressorWriter::new(Vec::new(), 4096, 5, 22);
+    writer.write_all(bytes).unwrap();
+    writer.into_inner()
+}
+
+/// Encodes a flashblock into a feed frame.
+pub fn encode_frame(flashblock: &FlashblocksPayloadV1) -> Vec<u8> {
+    compress(&serde_json::to_vec(flashblock).unwrap())
+}
+
+/// One thing the mock server does on a connection.
+#[derive(Clone, Debug)]
+#[allow(clippy::large_enum_variant)]
+pub enum Step {
+    /// Send a compressed flashblock
+    Flashblock(FlashblocksPayloadV1),
+    /// Send compressed text that is not a flashblock
+    MalformedJson(String),
+    /// Send a binary frame as is, without compressing it
+    Garbage(Vec<u8>),
+    /// Send a text frame, the way the Arbitrum feed does
+    Text(String),
+    /// Send a ping, the pong is counted by [MockFlashblocksServer::pongs]
+    Ping(Vec<u8>),
+    /// Wait before the next step
+    Sleep(Duration),
+    /// Send a close frame and end the connection
+    Close,
+    /// Drop the TCP connection without a close frame
+    Disconnect,
+}
+
+/// A local websocket server that plays a scripted feed to every client.
+///
+/// Connection `n` gets `scripts[n]`; connections past the last script get the
+/// last one again. A connection whose script runs out stays open, silently,
+/// until the client goes away.
+pub struct MockFlashblocksServer {
+    pub url: String,
+    connections: Arc<AtomicUsize>,
+    pongs: Arc<AtomicUsize>,
+    handle: JoinHandle<()>,
+}
+
+impl MockFlashblocksServer {
+    pub async fn start(scripts: Vec<Vec<Step>>) -> Self {
+        assert!(!scripts.is_empty(), "the mock server needs at least one script");
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let connections = Arc::new(AtomicUsize::new(0));
+        let pongs = Arc::new(AtomicUsize::new(0));
+
+        let accepted = connections.clone();
+        let received_pongs = pongs.clone();
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                let n = accepted.fetch_add(1, Ordering::SeqCst);
+                let script = scripts[n.min(scripts.len() - 1)].clone();
+                let received_pongs = received_pongs.clone();
+                tokio::spawn(async move {
+                    if let Ok(socket) = accept_async(stream).await {
+                        play(socket, script, received_pongs).await;
+                    }
+                });
+            }
+        });
+
+        Self { url, connections, pongs, handle }
+    }
+
+    /// Starts a server that sends `flashblocks` on every connection.
+    pub async fn with_flashblocks(flashblocks: Vec<FlashblocksPayloadV1>) -> Self {
+        Self::start(vec![flashblocks.into_iter().map(Step::Flashblock).collect()]).await
+    }
+
+    /// Number of websocket connections accepted so far.
+    pub fn connections(&self) -> usize {
+        self.connections.load(Ordering::SeqCst)
+    }
+
+    /// Number of pongs received for [Step::Ping] so far.
+    pub fn pongs(&self) -> usize {
+        self.pongs.load(Ordering::SeqCst)
+    }
+}
+
+impl Drop for MockFlashblocksServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn play(mut socket: tokio_tungstenite::WebSocketStream<tokio::net::TcpStream>, script: Vec<Step>, pongs: Arc<AtomicUsize>) {
+    for step in script {
+        let sent = match step {
+            Step::Flashblock(flashblock) => socket.send(Message::Binary(encode_frame(&flashblock))).await,
+            Step::MalformedJson(text) => socket.send(Message::Binary(compress(text.as_bytes()))).await,
+            Step::Garbage(bytes) => socket.send(Message::Binary(bytes)).await,
+            Step::Text(text) => socket.send(Message::Text(text)).await,
+            Step::Ping(data) => socket.send(Message::Ping(data)).await,
+            Step::Sleep(duration) => {
+                tokio::time::sleep(duration).await;
+                Ok(())
+            }
+            Step::Close => {
+                let frame = CloseFrame { code: CloseCode::Normal, reason: "mock server closing".into() };
+                let _ = socket.close(Some(frame)).await;
+                return;
+            }
+            Step::Disconnect => return,
+        };
+
+        if sent.is_err() {
+            return;
+        }
+    }
+
+    // Keep the connection open until the client hangs up
+    while let Some(Ok(msg)) = futures_util::StreamExt::next(&mut socket).await {
+        if let Message::Pong(_) = msg {
+            pongs.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use ethers::types::{Bytes, H160, H256, U256};
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::task::JoinHandle;
+
+pub const CHAIN_ID: u64 = 8453;
+pub const BLOCK_NUMBER: u64 = 100;
+pub const TIMESTAMP: u64 = 1_700_000_000;
+
+/// An account of the mock chain.
+#[derive(Clone, Debug, Default)]
+pub struct MockAccount {
+    pub balance: U256,
+    pub nonce: u64,
+    pub code: Bytes,
+    pub storage: HashMap<H256, H256>,
+}
+
+impl MockAccount {
+    pub fn with_balance(balance: U256) -> Self {
+        Self { balance, ..Default::default() }
+    }
+
+    pub fn with_code(code: Vec<u8>) -> Self {
+        Self { code: code.into(), ..Default::default() }
+    }
+}
+
+/// A local JSON-RPC server over HTTP that serves a fixed chain state at
+/// [BLOCK_NUMBER], enough for the EVM to fork from it. Accounts it doesn't
+/// know are empty.
+pub struct MockRpcServer {
+    pub url: String,
+    handle: JoinHandle<()>,
+}
+
+impl MockRpcServer {
+    pub async fn start(accounts: HashMap<H160, MockAccount>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let accounts = Arc::new(accounts);
+
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve(stream, accounts.clone()));
+            }
+        });
+
+        Self { url, handle }
+    }
+}
+
+impl Drop for MockRpcServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+// Answers the requests of one keep-alive connection
+async fn serve(stream: TcpStream, accounts: Arc<HashMap<H160, MockAccount>>) {
+    let mut stream = BufReader::new(stream);
+    loop {
+        let mut content_length = 0;
+        loop {
+            let mut line = String::new();
+            match stream.read_line(&mut line).await {
+                Ok(0) | Err(_) => return,
+                Ok(_) => {}
+            }
+            if line == "\r\n" {
+                break;
+            }
+            if let Some((name, value)) = line.split_once(':') {
+                if name.eq_ignore_ascii_case("content-length") {
+                    content_length = value.trim().parse().unwrap_or(0);
+                }
+            }
+        }
+
+        let mut body = vec![0; content_length];
+        if stream.read_exact(&mut body).await.is_err() {
+            return;
+        }
+
+        let response = match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Array(requests)) => Value::Array(requests.iter().map(|request| respond(request, &accounts)).collect()),
+            Ok(request) => respond(&request, &accounts),
+            Err(_) => json!({ "jsonrpc": "2.0", "id": null, "error": { "code": -32700, "message": "parse error" } }),
+        };
+
+        let response = response.to_string();
+        let head = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\n\r\n", response.len());
+        if stream.get_mut().write_all(head.as_bytes()).await.is_err()
+            || stream.get_mut().write_all(response.as_bytes()).await.is_err() {
+            return;
+        }
+    }
+}
+
+fn respond(request: &Value, accounts: &HashMap<H160, MockAccount>) -> Value {
+    let params = request["params"].as_array().cloned().unwrap_or_default();
+    let account = || {
+        params.first()
+            .and_then(|address| serde_json::from_value::<H160>(address.clone()).ok())
+            .and_then(|address| accounts.get(&address).cloned())
+            .unwrap_or_default()
+    };
+
+    let result = match request["method"].as_str().unwrap_or_default() {
+        "eth_chainId" => json!(format!("{:#x}", CHAIN_ID)),
+        "eth_blockNumber" => json!(format!("{:#x}", BLOCK_NUMBER)),
+        "eth_getBlockByNumber" | "eth_getBlockByHash" => block(),
+        "eth_getBalance" => json!(account().balance),
+        "eth_getTransactionCount" => json!(format!("{:#x}", account().nonce)),
+        "eth_getCode" => json!(account().code),
+        "eth_getStorageAt" => {
+            let slot = params.get(1).and_then(|slot| serde_json::from_value::<U256>(slot.clone()).ok()).unwrap_or_default();
+            let mut key = [0u8; 32];
+            slot.to_big_endian(&mut key);
+            json!(account().storage.get(&H256::from(key)).copied().unwrap_or_default())
+        }
+        method => {
+            return json!({ "jsonrpc": "2.0", "id": request["id"], "error": { "code": -32601, "message": format!("{} not supported", method) } });
+        }
+    };
+
+    json!({ "jsonrpc": "2.0", "id": request["id"], "result": result })
+}
+
+fn block() -> Value {
+    json!({
+        "hash": H256::repeat_byte(0xbb),
+        "parentHash": H256::repeat_byte(0xaa),
+        "sha3Uncles": H256::zero(),
+        "miner": H160::zero(),
+        "stateRoot": H256::zero(),
+        "transactionsRoot": H256::zero(),
+        "receiptsRoot": H256::zero(),
+        "logsBloom": format!("0x{}", "00".repeat(256)),
+        "difficulty": "0x0",
+        "number": format!("{:#x}", BLOCK_NUMBER),
+        "gasLimit": "0x1c9c380",
+        "gasUsed": "0x0",
+        "timestamp": format!("{:#x}", TIMESTAMP),
+        "extraData": "0x",
+        "mixHash": H256::zero(),
+        "nonce": "0x0000000000000000",
+        "baseFeePerGas": "0x0",
+        "size": "0x0",
+        "uncles": [],
+        "transactions": []
+    })
+}
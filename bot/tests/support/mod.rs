@@ -0,0 +1,5 @@
+//! Shared helpers for the integration tests.
+#![allow(dead_code)]
+
+pub mod mock_flashblocks;
+pub mod mock_rpc;
@@ -0,0 +1,145 @@
+use ethers::abi::{encode, Token};
+use ethers::types::{Bytes, H160, H256, I256, U256};
+use ms_bot::errors::SwapEventError;
+use ms_bot::swap_events::*;
+use ms_bot::types::{LogItem, PoolType};
+
+fn int(value: i64) -> Token {
+    Token::Int(I256::from(value).into_raw())
+}
+
+fn uint(value: u64) -> Token {
+    Token::Uint(U256::from(value))
+}
+
+fn log(signature: &str, topics: &[H256], data: &[Token]) -> LogItem {
+    LogItem {
+        address: H160::repeat_byte(0xaa),
+        topics: [&[event_topic(signature)][..], topics].concat(),
+        data: Bytes::from(encode(data)),
+    }
+}
+
+fn decode(pool_type: PoolType, log: &LogItem) -> Result<SwapEvent, SwapEventError> {
+    SwapEventDecoder::default().decode(pool_type, log).expect("a registered event")
+}
+
+#[test]
+fn topics_match_the_deployed_events() {
+    let topics = [
+        (UNISWAP_V3_SWAP, "0xc42079f94a6350d7e6235f29174924f928cc2ac818eb64fed8004e115fbcca67"),
+        (PANCAKE_V3_SWAP, "0x19b47279256b2a23a1665c810c8d55a1758940ee09377d4f8d26497a3577dc83"),
+        (UNISWAP_V4_SWAP, "0x40e9cecb9f5f1f1c5b9c97dec2917b7ee92e57ba5563708daca94dd84ad7112f"),
+        (UNISWAP_V2_SWAP, "0xd78ad95fa46c994b6551d0da85fc275fe613ce37657fb8d5e3d130840159d822"),
+        (UNISWAP_V2_SYNC, "0x1c411e9a96e071241c2f21f7726b17ae89e3cab4c78be50e062b03a9fffbbad1"),
+        (CURVE_TOKEN_EXCHANGE, "0x8b3e96f2b889fa771c53c981b40daf005f63f637f1869f707052d15a3dd97140"),
+    ];
+
+    for (signature, topic) in topics {
+        assert_eq!(event_topic(signature), topic.parse::<H256>().unwrap(), "{}", signature);
+    }
+}
+
+#[test]
+fn decodes_concentrated_liquidity_swaps() {
+    let data = [int(-1_000), int(2_500), uint(1 << 40), uint(77), int(-200_000)];
+    let topics = [H256::zero(), H256::zero()];
+
+    for pool_type in [PoolType::UniswapV3, PoolType::Aerodrome, PoolType::Algebra] {
+        let swap = decode(pool_type, &log(UNISWAP_V3_SWAP, &topics, &data)).unwrap();
+        assert_eq!(swap.address, H160::repeat_byte(0xaa));
+        assert_eq!((swap.amount0, swap.amount1), (I256::from(-1_000), I256::from(2_500)));
+        assert_eq!(swap.sqrt_price_x96, Some(U256::from(1u64 << 40)));
+        assert_eq!((swap.liquidity, swap.tick, swap.fee), (Some(77), Some(-200_000), None));
+    }
+
+    let pancake = [&data[..], &[uint(3), uint(4)]].concat();
+    let swap = decode(PoolType::PancakeV3, &log(PANCAKE_V3_SWAP, &topics, &pancake)).unwrap();
+    assert_eq!(swap.tick, Some(-200_000));
+}
+
+#[test]
+fn decodes_v4_swaps_from_the_pool() {
+    let pool_id = H256::repeat_byte(0x91);
+    let data = [int(-1_000), int(2_500), uint(1 << 40), uint(77), int(12), uint(3_000)];
+
+    let swap = decode(PoolType::UniswapV4, &log(UNISWAP_V4_SWAP, &[pool_id, H256::zero()], &data)).unwrap();
+    assert_eq!(swap.pool_id, Some(pool_id));
+    assert_eq!((swap.amount0, swap.amount1), (I256::from(1_000), I256::from(-2_500)));
+    assert_eq!((swap.tick, swap.fee), (Some(12), Some(3_000)));
+
+    // The pool id is required
+    assert!(matches!(
+        decode(PoolType::UniswapV4, &log(UNISWAP_V4_SWAP, &[], &data)),
+        Err(SwapEventError::MissingTopic(1))
+    ));
+}
+
+#[test]
+fn decodes_constant_product_events() {
+    let data = [uint(100), uint(0), uint(0), uint(180)];
+    let swap = decode(PoolType::UniswapV2, &log(UNISWAP_V2_SWAP, &[H256::zero(), H256::zero()], &data)).unwrap();
+    assert_eq!((swap.amount0, swap.amount1), (I256::from(100), I256::from(-180)));
+    assert_eq!(swap.sqrt_price_x96, None);
+
+    let swap = decode(PoolType::AerodromeClassic, &log(SOLIDLY_SWAP, &[H256::zero(), H256::zero()], &data)).unwrap();
+    assert_eq!((swap.amount0, swap.amount1), (I256::from(100), I256::from(-180)));
+
+    let sync = decode(PoolType::UniswapV2, &log(UNISWAP_V2_SYNC, &[], &[uint(5), uint(6)])).unwrap();
+    assert_eq!(sync.reserves, Some((U256::from(5), U256::from(6))));
+    assert_eq!(sync.amount0, I256::zero());
+
+    let sync = decode(PoolType::AerodromeClassic, &log(SOLIDLY_SYNC, &[], &[uint(5), uint(6)])).unwrap();
+    assert_eq!(sync.reserves, Some((U256::from(5), U256::from(6))));
+}
+
+#[test]
+fn decodes_curve_exchanges() {
+    let buyer = [H256::zero()];
+
+    let swap = decode(PoolType::Curve, &log(CURVE_TOKEN_EXCHANGE, &buyer, &[int(1), uint(50), int(0), uint(49)])).unwrap();
+    assert_eq!((swap.amount0, swap.amount1), (I256::from(-49), I256::from(50)));
+
+    let swap = decode(PoolType::Curve, &log(CURVE_CRYPTO_TOKEN_EXCHANGE, &buyer, &[uint(0), uint(50), uint(1), uint(49)])).unwrap();
+    assert_eq!((swap.amount0, swap.amount1), (I256::from(50), I256::from(-49)));
+
+    for (i, j) in [(0, 2), (-1, 0)] {
+        assert!(matches!(
+            decode(PoolType::Curve, &log(CURVE_TOKEN_EXCHANGE, &buyer, &[int(i), uint(50), int(j), uint(49)])),
+            Err(SwapEventError::InvalidValue(_))
+        ));
+    }
+}
+
+#[test]
+fn rejects_malformed_events() {
+    let truncated = LogItem { data: Bytes::from(vec![0u8; 40]), ..log(UNISWAP_V3_SWAP, &[], &[]) };
+    assert!(matches!(decode(PoolType::UniswapV3, &truncated), Err(SwapEventError::Abi(_))));
+
+    // uint160 with bits above 160
+    let data = [int(1), int(1), Token::Uint(U256::MAX), uint(1), int(1)];
+    assert!(decode(PoolType::UniswapV3, &log(UNISWAP_V3_SWAP, &[], &data)).is_err());
+}
+
+#[test]
+fn looks_up_decoders_by_pool_type_and_event() {
+    let decoder = SwapEventDecoder::default();
+    let v3_swap = log(UNISWAP_V3_SWAP, &[], &[int(1), int(1), uint(1), uint(1), int(1)]);
+
+    // Only the events of the pool's own type are decoded
+    assert!(decoder.decode(PoolType::PancakeV3, &v3_swap).is_none());
+    assert!(decoder.decode(PoolType::UniswapV3, &LogItem::default()).is_none());
+
+    let mut topics: Vec<_> = decoder.topics(PoolType::UniswapV2).collect();
+    topics.sort();
+    let mut expected = vec![event_topic(UNISWAP_V2_SWAP), event_topic(UNISWAP_V2_SYNC)];
+    expected.sort();
+    assert_eq!(topics, expected);
+
+    // New events are registered without touching the built-in ones
+    let custom = SwapEventDecoder::default().with_decoder(PoolType::PancakeV3, event_topic(UNISWAP_V3_SWAP), |log: &LogItem| {
+        Ok(SwapEvent { address: log.address, fee: Some(100), ..SwapEvent::default() })
+    });
+    assert_eq!(custom.decode(PoolType::PancakeV3, &v3_swap).unwrap().unwrap().fee, Some(100));
+    assert!(custom.decode(PoolType::UniswapV3, &v3_swap).unwrap().is_ok());
+}
@@ -0,0 +1,158 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use async_trait::async_trait;
+use ethers::types::{H160, H256, U256};
+use ms_bot::errors::TokenError;
+use ms_bot::tokens::*;
+use ms_bot::price_math::{price_to_sqrt_price_x96, sqrt_price_x96_to_price};
+use ms_bot::types::calculate_balance_slot;
+
+fn weth() -> TokenMetadata {
+    TokenMetadata::new("0x4200000000000000000000000000000000000006".parse().unwrap(), "WETH", 18)
+}
+
+fn usdc() -> TokenMetadata {
+    TokenMetadata::new("0x833589fcd6edb6e08f4c7c32d4f71b54bda02913".parse().unwrap(), "USDC", 6)
+}
+
+fn op() -> TokenMetadata {
+    TokenMetadata::new("0x4200000000000000000000000000000000000042".parse().unwrap(), "OP", 18)
+}
+
+// sqrt(price) * 2^96 for a price of token1 per token0 in raw units
+fn sqrt_price_x96(raw_price: f64) -> U256 {
+    U256::from((raw_price.sqrt() * 2f64.powi(48)) as u128) << 48
+}
+
+fn assert_close(actual: U256, expected: u64) {
+    let diff = actual.abs_diff(U256::from(expected));
+    assert!(diff * 100_000 <= U256::from(expected), "{} is not {}", actual, expected);
+}
+
+// A token whose balances are kept in mapping slot 5
+struct MockToken {
+    holder: H160,
+    balance: U256,
+    calls: AtomicU32,
+}
+
+#[async_trait]
+impl TokenSource for MockToken {
+    async fn symbol(&self, _token: H160) -> Result<String, TokenError> {
+        self.calls.fetch_add(1, Ordering::SeqCst);
+        Ok("AERO".to_string())
+    }
+
+    async fn decimals(&self, _token: H160) -> Result<u8, TokenError> {
+        Ok(18)
+    }
+
+    async fn balance_of(&self, _token: H160, holder: H160) -> Result<U256, TokenError> {
+        Ok(if holder == self.holder { self.balance } else { U256::zero() })
+    }
+
+    async fn storage_at(&self, _token: H160, slot: H256) -> Result<H256, TokenError> {
+        let storage = HashMap::from([(calculate_balance_slot(self.holder, 5), H256::from_low_u64_be(self.balance.as_u64()))]);
+        Ok(storage.get(&slot).copied().unwrap_or_default())
+    }
+}
+
+#[test]
+fn converts_prices_with_the_token_decimals() {
+    // WETH/USDC on Base, WETH is token0 at 3000 USDC
+    let price = sqrt_price_x96(3000.0 * 1e6 / 1e18);
+    assert_close(sqrt_price_x96_to_price(price, true, &weth(), &usdc()), 3000_0000_0000);
+
+    // WETH/USDC on OP, USDC is token0
+    let price = sqrt_price_x96(1e18 / (3000.0 * 1e6));
+    assert_close(sqrt_price_x96_to_price(price, false, &usdc(), &weth()), 3000_0000_0000);
+    assert_close(sqrt_price_x96_to_price(price, true, &usdc(), &weth()), 33_333);
+
+    // WETH/OP, both with 18 decimals
+    let price = sqrt_price_x96(4000.0);
+    assert_close(sqrt_price_x96_to_price(price, true, &weth(), &op()), 4000_0000_0000);
+
+    assert_eq!(sqrt_price_x96_to_price(U256::zero(), false, &usdc(), &weth()), U256::zero());
+}
+
+#[test]
+fn inverts_prices_back_to_sqrt_prices() {
+    for (zero_for_one, token0, token1) in [(true, weth(), usdc()), (false, usdc(), weth()), (true, weth(), op())] {
+        let price = U256::from(2512_3456_7890u64);
+        let sqrt_price = price_to_sqrt_price_x96(price, zero_for_one, &token0, &token1);
+        assert_close(sqrt_price_x96_to_price(sqrt_price, zero_for_one, &token0, &token1), price.as_u64());
+    }
+}
+
+#[test]
+fn pairs_order_tokens_like_pools() {
+    let base = TokenPair::new(weth(), usdc());
+    assert!(base.base_is_token0);
+    assert_eq!((base.base(), base.quote()), (&weth(), &usdc()));
+
+    // On OP, USDC sorts before WETH
+    let usdc = TokenMetadata::new("0x0b2c639c533813f4aa9d7837caf62653d097ff85".parse().unwrap(), "USDC", 6);
+    let op_pair = TokenPair::new(weth(), usdc.clone());
+    assert_eq!((&op_pair.token0, op_pair.base_is_token0), (&usdc, false));
+
+    let price = U256::from(3000_0000_0000u64);
+    assert_close(op_pair.price(op_pair.sqrt_price_x96(price)), price.as_u64());
+    assert_close(base.price(base.sqrt_price_x96(price)), price.as_u64());
+}
+
+#[test]
+fn funds_simulators_with_both_tokens_in_their_decimals() {
+    let word = |exp: usize| {
+        let mut word = H256::zero();
+        U256::exp10(exp).to_big_endian(word.as_bytes_mut());
+        word
+    };
+
+    // A billion of each token
+    let simulator = H160::repeat_byte(0x51);
+    let pair = TokenPair::new(weth().with_balance_slot(3), usdc().with_balance_slot(9));
+    assert_eq!(pair.simulator_balances(simulator), vec![
+        (weth().address, calculate_balance_slot(simulator, 3), word(27)),
+        (usdc().address, calculate_balance_slot(simulator, 9), word(15)),
+    ]);
+
+    // A token without a known balance slot can't be funded
+    let pair = TokenPair::new(weth().with_balance_slot(3), op());
+    assert_eq!(pair.simulator_balances(simulator), vec![(weth().address, calculate_balance_slot(simulator, 3), word(27))]);
+}
+
+#[test]
+fn knows_the_tokens_of_each_chain() {
+    let base = TokenRegistry::for_chain(8453);
+    let token = base.get(usdc().address).unwrap();
+    assert_eq!((token.symbol.as_str(), token.decimals, token.balance_slot), ("USDC", 6, Some(9)));
+    assert_eq!(base.get(H160::zero()), Some(&TokenMetadata::native()));
+
+    let optimism = TokenRegistry::for_chain(10);
+    assert_eq!(optimism.get(op().address).unwrap().balance_slot, Some(0));
+    assert!(optimism.get(usdc().address).is_none());
+
+    assert!(TokenRegistry::for_chain(42).get(weth().address).is_none());
+}
+
+#[tokio::test]
+async fn resolves_unknown_tokens_once() {
+    let holder = H160::repeat_byte(0x11);
+    let source = MockToken { holder, balance: U256::from(123_456), calls: AtomicU32::new(0) };
+    let aero = H160::repeat_byte(0x94);
+
+    let mut registry = TokenRegistry::for_chain(8453);
+    let token = registry.resolve(&source, aero, holder).await.unwrap();
+    assert_eq!(token, TokenMetadata::new(aero, "AERO", 18).with_balance_slot(5));
+    assert_eq!(token.balance_slot_of(holder), Some(calculate_balance_slot(holder, 5)));
+
+    // Cached, and well known tokens are never read
+    assert_eq!(registry.resolve(&source, aero, holder).await.unwrap(), token);
+    assert_eq!(registry.resolve(&source, weth().address, holder).await.unwrap().balance_slot, Some(3));
+    assert_eq!(source.calls.load(Ordering::SeqCst), 1);
+
+    // Without a balance to match the slot stays unknown
+    let token = TokenRegistry::new().resolve(&source, aero, H160::repeat_byte(0x22)).await.unwrap();
+    assert_eq!(token.balance_slot, None);
+}
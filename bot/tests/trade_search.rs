@@ -0,0 +1,160 @@
+use ethers::types::{H160, I256, U256};
+use ethers::utils::WEI_IN_ETHER;
+use ms_bot::config::SearchConfig;
+use ms_bot::trade_search::*;
+use proptest::prelude::*;
+
+const THRESHOLD_ALGORITHMS: [SearchAlgorithm; 3] =
+    [SearchAlgorithm::Bisection, SearchAlgorithm::AdaptiveStep, SearchAlgorithm::Newton];
+
+fn eth(amount: f64) -> U256 {
+    U256::from((amount * 1e18) as u128)
+}
+
+fn params(algorithm: SearchAlgorithm) -> SearchParams {
+    SearchParams::default().with_algorithm(algorithm)
+}
+
+// Profit grows with the delta and passes zero at `threshold`. Below
+// `reverts_below` the simulator returns nothing.
+fn rising(threshold: U256, reverts_below: U256) -> impl Fn(U256) -> Result<Option<I256>, ()> {
+    move |delta| Ok((delta >= reverts_below).then(|| I256::from_raw(delta) - I256::from_raw(threshold)))
+}
+
+#[test]
+fn finds_the_smallest_profitable_delta() {
+    let threshold = eth(7.3);
+    for algorithm in THRESHOLD_ALGORITHMS {
+        let report = find_start_delta(&params(algorithm), I256::zero(), eth(100.0), rising(threshold, U256::zero())).unwrap();
+        assert!(report.delta > threshold, "{:?}", algorithm);
+        assert!(report.delta - threshold <= WEI_IN_ETHER / 1000, "{:?}: {}", algorithm, report.delta);
+        assert!(report.evaluations <= 64);
+    }
+
+    // A straight line is where Newton shines
+    let newton = find_start_delta(&params(SearchAlgorithm::Newton), I256::zero(), eth(100.0), rising(threshold, U256::zero())).unwrap();
+    let bisection = find_start_delta(&params(SearchAlgorithm::Bisection), I256::zero(), eth(100.0), rising(threshold, U256::zero())).unwrap();
+    assert!(newton.evaluations < bisection.evaluations / 2, "{} vs {}", newton.evaluations, bisection.evaluations);
+
+    // Early profits are cheap to find stepping up from zero
+    let early = rising(eth(0.01), U256::zero());
+    let adaptive = find_start_delta(&params(SearchAlgorithm::AdaptiveStep), I256::zero(), eth(100.0), &early).unwrap();
+    let bisection = find_start_delta(&params(SearchAlgorithm::Bisection), I256::zero(), eth(100.0), &early).unwrap();
+    assert!(adaptive.evaluations < bisection.evaluations);
+}
+
+#[test]
+fn finds_the_most_profitable_delta() {
+    // Profit peaks at 42 ETH
+    let peak = eth(42.0);
+    let profit = |delta: U256| Ok::<_, ()>(Some(I256::from(1_000_000) - I256::from_raw(delta.abs_diff(peak) / 1_000_000_000)));
+    let report = find_start_delta(&params(SearchAlgorithm::GoldenSection), I256::zero(), eth(100.0), profit).unwrap();
+    assert!(report.delta.abs_diff(peak) <= WEI_IN_ETHER / 1000, "{}", report.delta);
+    assert!(report.evaluations <= 40);
+
+    // Not profitable enough anywhere
+    let report = find_start_delta(&params(SearchAlgorithm::GoldenSection), I256::from(2_000_000), eth(100.0), profit).unwrap();
+    assert_eq!(report.delta, U256::zero());
+}
+
+#[test]
+fn returns_zero_without_profitable_deltas() {
+    let never = |_| Ok::<_, ()>(None);
+    for algorithm in THRESHOLD_ALGORITHMS {
+        assert_eq!(find_start_delta(&params(algorithm), I256::zero(), eth(100.0), never).unwrap().delta, U256::zero());
+        let losing = rising(eth(200.0), U256::zero());
+        assert_eq!(find_start_delta(&params(algorithm), I256::zero(), eth(100.0), losing).unwrap().delta, U256::zero());
+    }
+}
+
+#[test]
+fn stops_at_errors_and_the_evaluation_budget() {
+    let mut calls = 0;
+    let failing = |_| {
+        calls += 1;
+        if calls == 3 { Err("rpc down") } else { Ok(None) }
+    };
+    assert_eq!(find_start_delta(&params(SearchAlgorithm::Bisection), I256::zero(), eth(100.0), failing), Err("rpc down"));
+
+    let params = SearchParams { max_evaluations: 5, ..params(SearchAlgorithm::Bisection) };
+    let report = find_start_delta(&params, I256::zero(), eth(100.0), rising(eth(7.3), eth(7.0))).unwrap();
+    assert_eq!(report.evaluations, 5);
+    assert!(report.delta > eth(7.3));
+}
+
+#[test]
+fn builds_the_ladder_from_the_config() {
+    let params = SearchParams::default().with_regression(50, 7);
+    assert_eq!(params.regression_step(eth(10.0)), eth(0.2));
+    // Capped at 1.5 ETH
+    assert_eq!(params.regression_step(eth(1000.0)), eth(1.5));
+
+    let ladder = PriceLadder::new(&params, eth(10.0), eth(1.0));
+    assert_eq!((ladder.rungs.len(), ladder.step), (7, eth(0.2)));
+    assert_eq!((ladder.rungs[0].delta, ladder.rungs[6].delta), (eth(1.0), eth(1.0) + eth(0.2) * 6));
+
+    // The defaults of the config are those of the search
+    assert_eq!(SearchParams::from(&SearchConfig::default()), SearchParams::default());
+    let config: SearchConfig = toml::from_str("algorithm = \"golden_section\"\ntolerance = 100").unwrap();
+    let params = SearchParams::from(&config);
+    assert_eq!((params.algorithm, params.tolerance, params.gas_limit), (SearchAlgorithm::GoldenSection, eth(0.01), U256::from(10_000_000)));
+}
+
+#[test]
+fn ladder_starts_at_the_found_delta_and_steps_by_the_search_range() {
+    let params = params(SearchAlgorithm::Bisection).with_regression(50, 7);
+    let max_delta = eth(10.0);
+    let report = find_start_delta(&params, I256::zero(), max_delta, rising(eth(3.3), U256::zero())).unwrap();
+    assert!(report.delta >= eth(3.3) && report.delta < max_delta);
+
+    let ladder = PriceLadder::new(&params, max_delta, report.delta);
+    assert_eq!(ladder.start_delta, report.delta);
+    assert_eq!(ladder.step, params.regression_step(max_delta));
+    assert_eq!(ladder.rungs.first().unwrap().delta, report.delta);
+    assert_eq!(ladder.rungs.last().unwrap().delta, report.delta + ladder.step * 6);
+    assert!(ladder.rungs.windows(2).all(|w| w[1].delta - w[0].delta == eth(0.2)));
+}
+
+#[test]
+fn publishes_each_rung_with_its_own_delta() {
+    let mut ladder = PriceLadder::new(&SearchParams::default().with_regression(10, 4), eth(10.0), eth(2.0));
+    for (i, rung) in ladder.rungs.iter_mut().enumerate() {
+        rung.sqrt_price_x96 = U256::from(1000 - i);
+        rung.swap_amount = eth(1.0) * (i + 1);
+        rung.gas_used = U256::from(100_000 + i);
+    }
+    // The first rung was never traded, the second loses
+    ladder.rungs[1].profit = Some(I256::from(-1));
+    ladder.rungs[2].profit = Some(I256::from(5));
+    ladder.rungs[3].profit = Some(I256::from(7));
+
+    let launcher = H160::repeat_byte(0x01);
+    let trades = ladder.trades(launcher, 3, true, I256::zero(), U256::zero());
+    assert_eq!(trades.len(), 2);
+    for (trade, rung) in trades.iter().zip(&ladder.rungs[2..]) {
+        assert_eq!((trade.start_delta, trade.delta), (eth(2.0), rung.delta));
+        assert_eq!((trade.sqrt_price_x96, trade.swap_amount, trade.gas_used), (rung.sqrt_price_x96, rung.swap_amount, rung.gas_used));
+        assert_eq!(I256::from_raw(trade.profit), rung.profit.unwrap());
+        assert_eq!((trade.launcher_addr, trade.pool_index, trade.sell_base_token), (launcher, 3, true));
+    }
+
+    // Rungs have to clear both minimums
+    assert_eq!(ladder.trades(launcher, 3, true, I256::from(5), U256::zero()).len(), 1);
+    assert_eq!(ladder.trades(launcher, 3, true, I256::zero(), eth(3.5)).len(), 1);
+}
+
+proptest! {
+    #[test]
+    fn threshold_searches_agree(threshold in 1u64..90_000, reverts_below in 0u64..90_000) {
+        // In 1/1000 ETH, reverting below some delta that may be above the threshold
+        let threshold = WEI_IN_ETHER * threshold / 1000;
+        let reverts_below = WEI_IN_ETHER * reverts_below / 1000;
+        let first_profitable = threshold.max(reverts_below);
+
+        for algorithm in THRESHOLD_ALGORITHMS {
+            let report = find_start_delta(&params(algorithm), I256::zero(), eth(100.0), rising(threshold, reverts_below)).unwrap();
+            prop_assert!(report.delta >= first_profitable, "{:?}: {}", algorithm, report.delta);
+            prop_assert!(report.delta - first_profitable <= WEI_IN_ETHER / 1000, "{:?}: {}", algorithm, report.delta);
+        }
+    }
+}
@@ -0,0 +1,139 @@
+use ethers::abi::{encode, Token};
+use ethers::types::{Bytes, H160, H256, I256, U256};
+use ethers::utils::keccak256;
+use ms_bot::errors::SwapEventError;
+use ms_bot::swap_events::{event_topic, SwapEventDecoder, UNISWAP_V4_SWAP};
+use ms_bot::types::{LogItem, PoolType};
+use ms_bot::uniswap_v4::*;
+
+fn manager() -> H160 {
+    "0x498581ff718922c3f8e6a244956af099b2652b2b".parse().unwrap()
+}
+
+fn usdc() -> H160 {
+    "0x833589fcd6edb6e08f4c7c32d4f71b54bda02913".parse().unwrap()
+}
+
+fn key(tick_spacing: i32, hooks: H160) -> PoolKey {
+    PoolKey { currency0: H160::zero(), currency1: usdc(), fee: 500, tick_spacing, hooks }
+}
+
+fn word(bytes: &[u8]) -> [u8; 32] {
+    let mut word = [0u8; 32];
+    word[32 - bytes.len()..].copy_from_slice(bytes);
+    word
+}
+
+fn initialize_log(id: H256, key: &PoolKey) -> LogItem {
+    LogItem {
+        address: manager(),
+        topics: vec![event_topic(INITIALIZE), id, H256::from(key.currency0), H256::from(key.currency1)],
+        data: Bytes::from(encode(&[
+            Token::Uint(key.fee.into()),
+            Token::Int(I256::from(key.tick_spacing).into_raw()),
+            Token::Address(key.hooks),
+            Token::Uint(U256::from(1u64) << 96),
+            Token::Int(I256::from(-3).into_raw()),
+        ])),
+    }
+}
+
+#[test]
+fn hashes_pool_keys_like_the_pool_manager() {
+    for tick_spacing in [10, -10] {
+        let key = key(tick_spacing, H160::repeat_byte(0x44));
+
+        // abi.encode lays the five fields out as words, int24 sign extended
+        let fill = if tick_spacing < 0 { 0xff } else { 0 };
+        let mut spacing = [fill; 32];
+        spacing[28..].copy_from_slice(&tick_spacing.to_be_bytes());
+        let encoded = [
+            word(key.currency0.as_bytes()),
+            word(key.currency1.as_bytes()),
+            word(&500u32.to_be_bytes()),
+            spacing,
+            word(key.hooks.as_bytes()),
+        ].concat();
+
+        assert_eq!(key.id(), H256::from(keccak256(encoded)));
+    }
+
+    assert_ne!(key(10, H160::zero()).id(), key(60, H160::zero()).id());
+}
+
+#[test]
+fn reads_hook_permissions_from_the_address() {
+    let plain = key(10, H160::zero());
+    assert_eq!(plain.hook_flags(), 0);
+    assert!(!plain.hooks_swaps());
+    assert!(!plain.is_dynamic_fee());
+
+    let mut hooks = [0x11u8; 20];
+    hooks[18] = 0x00;
+    hooks[19] = 0xc8; // before and after swap, before swap returns delta
+    let hooked = PoolKey { fee: DYNAMIC_FEE_FLAG, ..key(10, H160::from(hooks)) };
+    assert_eq!(hooked.hook_flags(), BEFORE_SWAP_FLAG | AFTER_SWAP_FLAG | BEFORE_SWAP_RETURNS_DELTA_FLAG);
+    assert!(hooked.hooks_swaps());
+    assert!(hooked.is_dynamic_fee());
+
+    // Only the low 14 bits are permissions
+    hooks[18] = 0xc0;
+    hooks[19] = 0x04;
+    assert_eq!(key(10, H160::from(hooks)).hook_flags(), AFTER_SWAP_RETURNS_DELTA_FLAG);
+}
+
+#[test]
+fn orders_the_currencies_of_new_keys() {
+    let hooks = H160::repeat_byte(0x44);
+    assert_eq!(PoolKey::new(usdc(), H160::zero(), 500, 10, hooks), key(10, hooks));
+    assert_eq!(PoolKey::new(H160::zero(), usdc(), 500, 10, hooks).id(), key(10, hooks).id());
+}
+
+#[test]
+fn decodes_pool_keys_from_initialize_logs() {
+    let expected = key(-60, H160::repeat_byte(0x44));
+    assert_eq!(PoolKey::from_initialize(&initialize_log(expected.id(), &expected)).unwrap(), expected);
+
+    // The key has to hash to the id of the log
+    assert!(matches!(
+        PoolKey::from_initialize(&initialize_log(H256::repeat_byte(1), &expected)),
+        Err(SwapEventError::InvalidValue(_))
+    ));
+
+    let mut log = initialize_log(expected.id(), &expected);
+    log.topics.truncate(2);
+    assert!(matches!(PoolKey::from_initialize(&log), Err(SwapEventError::MissingTopic(2))));
+
+    log.topics[0] = H256::zero();
+    assert!(PoolKey::from_initialize(&log).is_err());
+}
+
+#[test]
+fn tells_pools_of_the_manager_apart() {
+    let key = key(10, H160::zero());
+    let pool = V4Pool::new(manager(), key.id());
+
+    let swap = |address: H160, id: H256| LogItem {
+        address,
+        topics: vec![event_topic(UNISWAP_V4_SWAP), id, H256::zero()],
+        data: Bytes::from(encode(&[
+            Token::Int(I256::from(-10).into_raw()),
+            Token::Int(I256::from(20).into_raw()),
+            Token::Uint(U256::from(1u64) << 96),
+            Token::Uint(U256::from(1_000)),
+            Token::Int(I256::from(0).into_raw()),
+            Token::Uint(U256::from(500)),
+        ])),
+    };
+
+    assert!(pool.emitted(&swap(manager(), key.id())));
+    assert!(!pool.emitted(&swap(manager(), H256::repeat_byte(2))));
+    assert!(!pool.emitted(&swap(H160::repeat_byte(1), key.id())));
+
+    let decoded = SwapEventDecoder::default()
+        .decode(PoolType::UniswapV4, &swap(manager(), key.id()))
+        .unwrap()
+        .unwrap();
+    assert_eq!(decoded.pool_id, Some(pool.id));
+    assert_eq!(decoded.sqrt_price_x96, Some(U256::from(1u64) << 96));
+}